@@ -78,7 +78,7 @@ fn bench_verify_shares_and_commitments(b: &mut Bencher) {
             commitment_batch.push(commitment);
         }
     }
-    let params = Parameters { indices, index, h };
+    let params = Parameters::new(indices, index, h).unwrap();
 
     let iter = vshare_batches
         .iter()
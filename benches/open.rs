@@ -22,12 +22,13 @@ fn bench_handle_share_batch(b: &mut Bencher) {
         testutil::random_sharing_batch(n, k, batch_size, &indices, &h);
 
     let inst_params = InstanceParams::new(commitment_batch);
-    let params = Parameters { indices, index, h };
-    let state = State::new(&inst_params);
+    let params = Parameters::new(indices, index, h).unwrap();
 
     b.iter(|| {
-        let _ = state
-            .clone()
-            .handle_vshare_batch(&inst_params, &params, vshare_batches[0].clone());
+        let _ = State::new(&inst_params).handle_vshare_batch(
+            &inst_params,
+            &params,
+            vshare_batches[0].clone(),
+        );
     });
 }
@@ -1,7 +1,4 @@
-#![feature(test)]
-
-extern crate test;
-
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use mpc::rkpg::{self, State};
 use mpc::testutil;
 use secp256k1::group::Gej;
@@ -9,19 +6,12 @@ use secp256k1::scalar::{self, Scalar};
 use shamir::rs::Precompute;
 use shamir::sss::Share;
 use shamir::vss::SharingCommitment;
-use test::Bencher;
 
 fn setup(
     indices: &[Scalar],
     k: usize,
     b: usize,
-) -> (
-    State,
-    Vec<Vec<Share>>,
-    Precompute,
-    Vec<SharingCommitment>,
-    Gej,
-) {
+) -> (Vec<Vec<Share>>, Precompute, Vec<SharingCommitment>, Gej) {
     let n = indices.len();
     let h = Gej::new_random_using_thread_rng();
     let precompute = Precompute::new(indices.iter());
@@ -40,68 +30,85 @@ fn setup(
     for vshare_batch in all_vshare_batches {
         all_initial_message_batches.push(rkpg::initial_messages_batch(&vshare_batch));
     }
-    let state = State::new(&indices, b);
 
-    (
-        state,
-        all_initial_message_batches,
-        precompute,
-        commitment_batch,
-        h,
-    )
+    (all_initial_message_batches, precompute, commitment_batch, h)
 }
 
-#[bench]
-fn bench_handle_share_no_reconstruct(b: &mut Bencher) {
+// The state can not be cloned, so it is built in the untimed setup of iter_batched.
+fn bench_handle_share_no_reconstruct(c: &mut Criterion) {
     let n = 100;
     let k = 33;
     let batch_size = 5;
     let indices = scalar::random_scalars_using_thread_rng(n);
 
-    let (state, all_initial_message_batches, precompute, commitment_batch, h) =
+    let (all_initial_message_batches, precompute, commitment_batch, h) =
         setup(&indices, k, batch_size);
 
-    let share_batch = all_initial_message_batches[0].clone();
-    b.iter(|| {
-        let res = rkpg::handle_share_batch(
-            &mut state.clone(),
-            share_batch.clone(),
-            &precompute,
-            &commitment_batch,
-            &h,
-        );
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), None);
-    })
+    let share_batch = &all_initial_message_batches[0];
+    c.bench_function("rkpg handle share no reconstruct", |b| {
+        b.iter_batched(
+            || (State::new(&indices, batch_size), share_batch.clone()),
+            |(mut state, share_batch)| {
+                let res = rkpg::handle_share_batch(
+                    &mut state,
+                    share_batch,
+                    &precompute,
+                    &commitment_batch,
+                    &h,
+                );
+                assert_eq!(res, Ok(None));
+            },
+            BatchSize::SmallInput,
+        )
+    });
 }
 
-#[bench]
-fn bench_handle_share_reconstruct(b: &mut Bencher) {
+fn bench_handle_share_reconstruct(c: &mut Criterion) {
     let n = 100;
     let k = 33;
     let batch_size = 5;
     let indices = scalar::random_scalars_using_thread_rng(n);
 
-    let (mut state, mut all_initial_message_batches, precompute, commitment_batch, h) =
+    let (mut all_initial_message_batches, precompute, commitment_batch, h) =
         setup(&indices, k, batch_size);
 
     let last_message_batches = all_initial_message_batches.split_off(n - k);
-    for share_batch in all_initial_message_batches {
-        let res =
-            rkpg::handle_share_batch(&mut state, share_batch, &precompute, &commitment_batch, &h);
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), None);
-    }
-
-    let share_batch = last_message_batches[0].clone();
-    b.iter(|| {
-        let res = rkpg::handle_share_batch(
-            &mut state.clone(),
-            share_batch.clone(),
-            &precompute,
-            &commitment_batch,
-            &h,
-        );
-        assert!(res.as_ref().map(Option::is_some).unwrap_or(false));
-    })
+    let share_batch = &last_message_batches[0];
+    let setup_state = || {
+        let mut state = State::new(&indices, batch_size);
+        for share_batch in all_initial_message_batches.iter().cloned() {
+            let res = rkpg::handle_share_batch(
+                &mut state,
+                share_batch,
+                &precompute,
+                &commitment_batch,
+                &h,
+            );
+            assert_eq!(res, Ok(None));
+        }
+        (state, share_batch.clone())
+    };
+    c.bench_function("rkpg handle share reconstruct", |b| {
+        b.iter_batched(
+            &setup_state,
+            |(mut state, share_batch)| {
+                let res = rkpg::handle_share_batch(
+                    &mut state,
+                    share_batch,
+                    &precompute,
+                    &commitment_batch,
+                    &h,
+                );
+                assert!(res.as_ref().map(Option::is_some).unwrap_or(false));
+            },
+            BatchSize::SmallInput,
+        )
+    });
 }
+
+criterion_group!(
+    benches,
+    bench_handle_share_no_reconstruct,
+    bench_handle_share_reconstruct
+);
+criterion_main!(benches);
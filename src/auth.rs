@@ -0,0 +1,379 @@
+use crate::params::{Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::transport::codec::Codec;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use sha2::{Digest, Sha256};
+
+// Schnorr signatures over secp256k1. These are used to bind messages to the identities of the
+// players, so that a player can not send messages under the index of another player.
+pub struct SigningKey {
+    secret: Scalar,
+    pub_key: Gej,
+}
+
+impl SigningKey {
+    pub fn new(secret: Scalar) -> Self {
+        let mut pub_key = Gej::default();
+        pub_key.scalar_base_mul(&secret);
+        SigningKey { secret, pub_key }
+    }
+
+    pub fn new_random_using_thread_rng() -> Self {
+        SigningKey::new(Scalar::new_random_using_thread_rng())
+    }
+
+    pub fn pub_key(&self) -> Gej {
+        self.pub_key
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> Signature {
+        let nonce = Scalar::new_random_using_thread_rng();
+        let mut r = Gej::default();
+        r.scalar_base_mul(&nonce);
+        let e = challenge(&r, &self.pub_key, msg);
+        let s = &nonce + &(&e * &self.secret);
+        Signature { r, s }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    r: Gej,
+    s: Scalar,
+}
+
+pub fn verify(pub_key: &Gej, msg: &[u8], signature: &Signature) -> bool {
+    let e = challenge(&signature.r, pub_key, msg);
+    let mut lhs = Gej::default();
+    lhs.scalar_base_mul(&signature.s);
+    let mut rhs = Gej::default();
+    rhs.scalar_mul(pub_key, &e);
+    rhs.add_assign(&signature.r);
+    lhs == rhs
+}
+
+fn challenge(r: &Gej, pub_key: &Gej, msg: &[u8]) -> Scalar {
+    let mut bs = [0_u8; 66];
+    r.put_bytes(&mut bs);
+    pub_key.put_bytes(&mut bs[33..]);
+    let mut hasher = Sha256::new();
+    hasher.update(&bs[..]);
+    hasher.update(msg);
+    let hash = hasher.finalize();
+    let mut e = Scalar::default();
+    e.set_b32(hash.as_slice());
+    e
+}
+
+impl Codec for Signature {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.r.encode(buf);
+        self.s.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Signature {
+            r: Gej::decode(bs)?,
+            s: Scalar::decode(bs)?,
+        })
+    }
+}
+
+// The identity keys of the players, in the same order as the indices in the parameters.
+#[derive(Clone, Debug)]
+pub struct Directory {
+    entries: Vec<(Scalar, Gej)>,
+}
+
+impl Directory {
+    pub fn new(params: &Parameters, pub_keys: Vec<Gej>) -> Result<Self, ParamsError> {
+        if pub_keys.len() != params.indices.len() {
+            return Err(ParamsError::WrongNumberOfKeys);
+        }
+        Ok(Directory {
+            entries: params.indices.iter().cloned().zip(pub_keys).collect(),
+        })
+    }
+
+    pub fn pub_key(&self, index: &Scalar) -> Option<&Gej> {
+        self.entries
+            .iter()
+            .find(|(i, _)| i == index)
+            .map(|(_, pub_key)| pub_key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signed<M> {
+    pub signer: Scalar,
+    pub message: M,
+    pub signature: Signature,
+}
+
+impl<M: Codec> Signed<M> {
+    pub fn new(key: &SigningKey, session: &[u8], signer: Scalar, message: M) -> Self {
+        let signature = key.sign(&signed_bytes(session, &signer, &message));
+        Signed {
+            signer,
+            message,
+            signature,
+        }
+    }
+
+    // Checks that the message was signed by the identity key of the signer for the given session.
+    pub fn is_valid(&self, directory: &Directory, session: &[u8]) -> bool {
+        match directory.pub_key(&self.signer) {
+            Some(pub_key) => verify(
+                pub_key,
+                &signed_bytes(session, &self.signer, &self.message),
+                &self.signature,
+            ),
+            None => false,
+        }
+    }
+}
+
+fn signed_bytes<M: Codec>(session: &[u8], signer: &Scalar, message: &M) -> Vec<u8> {
+    let mut buf = Vec::new();
+    session.len().encode(&mut buf);
+    buf.extend_from_slice(session);
+    signer.encode(&mut buf);
+    message.encode(&mut buf);
+    buf
+}
+
+impl<M: Codec> Codec for Signed<M> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.signer.encode(buf);
+        self.message.encode(buf);
+        self.signature.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Signed {
+            signer: Scalar::decode(bs)?,
+            message: M::decode(bs)?,
+            signature: Signature::decode(bs)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError<E> {
+    UnknownSigner,
+    InvalidSignature,
+    Protocol(E),
+}
+
+// Wraps a machine so that all of its messages are signed, and only messages that are signed by
+// the player that they claim to be from are handled. The inner machine is given the signer as the
+// sender, so its checks that shares have the index of the sender become checks against the
+// signer. The sender reported by the transport is only used to blame messages with invalid
+// signatures.
+//
+// The session is included in everything that is signed, so that messages from one instance of a
+// protocol can not be replayed in another.
+pub struct Authenticated<P> {
+    inner: P,
+    key: SigningKey,
+    directory: Directory,
+    session: Vec<u8>,
+}
+
+impl<P> Authenticated<P>
+where
+    P: Protocol,
+    P::Message: Codec,
+{
+    pub fn new(inner: P, key: SigningKey, directory: Directory, session: &[u8]) -> Self {
+        Authenticated {
+            inner,
+            key,
+            directory,
+            session: session.to_vec(),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn sign(&self, outgoing: Outgoing<P::Message>) -> Outgoing<Signed<P::Message>> {
+        Outgoing {
+            to: outgoing.to,
+            message: Signed::new(
+                &self.key,
+                &self.session,
+                self.inner.index(),
+                outgoing.message,
+            ),
+        }
+    }
+}
+
+impl<P> Protocol for Authenticated<P>
+where
+    P: Protocol,
+    P::Message: Codec,
+{
+    type Message = Signed<P::Message>;
+    type Output = P::Output;
+    type Error = AuthError<P::Error>;
+
+    fn index(&self) -> Scalar {
+        self.inner.index()
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Signed<P::Message>>> {
+        let outgoing = self.inner.initial_messages();
+        outgoing.into_iter().map(|o| self.sign(o)).collect()
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        signed: Signed<P::Message>,
+        outgoing: &mut Vec<Outgoing<Signed<P::Message>>>,
+    ) -> Result<(), Blame<AuthError<P::Error>>> {
+        if self.directory.pub_key(&signed.signer).is_none() {
+            return Err(Blame::new(*from, AuthError::UnknownSigner));
+        }
+        if !signed.is_valid(&self.directory, &self.session) {
+            return Err(Blame::new(*from, AuthError::InvalidSignature));
+        }
+
+        let mut inner_outgoing = Vec::new();
+        let res = self
+            .inner
+            .handle_message(&signed.signer, signed.message, &mut inner_outgoing);
+        for o in inner_outgoing {
+            outgoing.push(self.sign(o));
+        }
+        res.map_err(|blame| Blame::new(blame.culprit, AuthError::Protocol(blame.error)))
+    }
+
+    fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    fn output(&self) -> Option<P::Output> {
+        self.inner.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open::{self, OpenError};
+    use crate::sim::{self, Delivery};
+    use crate::testutil;
+    use secp256k1::scalar;
+    use shamir::vss::{SharingCommitment, VShare};
+
+    #[test]
+    fn signatures_verify_only_for_the_signed_message() {
+        let key = SigningKey::new_random_using_thread_rng();
+        let signature = key.sign(b"message");
+        assert!(verify(&key.pub_key(), b"message", &signature));
+        assert!(!verify(&key.pub_key(), b"massage", &signature));
+        let other = SigningKey::new_random_using_thread_rng();
+        assert!(!verify(&other.pub_key(), b"message", &signature));
+    }
+
+    fn authenticated_open(
+        params: Parameters,
+        secret: &Scalar,
+        pub_keys: &[Gej],
+        commitment_batch: &[SharingCommitment],
+        vshare_batch: &[VShare],
+        session: &[u8],
+    ) -> Authenticated<open::Machine> {
+        let directory = Directory::new(&params, pub_keys.to_vec()).unwrap();
+        let inner = open::Machine::new(params, commitment_batch.to_vec(), vshare_batch.to_vec());
+        Authenticated::new(inner, SigningKey::new(*secret), directory, session)
+    }
+
+    #[test]
+    fn messages_are_bound_to_the_signer() {
+        let n = 5;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let key_secrets = scalar::random_scalars_using_thread_rng(n);
+        let pub_keys: Vec<Gej> = key_secrets
+            .iter()
+            .map(|secret| SigningKey::new(*secret).pub_key())
+            .collect();
+        let params = |i: usize| Parameters::new(indices.clone(), indices[i], h).unwrap();
+        assert_eq!(
+            Directory::new(&params(0), pub_keys[1..].to_vec()).err(),
+            Some(ParamsError::WrongNumberOfKeys)
+        );
+
+        let mut machines: Vec<_> = (0..n)
+            .map(|i| {
+                authenticated_open(
+                    params(i),
+                    &key_secrets[i],
+                    &pub_keys,
+                    &commitment_batch,
+                    &vshares_by_player[i],
+                    b"session",
+                )
+            })
+            .collect();
+        let mut outgoing = Vec::new();
+
+        // A message that claims to be signed by another player.
+        let mut forged = machines[1].initial_messages().pop().unwrap().message;
+        forged.signer = indices[2];
+        assert_eq!(
+            machines[0].handle_message(&indices[1], forged, &mut outgoing),
+            Err(Blame::new(indices[1], AuthError::InvalidSignature))
+        );
+
+        // A message from a different instance of the protocol.
+        let mut other_session = authenticated_open(
+            params(1),
+            &key_secrets[1],
+            &pub_keys,
+            &commitment_batch,
+            &vshares_by_player[1],
+            b"other session",
+        );
+        let replayed = other_session.initial_messages().pop().unwrap().message;
+        assert_eq!(
+            machines[0].handle_message(&indices[1], replayed, &mut outgoing),
+            Err(Blame::new(indices[1], AuthError::InvalidSignature))
+        );
+
+        // A correctly signed message containing the shares of another player.
+        let mut cheater = authenticated_open(
+            params(1),
+            &key_secrets[1],
+            &pub_keys,
+            &commitment_batch,
+            &vshares_by_player[2],
+            b"session",
+        );
+        let stolen = cheater.initial_messages().pop().unwrap().message;
+        assert_eq!(
+            machines[0].handle_message(&indices[3], stolen, &mut outgoing),
+            Err(Blame::new(
+                indices[1],
+                AuthError::Protocol(OpenError::InconsistentIndices)
+            ))
+        );
+        assert!(outgoing.is_empty());
+
+        for output in sim::run(machines, Delivery::Random(3)) {
+            let values: Vec<Scalar> = output.iter().map(|(s, _)| *s).collect();
+            assert_eq!(values, secrets);
+        }
+    }
+}
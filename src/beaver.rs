@@ -0,0 +1,715 @@
+use crate::mulopen::{self, MulOpenErr};
+use crate::open::{self, OpenError};
+use crate::params::{self, Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::shared::{self, Linear};
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{SharingCommitment, VShare};
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Triple {
+    pub a: VShare,
+    pub b: VShare,
+    pub c: VShare,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TripleCommitment {
+    pub a: SharingCommitment,
+    pub b: SharingCommitment,
+    pub c: SharingCommitment,
+}
+
+// Triple generation runs mulopen with a random double sharing r from rng in place of the zero
+// sharing. The product a(x)b(x) is masked with the degree 2k - 2 sharing of r, so that the opened
+// polynomial ab + r is uniformly random in all of its coefficients and not only in its constant
+// term. Subtracting the degree k - 1 sharing of r from the opened value then leaves a degree k - 1
+// sharing of ab whose correctness is guaranteed by the product proofs.
+pub fn initial_message_batch(
+    a_vshare_batch: Vec<VShare>,
+    b_vshare_batch: Vec<VShare>,
+    r_high_vshare_batch: Vec<VShare>,
+    h: &Gej,
+) -> Vec<mulopen::Message> {
+    mulopen::initial_message_batch(a_vshare_batch, b_vshare_batch, r_high_vshare_batch, h)
+}
+
+pub fn handle_message_batch(
+    state: &mut Vec<Vec<Share>>,
+    message_batch: Vec<mulopen::Message>,
+    a_commitment_batch: &[SharingCommitment],
+    b_commitment_batch: &[SharingCommitment],
+    r_high_commitment_batch: &[SharingCommitment],
+    h: &Gej,
+) -> Result<Option<Vec<Scalar>>, MulOpenErr> {
+    mulopen::handle_message_batch(
+        state,
+        message_batch,
+        a_commitment_batch,
+        b_commitment_batch,
+        r_high_commitment_batch,
+        h,
+    )
+}
+
+pub fn output_triple_batch(
+    a_vshare_batch: Vec<VShare>,
+    b_vshare_batch: Vec<VShare>,
+    r_low_vshare_batch: Vec<VShare>,
+    opened_values: &[Scalar],
+) -> Vec<Triple> {
+    let b = opened_values.len();
+    assert_eq!(a_vshare_batch.len(), b);
+    assert_eq!(b_vshare_batch.len(), b);
+    assert_eq!(r_low_vshare_batch.len(), b);
+
+    let mut triple_batch = Vec::with_capacity(b);
+    for (((a, b), r), value) in a_vshare_batch
+        .into_iter()
+        .zip(b_vshare_batch.into_iter())
+        .zip(r_low_vshare_batch.into_iter())
+        .zip(opened_values.iter())
+    {
+        triple_batch.push(Triple {
+            a,
+            b,
+            c: output_c(&r, value),
+        });
+    }
+    triple_batch
+}
+
+pub fn output_triple_commitment_batch(
+    a_commitment_batch: &[SharingCommitment],
+    b_commitment_batch: &[SharingCommitment],
+    r_low_commitment_batch: &[SharingCommitment],
+    opened_values: &[Scalar],
+) -> Vec<TripleCommitment> {
+    let b = opened_values.len();
+    assert_eq!(a_commitment_batch.len(), b);
+    assert_eq!(b_commitment_batch.len(), b);
+    assert_eq!(r_low_commitment_batch.len(), b);
+
+    let mut triple_commitment_batch = Vec::with_capacity(b);
+    for (((a, b), r), value) in a_commitment_batch
+        .iter()
+        .zip(b_commitment_batch.iter())
+        .zip(r_low_commitment_batch.iter())
+        .zip(opened_values.iter())
+    {
+        triple_commitment_batch.push(TripleCommitment {
+            a: a.clone(),
+            b: b.clone(),
+            c: output_c(r, value),
+        });
+    }
+    triple_commitment_batch
+}
+
+// The sharing of c = ab is the opened value ab + r minus the degree k - 1 sharing of r.
+fn output_c<T: Linear>(r_low: &T, opened_value: &Scalar) -> T {
+    let mut c = shared::negated(r_low);
+    c.add_constant_assign_mut(opened_value);
+    c
+}
+
+// Generates a batch of triples for one player from random sharings a and b and a random double
+// sharing r.
+pub struct TripleMachine {
+    mulopen: mulopen::Machine,
+    a_vshare_batch: Vec<VShare>,
+    b_vshare_batch: Vec<VShare>,
+    r_low_vshare_batch: Vec<VShare>,
+    a_commitment_batch: Vec<SharingCommitment>,
+    b_commitment_batch: Vec<SharingCommitment>,
+    r_low_commitment_batch: Vec<SharingCommitment>,
+}
+
+impl TripleMachine {
+    pub fn new(
+        params: Parameters,
+        a_vshare_batch: Vec<VShare>,
+        b_vshare_batch: Vec<VShare>,
+        (r_low_vshare_batch, r_high_vshare_batch): (Vec<VShare>, Vec<VShare>),
+        a_commitment_batch: Vec<SharingCommitment>,
+        b_commitment_batch: Vec<SharingCommitment>,
+        (r_low_commitment_batch, r_high_commitment_batch): (
+            Vec<SharingCommitment>,
+            Vec<SharingCommitment>,
+        ),
+    ) -> Result<Self, ParamsError> {
+        // The product is masked by r_high, so it needs threshold 2k - 1 to hide all of the
+        // coefficients of ab.
+        if let Some(a_commitment) = a_commitment_batch.first() {
+            let k = a_commitment.len();
+            params::check_commitment_threshold(&b_commitment_batch, k)?;
+            params::check_commitment_threshold(&r_low_commitment_batch, k)?;
+            params::check_commitment_threshold(&r_high_commitment_batch, 2 * k - 1)?;
+        }
+        let mulopen = mulopen::Machine::new(
+            params,
+            a_vshare_batch.clone(),
+            b_vshare_batch.clone(),
+            r_high_vshare_batch,
+            a_commitment_batch.clone(),
+            b_commitment_batch.clone(),
+            r_high_commitment_batch,
+        )?;
+        Ok(TripleMachine {
+            mulopen,
+            a_vshare_batch,
+            b_vshare_batch,
+            r_low_vshare_batch,
+            a_commitment_batch,
+            b_commitment_batch,
+            r_low_commitment_batch,
+        })
+    }
+}
+
+impl Protocol for TripleMachine {
+    type Message = Vec<mulopen::Message>;
+    type Output = (Vec<Triple>, Vec<TripleCommitment>);
+    type Error = MulOpenErr;
+
+    fn index(&self) -> Scalar {
+        self.mulopen.index()
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<mulopen::Message>>> {
+        self.mulopen.initial_messages()
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message_batch: Vec<mulopen::Message>,
+        outgoing: &mut Vec<Outgoing<Vec<mulopen::Message>>>,
+    ) -> Result<(), Blame<MulOpenErr>> {
+        self.mulopen.handle_message(from, message_batch, outgoing)
+    }
+
+    fn is_done(&self) -> bool {
+        self.mulopen.is_done()
+    }
+
+    fn output(&self) -> Option<(Vec<Triple>, Vec<TripleCommitment>)> {
+        let opened_values = self.mulopen.output()?;
+        let triple_batch = output_triple_batch(
+            self.a_vshare_batch.clone(),
+            self.b_vshare_batch.clone(),
+            self.r_low_vshare_batch.clone(),
+            &opened_values,
+        );
+        let triple_commitment_batch = output_triple_commitment_batch(
+            &self.a_commitment_batch,
+            &self.b_commitment_batch,
+            &self.r_low_commitment_batch,
+            &opened_values,
+        );
+        Some((triple_batch, triple_commitment_batch))
+    }
+}
+
+// Beaver multiplication opens d = x - a and e = y - b. The masked values are returned as a single
+// batch of size 2b, with all of the d values first, so that both can be opened using one
+// open::State.
+pub fn masked_vshare_batch(
+    x_vshare_batch: &[VShare],
+    y_vshare_batch: &[VShare],
+    triple_batch: &[Triple],
+) -> Vec<VShare> {
+    masked_batch(
+        x_vshare_batch,
+        y_vshare_batch,
+        triple_batch.iter().map(|triple| (&triple.a, &triple.b)),
+    )
+}
+
+pub fn masked_commitment_batch(
+    x_commitment_batch: &[SharingCommitment],
+    y_commitment_batch: &[SharingCommitment],
+    triple_commitment_batch: &[TripleCommitment],
+) -> Vec<SharingCommitment> {
+    masked_batch(
+        x_commitment_batch,
+        y_commitment_batch,
+        triple_commitment_batch
+            .iter()
+            .map(|triple_commitment| (&triple_commitment.a, &triple_commitment.b)),
+    )
+}
+
+fn masked_batch<'a, T: Linear + 'a>(
+    x_batch: &[T],
+    y_batch: &[T],
+    ab_batch: impl ExactSizeIterator<Item = (&'a T, &'a T)> + Clone,
+) -> Vec<T> {
+    let b = ab_batch.len();
+    assert_eq!(x_batch.len(), b);
+    assert_eq!(y_batch.len(), b);
+
+    let mut masked_batch = Vec::with_capacity(2 * b);
+    for (x, (a, _)) in x_batch.iter().zip(ab_batch.clone()) {
+        let mut d = shared::negated(a);
+        d.add_assign_mut(x);
+        masked_batch.push(d);
+    }
+    for (y, (_, triple_b)) in y_batch.iter().zip(ab_batch) {
+        let mut e = shared::negated(triple_b);
+        e.add_assign_mut(y);
+        masked_batch.push(e);
+    }
+    masked_batch
+}
+
+pub fn output_vshare_batch(
+    triple_batch: &[Triple],
+    opened_values: &[(Scalar, Scalar)],
+) -> Vec<VShare> {
+    output_batch(
+        triple_batch
+            .iter()
+            .map(|triple| (&triple.a, &triple.b, &triple.c)),
+        opened_values,
+    )
+}
+
+pub fn output_commitment_batch(
+    triple_commitment_batch: &[TripleCommitment],
+    opened_values: &[(Scalar, Scalar)],
+) -> Vec<SharingCommitment> {
+    output_batch(
+        triple_commitment_batch.iter().map(|triple_commitment| {
+            (
+                &triple_commitment.a,
+                &triple_commitment.b,
+                &triple_commitment.c,
+            )
+        }),
+        opened_values,
+    )
+}
+
+// z = c + d b + e a + d e, where d = x - a and e = y - b were opened.
+fn output_batch<'a, T: Linear + 'a>(
+    triple_batch: impl ExactSizeIterator<Item = (&'a T, &'a T, &'a T)>,
+    opened_values: &[(Scalar, Scalar)],
+) -> Vec<T> {
+    let b = triple_batch.len();
+    assert_eq!(opened_values.len(), 2 * b);
+    let (ds, es) = opened_values.split_at(b);
+
+    let mut output_batch = Vec::with_capacity(b);
+    for ((a, triple_b, c), ((d, _), (e, _))) in triple_batch.zip(ds.iter().zip(es.iter())) {
+        let mut z = c.clone();
+        let mut tmp = triple_b.clone();
+        tmp.scale_assign_mut(d);
+        z.add_assign_mut(&tmp);
+        let mut tmp = a.clone();
+        tmp.scale_assign_mut(e);
+        z.add_assign_mut(&tmp);
+        z.add_constant_assign_mut(&(d * e));
+        output_batch.push(z);
+    }
+    output_batch
+}
+
+// The multi-round protocols that are built from Beaver multiplications (compare, bitdec and
+// circuit) all proceed in the same way: each round, a batch of masked values is computed using some
+// Beaver triples and opened, and then the opened values are used to update the state.
+pub trait Rounds {
+    type Output;
+
+    fn is_done(&self) -> bool;
+
+    fn num_multiplications(&self) -> usize;
+
+    // The number of triples needed for this round and all of the rounds after it.
+    fn num_triples(&self) -> usize;
+
+    fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare>;
+
+    fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment>;
+
+    fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    );
+
+    fn output(&self) -> Option<Self::Output>;
+}
+
+struct Round {
+    triple_batch: Vec<Triple>,
+    triple_commitment_batch: Vec<TripleCommitment>,
+    inst_params: open::InstanceParams,
+    state: open::State,
+}
+
+// Drives any of the Beaver round based protocols, taking triples from the given supply as they
+// are needed. Messages are tagged with the round that they are for, and messages for later rounds
+// are kept until that round starts.
+pub struct Machine<S: Rounds> {
+    params: Parameters,
+    state: S,
+    triple_batch: VecDeque<Triple>,
+    triple_commitment_batch: VecDeque<TripleCommitment>,
+    round: usize,
+    current: Option<Round>,
+    pending: Vec<(usize, Scalar, Vec<VShare>)>,
+    started: bool,
+    // An invalid message that was received before the first round started, which is reported
+    // from the next call to handle_message.
+    deferred: Option<Blame<OpenError>>,
+}
+
+impl<S: Rounds> Machine<S> {
+    pub fn new(
+        params: Parameters,
+        state: S,
+        triple_batch: Vec<Triple>,
+        triple_commitment_batch: Vec<TripleCommitment>,
+    ) -> Result<Self, ParamsError> {
+        assert_eq!(triple_batch.len(), triple_commitment_batch.len());
+        if triple_batch.len() < state.num_triples() {
+            return Err(ParamsError::NotEnoughTriples);
+        }
+        Ok(Machine {
+            params,
+            state,
+            triple_batch: triple_batch.into(),
+            triple_commitment_batch: triple_commitment_batch.into(),
+            round: 0,
+            current: None,
+            pending: Vec::new(),
+            started: false,
+            deferred: None,
+        })
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    // Starts as many rounds as possible, using any messages that were received early. An invalid
+    // early message does not stop the remaining ones from being handled, but the first one found
+    // is reported once there is nothing left to do.
+    fn start_rounds(
+        &mut self,
+        outgoing: &mut Vec<Outgoing<(usize, Vec<VShare>)>>,
+    ) -> Result<(), Blame<OpenError>> {
+        let mut blame = None;
+        while !self.state.is_done() {
+            // There are enough triples for every round, which was checked in new.
+            let m = self.state.num_multiplications();
+            let triple_batch: Vec<Triple> = self.triple_batch.drain(..m).collect();
+            let triple_commitment_batch: Vec<TripleCommitment> =
+                self.triple_commitment_batch.drain(..m).collect();
+            let vshare_batch = self.state.masked_vshare_batch(&triple_batch);
+            let inst_params = open::InstanceParams::new(
+                self.state.masked_commitment_batch(&triple_commitment_batch),
+            );
+            let state = open::State::new(&inst_params);
+            self.current = Some(Round {
+                triple_batch,
+                triple_commitment_batch,
+                inst_params,
+                state,
+            });
+            outgoing.push(Outgoing::broadcast((self.round, vshare_batch)));
+
+            let round = self.round;
+            let (pending, later) = self.pending.drain(..).partition(|(r, _, _)| *r == round);
+            self.pending = later;
+            let mut finished = false;
+            for (_, from, vshare_batch) in pending {
+                match self.handle_current(&from, vshare_batch) {
+                    Ok(true) => {
+                        finished = true;
+                        break;
+                    }
+                    Ok(false) => (),
+                    Err(e) => {
+                        blame.get_or_insert(e);
+                    }
+                }
+            }
+            if !finished {
+                break;
+            }
+        }
+        match blame {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn take_deferred(&mut self) -> Result<(), Blame<OpenError>> {
+        match self.deferred.take() {
+            Some(blame) => Err(blame),
+            None => Ok(()),
+        }
+    }
+
+    // Returns true if the current round has been completed.
+    fn handle_current(
+        &mut self,
+        from: &Scalar,
+        vshare_batch: Vec<VShare>,
+    ) -> Result<bool, Blame<OpenError>> {
+        if vshare_batch
+            .iter()
+            .any(|vshare| &vshare.share.index != from)
+        {
+            return Err(Blame::new(*from, OpenError::InconsistentIndices));
+        }
+        let round = self.current.as_mut().expect("round should have started");
+        let res = round
+            .state
+            .handle_vshare_batch(&round.inst_params, &self.params, vshare_batch)
+            .map_err(|e| Blame::new(*from, e))?;
+        match res {
+            Some(opened_values) => {
+                let round = self.current.take().unwrap();
+                self.state.handle_opened_values(
+                    &round.triple_batch,
+                    &round.triple_commitment_batch,
+                    &opened_values,
+                );
+                self.round += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl<S: Rounds> Protocol for Machine<S> {
+    type Message = (usize, Vec<VShare>);
+    type Output = S::Output;
+    type Error = OpenError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<(usize, Vec<VShare>)>> {
+        let mut outgoing = Vec::new();
+        if self.started {
+            return outgoing;
+        }
+        self.started = true;
+        if let Err(blame) = self.start_rounds(&mut outgoing) {
+            self.deferred = Some(blame);
+        }
+        outgoing
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: (usize, Vec<VShare>),
+        outgoing: &mut Vec<Outgoing<(usize, Vec<VShare>)>>,
+    ) -> Result<(), Blame<OpenError>> {
+        let (round, vshare_batch) = message;
+        if round < self.round {
+            return self.take_deferred();
+        }
+        if round > self.round || self.current.is_none() {
+            self.pending.push((round, *from, vshare_batch));
+            return self.take_deferred();
+        }
+        if self.handle_current(from, vshare_batch)? {
+            self.start_rounds(outgoing)?;
+        }
+        self.take_deferred()
+    }
+
+    fn is_done(&self) -> bool {
+        self.state.is_done()
+    }
+
+    fn output(&self) -> Option<S::Output> {
+        self.state.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open;
+    use crate::params::Parameters;
+    use crate::testutil;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    #[test]
+    fn triple_masks_of_the_wrong_degree_are_rejected() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let (a_vshares_by_player, a_commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (
+            (r_low_vshares_by_player, r_low_commitment_batch),
+            (r_high_vshares_by_player, r_high_commitment_batch),
+        ) = testutil::double_rng_by_player(k, b, &indices, &h);
+        let new = |low: (&[Vec<VShare>], &[SharingCommitment]),
+                   high: (&[Vec<VShare>], &[SharingCommitment])| {
+            TripleMachine::new(
+                params.clone(),
+                a_vshares_by_player[0].clone(),
+                a_vshares_by_player[0].clone(),
+                (low.0[0].clone(), high.0[0].clone()),
+                a_commitment_batch.clone(),
+                a_commitment_batch.clone(),
+                (low.1.to_vec(), high.1.to_vec()),
+            )
+            .err()
+        };
+        let low = (&r_low_vshares_by_player[..], &r_low_commitment_batch[..]);
+        let high = (&r_high_vshares_by_player[..], &r_high_commitment_batch[..]);
+
+        assert_eq!(new(low, high), None);
+        assert_eq!(
+            new(high, low),
+            Some(ParamsError::InvalidCommitmentThreshold)
+        );
+        assert_eq!(new(low, low), Some(ParamsError::InvalidCommitmentThreshold));
+    }
+
+    #[test]
+    fn beaver_multiplication_produces_product_sharing() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let threshold = 2 * k - 1;
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        let (a_shares_by_player, a_commitments) = testutil::rng_by_player(k, b, &indices, &h);
+        let (b_shares_by_player, b_commitments) = testutil::rng_by_player(k, b, &indices, &h);
+        let (
+            (r_low_shares_by_player, r_low_commitments),
+            (r_high_shares_by_player, r_high_commitments),
+        ) = testutil::double_rng_by_player(k, b, &indices, &h);
+        let secrets = |vshares_by_player: &[Vec<VShare>], commitments: &[SharingCommitment]| {
+            testutil::open_by_player(vshares_by_player, commitments.to_vec(), &indices, &h)
+                .into_iter()
+                .map(|(secret, _)| secret)
+                .collect::<Vec<_>>()
+        };
+        let a_secrets = secrets(&a_shares_by_player, &a_commitments);
+        let b_secrets = secrets(&b_shares_by_player, &b_commitments);
+
+        let mut state = Vec::with_capacity(b);
+        state.resize_with(b, || Vec::with_capacity(threshold));
+        let mut opened_values = None;
+        for ((a_batch, b_batch), r_batch) in a_shares_by_player
+            .iter()
+            .zip(b_shares_by_player.iter())
+            .zip(r_high_shares_by_player.iter())
+            .take(threshold)
+        {
+            let message_batch =
+                initial_message_batch(a_batch.clone(), b_batch.clone(), r_batch.clone(), &h);
+            opened_values = handle_message_batch(
+                &mut state,
+                message_batch,
+                &a_commitments,
+                &b_commitments,
+                &r_high_commitments,
+                &h,
+            )
+            .unwrap();
+        }
+        let opened_values = opened_values.unwrap();
+
+        let triple_commitment_batch = output_triple_commitment_batch(
+            &a_commitments,
+            &b_commitments,
+            &r_low_commitments,
+            &opened_values,
+        );
+        let mut triples_by_player = Vec::with_capacity(n);
+        for ((a_batch, b_batch), r_batch) in a_shares_by_player
+            .into_iter()
+            .zip(b_shares_by_player.into_iter())
+            .zip(r_low_shares_by_player.into_iter())
+        {
+            let triple_batch = output_triple_batch(a_batch, b_batch, r_batch, &opened_values);
+            for (triple, commitment) in triple_batch.iter().zip(triple_commitment_batch.iter()) {
+                assert!(vss::vshare_is_valid(&triple.c, &commitment.c, &h));
+            }
+            triples_by_player.push(triple_batch);
+        }
+        let c_sharings = testutil::transpose(triples_by_player.clone());
+        for ((sharing, a_secret), b_secret) in c_sharings
+            .iter()
+            .zip(a_secrets.iter())
+            .zip(b_secrets.iter())
+        {
+            let c_vshares: Vec<_> = sharing.iter().map(|triple| triple.c).collect();
+            let (c_secret, _) = vss::interpolate_shares_at_zero(c_vshares.iter());
+            assert_eq!(c_secret, a_secret * b_secret);
+        }
+
+        let (x_shares_by_player, x_commitments, x_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (y_shares_by_player, y_commitments, y_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let inst_params = open::InstanceParams::new(masked_commitment_batch(
+            &x_commitments,
+            &y_commitments,
+            &triple_commitment_batch,
+        ));
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let mut state = open::State::new(&inst_params);
+        let mut opened_values = None;
+        for ((x, y), triple_batch) in x_shares_by_player
+            .iter()
+            .zip(y_shares_by_player.iter())
+            .zip(triples_by_player.iter())
+            .take(k)
+        {
+            let masked = masked_vshare_batch(x, y, triple_batch);
+            opened_values = state
+                .handle_vshare_batch(&inst_params, &params, masked)
+                .unwrap();
+        }
+        let opened_values = opened_values.unwrap();
+
+        let output_commitments = output_commitment_batch(&triple_commitment_batch, &opened_values);
+        let mut outputs_by_player = Vec::with_capacity(n);
+        for triple_batch in triples_by_player.iter() {
+            let output_batch = output_vshare_batch(triple_batch, &opened_values);
+            for (vshare, commitment) in output_batch.iter().zip(output_commitments.iter()) {
+                assert!(vss::vshare_is_valid(vshare, commitment, &h));
+            }
+            outputs_by_player.push(output_batch);
+        }
+        let output_sharings = testutil::transpose(outputs_by_player);
+        for ((sharing, x_secret), y_secret) in output_sharings
+            .iter()
+            .zip(x_secrets.iter())
+            .zip(y_secrets.iter())
+        {
+            let (output, _) = vss::interpolate_shares_at_zero(sharing.iter());
+            assert_eq!(output, x_secret * y_secret);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use crate::beaver::{self, Triple, TripleCommitment};
+use crate::compare::{self, CompareError};
+use crate::shared::{self, Linear};
+use crate::util;
+use secp256k1::scalar::Scalar;
+use shamir::vss::{SharingCommitment, VShare};
+
+// Bit decomposition of a value x in [0, 2^l). The value is masked with random bits and opened as
+// in compare, giving a public value c with x = (c - r) mod 2^l. The bits of x are then computed
+// with a binary subtractor: the borrows into each position are computed as a parallel prefix
+// (Kogge-Stone) over the (generate, propagate) nodes of each position, followed by one more round
+// to compute x_i = c_i xor r_i xor borrow_i.
+
+pub fn num_random_bits(l: usize) -> usize {
+    compare::num_random_bits_eqz(l)
+}
+
+pub fn masked_vshare(
+    x_vshare: &VShare,
+    random_bit_vshares: &[VShare],
+    l: usize,
+) -> Result<VShare, CompareError> {
+    compare::eqz_masked_vshare(x_vshare, random_bit_vshares, l)
+}
+
+pub fn masked_commitment(
+    x_commitment: &SharingCommitment,
+    random_bit_commitments: &[SharingCommitment],
+    l: usize,
+) -> Result<SharingCommitment, CompareError> {
+    compare::eqz_masked_commitment(x_commitment, random_bit_commitments, l)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Phase {
+    Prefix(usize),
+    Xor,
+    Done,
+}
+
+#[derive(Clone)]
+pub struct State {
+    phase: Phase,
+    d_vshares: Vec<VShare>,
+    d_commitments: Vec<SharingCommitment>,
+    g_vshares: Vec<VShare>,
+    g_commitments: Vec<SharingCommitment>,
+    p_vshares: Vec<VShare>,
+    p_commitments: Vec<SharingCommitment>,
+    output_vshares: Vec<VShare>,
+    output_commitments: Vec<SharingCommitment>,
+}
+
+impl State {
+    pub fn new(
+        opened_value: &Scalar,
+        random_bit_vshares: &[VShare],
+        random_bit_commitments: &[SharingCommitment],
+        l: usize,
+    ) -> Result<Self, CompareError> {
+        if l == 0 || l > compare::MAX_BIT_LENGTH {
+            return Err(CompareError::InvalidBitLength);
+        }
+        if random_bit_vshares.len() != num_random_bits(l)
+            || random_bit_commitments.len() != num_random_bits(l)
+        {
+            return Err(CompareError::WrongNumberOfRandomBits);
+        }
+
+        let (d_vshares, g_vshares, p_vshares) = bit_nodes(opened_value, &random_bit_vshares[..l]);
+        let (d_commitments, g_commitments, p_commitments) =
+            bit_nodes(opened_value, &random_bit_commitments[..l]);
+        let mut state = State {
+            phase: Phase::Prefix(1),
+            d_vshares,
+            d_commitments,
+            g_vshares,
+            g_commitments,
+            p_vshares,
+            p_commitments,
+            output_vshares: Vec::new(),
+            output_commitments: Vec::new(),
+        };
+        if l == 1 {
+            state.output_vshares = state.d_vshares.clone();
+            state.output_commitments = state.d_commitments.clone();
+            state.phase = Phase::Done;
+        }
+        Ok(state)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == Phase::Done
+    }
+
+    pub fn num_multiplications(&self) -> usize {
+        let l = self.d_vshares.len();
+        match self.phase {
+            Phase::Prefix(s) => 2 * (l - s),
+            Phase::Xor => l - 1,
+            Phase::Done => 0,
+        }
+    }
+
+    pub fn num_triples(&self) -> usize {
+        let l = self.d_vshares.len();
+        match self.phase {
+            Phase::Prefix(mut s) => {
+                let mut triples = l - 1;
+                while s < l {
+                    triples += 2 * (l - s);
+                    s *= 2;
+                }
+                triples
+            }
+            Phase::Xor => l - 1,
+            Phase::Done => 0,
+        }
+    }
+
+    pub fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare> {
+        let (xs, ys) = match self.phase {
+            Phase::Prefix(s) => prefix_operands(&self.g_vshares, &self.p_vshares, s),
+            Phase::Xor => xor_operands(&self.d_vshares, &self.g_vshares),
+            Phase::Done => panic!("bit decomposition has already finished"),
+        };
+        beaver::masked_vshare_batch(&xs, &ys, triple_batch)
+    }
+
+    pub fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment> {
+        let (xs, ys) = match self.phase {
+            Phase::Prefix(s) => prefix_operands(&self.g_commitments, &self.p_commitments, s),
+            Phase::Xor => xor_operands(&self.d_commitments, &self.g_commitments),
+            Phase::Done => panic!("bit decomposition has already finished"),
+        };
+        beaver::masked_commitment_batch(&xs, &ys, triple_commitment_batch)
+    }
+
+    pub fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    ) {
+        let l = self.d_vshares.len();
+        let vshare_products = beaver::output_vshare_batch(triple_batch, opened_values);
+        let commitment_products =
+            beaver::output_commitment_batch(triple_commitment_batch, opened_values);
+        match self.phase {
+            Phase::Prefix(s) => {
+                prefix_combine(&mut self.g_vshares, &mut self.p_vshares, s, vshare_products);
+                prefix_combine(
+                    &mut self.g_commitments,
+                    &mut self.p_commitments,
+                    s,
+                    commitment_products,
+                );
+                self.phase = if 2 * s < l {
+                    Phase::Prefix(2 * s)
+                } else {
+                    Phase::Xor
+                };
+            }
+            Phase::Xor => {
+                self.output_vshares =
+                    xor_outputs(&self.d_vshares, &self.g_vshares, vshare_products);
+                self.output_commitments = xor_outputs(
+                    &self.d_commitments,
+                    &self.g_commitments,
+                    commitment_products,
+                );
+                self.phase = Phase::Done;
+            }
+            Phase::Done => panic!("bit decomposition has already finished"),
+        }
+    }
+
+    pub fn output(&self) -> Option<(Vec<VShare>, Vec<SharingCommitment>)> {
+        if self.is_done() {
+            Some((self.output_vshares.clone(), self.output_commitments.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+impl beaver::Rounds for State {
+    type Output = (Vec<VShare>, Vec<SharingCommitment>);
+
+    fn is_done(&self) -> bool {
+        State::is_done(self)
+    }
+
+    fn num_multiplications(&self) -> usize {
+        State::num_multiplications(self)
+    }
+
+    fn num_triples(&self) -> usize {
+        State::num_triples(self)
+    }
+
+    fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare> {
+        State::masked_vshare_batch(self, triple_batch)
+    }
+
+    fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment> {
+        State::masked_commitment_batch(self, triple_commitment_batch)
+    }
+
+    fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    ) {
+        State::handle_opened_values(self, triple_batch, triple_commitment_batch, opened_values)
+    }
+
+    fn output(&self) -> Option<(Vec<VShare>, Vec<SharingCommitment>)> {
+        State::output(self)
+    }
+}
+
+// The initial nodes for each bit r_i: d = c_i xor r_i, p = 1 - d and g = (1 - c_i) r_i.
+fn bit_nodes<T: Linear>(opened_value: &Scalar, bits: &[T]) -> (Vec<T>, Vec<T>, Vec<T>) {
+    let l = bits.len();
+    let one = util::scalar_from_u64(1);
+    let c_bits = util::scalar_to_bits_le(opened_value);
+    let mut d_nodes = Vec::with_capacity(l);
+    let mut g_nodes = Vec::with_capacity(l);
+    let mut p_nodes = Vec::with_capacity(l);
+    for (c_bit, bit) in c_bits.iter().zip(bits.iter()) {
+        let mut d_node = bit.clone();
+        let mut g_node = bit.clone();
+        if *c_bit {
+            d_node.negate_assign_mut();
+            d_node.add_constant_assign_mut(&one);
+            g_node.scale_assign_mut(&Scalar::zero());
+        }
+        let mut p_node = shared::negated(&d_node);
+        p_node.add_constant_assign_mut(&one);
+
+        d_nodes.push(d_node);
+        g_nodes.push(g_node);
+        p_nodes.push(p_node);
+    }
+    (d_nodes, g_nodes, p_nodes)
+}
+
+// Combines each node i >= s with node i - s, so that after the rounds for s = 1, 2, 4, ... node i
+// covers all positions up to and including i.
+fn prefix_operands<T: Clone>(g: &[T], p: &[T], s: usize) -> (Vec<T>, Vec<T>) {
+    let l = p.len();
+    let mut xs = Vec::with_capacity(2 * (l - s));
+    xs.extend_from_slice(&p[s..]);
+    xs.extend_from_slice(&p[s..]);
+    let mut ys = Vec::with_capacity(2 * (l - s));
+    ys.extend_from_slice(&g[..l - s]);
+    ys.extend_from_slice(&p[..l - s]);
+    (xs, ys)
+}
+
+fn prefix_combine<T: Linear>(g: &mut [T], p: &mut [T], s: usize, products: Vec<T>) {
+    let m = p.len() - s;
+    let (pg_products, pp_products) = products.split_at(m);
+    for j in 0..m {
+        g[s + j].add_assign_mut(&pg_products[j]);
+        p[s + j] = pp_products[j].clone();
+    }
+}
+
+// The borrow into position i is the prefix generate value of position i - 1.
+fn xor_operands<T: Clone>(d: &[T], g: &[T]) -> (Vec<T>, Vec<T>) {
+    let l = d.len();
+    (d[1..].to_vec(), g[..l - 1].to_vec())
+}
+
+fn xor_outputs<T: Linear>(d: &[T], g: &[T], products: Vec<T>) -> Vec<T> {
+    let mut neg_two = util::scalar_from_u64(2);
+    neg_two.negate_assign_mut();
+    let mut outputs = Vec::with_capacity(d.len());
+    outputs.push(d[0].clone());
+    for (i, product) in products.into_iter().enumerate() {
+        let mut output = product;
+        output.scale_assign_mut(&neg_two);
+        output.add_assign_mut(&d[i + 1]);
+        output.add_assign_mut(&g[i]);
+        outputs.push(output);
+    }
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::group::Gej;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    #[test]
+    fn bits_recompose_to_secret() {
+        let n = 10;
+        let k = 3;
+        let l = 16;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        for x in [0, 1, 0xbeef, 0xffff].iter() {
+            let secret = util::scalar_from_u64(*x);
+            let (x_vshares, x_commitment) = vss::vshare_secret(&h, &indices, &secret, k);
+            let (bits_by_player, bit_commitments, _) =
+                testutil::random_bit_sharing_batch(k, num_random_bits(l), &indices, &h);
+
+            let mut masked_by_player = Vec::with_capacity(n);
+            for (x_vshare, bits) in x_vshares.iter().zip(bits_by_player.iter()) {
+                masked_by_player.push(vec![masked_vshare(x_vshare, bits, l).unwrap()]);
+            }
+            let opened_values = testutil::open_by_player(
+                &masked_by_player,
+                vec![masked_commitment(&x_commitment, &bit_commitments, l).unwrap()],
+                &indices,
+                &h,
+            );
+
+            let mut states = Vec::with_capacity(n);
+            for bits in bits_by_player.iter() {
+                states.push(State::new(&opened_values[0].0, bits, &bit_commitments, l).unwrap());
+            }
+            let mut remaining_triples = states[0].num_triples();
+            while !states[0].is_done() {
+                let m = states[0].num_multiplications();
+                assert_eq!(states[0].num_triples(), remaining_triples);
+                remaining_triples -= m;
+                let (triples_by_player, triple_commitments) =
+                    testutil::beaver_triple_batch(n, k, m, &indices, &h);
+                let mut masked_by_player = Vec::with_capacity(n);
+                for (state, triple_batch) in states.iter().zip(triples_by_player.iter()) {
+                    masked_by_player.push(state.masked_vshare_batch(triple_batch));
+                }
+                let opened_values = testutil::open_by_player(
+                    &masked_by_player,
+                    states[0].masked_commitment_batch(&triple_commitments),
+                    &indices,
+                    &h,
+                );
+                for (state, triple_batch) in states.iter_mut().zip(triples_by_player.iter()) {
+                    state.handle_opened_values(triple_batch, &triple_commitments, &opened_values);
+                }
+            }
+            assert_eq!(remaining_triples, 0);
+
+            let mut outputs_by_player = Vec::with_capacity(n);
+            for state in states.iter() {
+                let (vshares, commitments) = state.output().unwrap();
+                assert_eq!(vshares.len(), l);
+                for (vshare, commitment) in vshares.iter().zip(commitments.iter()) {
+                    assert!(vss::vshare_is_valid(vshare, commitment, &h));
+                }
+                outputs_by_player.push(vshares);
+            }
+
+            let one = util::scalar_from_u64(1);
+            let mut recomposed = Scalar::zero();
+            for (i, sharing) in testutil::transpose(outputs_by_player).iter().enumerate() {
+                let (bit, _) = vss::interpolate_shares_at_zero(sharing.iter());
+                assert!(bit.is_zero() || bit == one);
+                recomposed = recomposed + &bit * &util::scalar_pow_two(i);
+            }
+            assert_eq!(recomposed, secret);
+        }
+    }
+}
@@ -1,7 +1,12 @@
 use secp256k1::scalar::Scalar;
 use shamir::vss::{self, SharingCommitment, VShare, VSharing};
 
-use crate::params::Parameters;
+use crate::equivocation::{Accepted, Seen};
+use crate::params::{Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::rng::DirectedVShare;
+use crate::secret::Secret;
+use crate::shared::SharedScalar;
 
 #[derive(Debug, PartialEq)]
 pub enum BRNGError {
@@ -9,17 +14,17 @@ pub enum BRNGError {
     InvalidCommitments,
     WrongIndex,
     InvalidShare,
+    InvalidDealer,
+    DuplicateDealer,
+    Equivocation,
+    InvalidBatchSize,
 }
 
 pub fn create_sharing_batch(b: usize, k: usize, params: &Parameters) -> Vec<VSharing> {
     let mut sharing_batch = Vec::with_capacity(b);
     for _ in 0..b {
-        let (vshares, commitment) = vss::vshare_secret(
-            &params.h,
-            &params.indices,
-            &Scalar::new_random_using_thread_rng(),
-            k,
-        );
+        let secret = Secret::new(Scalar::new_random_using_thread_rng());
+        let (vshares, commitment) = vss::vshare_secret(&params.h, &params.indices, &secret, k);
         sharing_batch.push(VSharing {
             vshares,
             commitment,
@@ -28,6 +33,31 @@ pub fn create_sharing_batch(b: usize, k: usize, params: &Parameters) -> Vec<VSha
     sharing_batch
 }
 
+// The message that a dealer sends to one player, which contains the player's share of each sharing
+// in the batch together with the commitments.
+pub type Message = (Vec<DirectedVShare>, Vec<SharingCommitment>);
+
+pub fn messages(sharing_batch: &[VSharing], indices: &[Scalar]) -> Vec<Message> {
+    let commitment_batch: Vec<SharingCommitment> = sharing_batch
+        .iter()
+        .map(|sharing| sharing.commitment.clone())
+        .collect();
+    indices
+        .iter()
+        .enumerate()
+        .map(|(i, to)| {
+            let directed_vshare_batch = sharing_batch
+                .iter()
+                .map(|sharing| DirectedVShare {
+                    vshare: sharing.vshares[i],
+                    to: *to,
+                })
+                .collect();
+            (directed_vshare_batch, commitment_batch.clone())
+        })
+        .collect()
+}
+
 pub fn is_valid<'a, I, J>(
     k: usize,
     params: &Parameters,
@@ -82,14 +112,142 @@ where
     I: Iterator<Item = (&'a VShare, &'a SharingCommitment)>,
 {
     let (initial_vshare, initial_commitment) = vshare_commitment_pairs.next().unwrap();
-    vshare_commitment_pairs.fold(
-        (initial_vshare.clone(), initial_commitment.clone()),
-        |(mut acc_vshare, mut acc_commitment), (vshare, commitment)| {
-            acc_vshare.add_assign_mut(vshare);
-            acc_commitment.add_assign_mut(commitment);
-            (acc_vshare, acc_commitment)
-        },
-    )
+    vshare_commitment_pairs
+        .fold(
+            SharedScalar::new(*initial_vshare, initial_commitment.clone()),
+            |mut acc, (vshare, commitment)| {
+                acc.add_assign_mut(&SharedScalar::new(*vshare, commitment.clone()));
+                acc
+            },
+        )
+        .into_parts()
+}
+
+// Checks the message from a single dealer, in the same way as is_valid checks each contribution.
+fn check_message(
+    k: usize,
+    b: usize,
+    params: &Parameters,
+    message: &Message,
+) -> Result<(), BRNGError> {
+    use BRNGError::*;
+
+    let (directed_vshare_batch, commitment_batch) = message;
+    if directed_vshare_batch.len() != b || commitment_batch.len() != b {
+        return Err(InvalidBatchSize);
+    }
+    if commitment_batch
+        .iter()
+        .any(|commitment| commitment.len() != k)
+    {
+        return Err(InvalidCommitments);
+    }
+    if directed_vshare_batch
+        .iter()
+        .any(|dv| dv.to != params.index || dv.vshare.share.index != params.index)
+    {
+        return Err(WrongIndex);
+    }
+    if !directed_vshare_batch
+        .iter()
+        .zip(commitment_batch.iter())
+        .all(|(dv, commitment)| vss::vshare_is_valid(&dv.vshare, commitment, &params.h))
+    {
+        return Err(InvalidShare);
+    }
+    Ok(())
+}
+
+// Runs brng for one player. The first k players are the dealers, and each of them shares a batch of
+// b random secrets. Every player outputs the sums of the dealers' sharings.
+pub struct Machine {
+    params: Parameters,
+    k: usize,
+    b: usize,
+    messages: Vec<Message>,
+    accepted: Accepted<Message>,
+    output: Option<(Vec<VShare>, Vec<SharingCommitment>)>,
+}
+
+impl Machine {
+    pub fn new(params: Parameters, k: usize, b: usize) -> Result<Self, ParamsError> {
+        params.check_threshold(k)?;
+        let messages = if params.indices[..k].contains(&params.index) {
+            messages(&create_sharing_batch(b, k, &params), &params.indices)
+        } else {
+            Vec::new()
+        };
+        Ok(Machine {
+            params,
+            k,
+            b,
+            messages,
+            accepted: Accepted::new(),
+            output: None,
+        })
+    }
+
+    pub fn dealers(&self) -> &[Scalar] {
+        &self.params.indices[..self.k]
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Message;
+    type Output = (Vec<VShare>, Vec<SharingCommitment>);
+    type Error = BRNGError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Message>> {
+        self.params
+            .indices
+            .iter()
+            .zip(self.messages.drain(..))
+            .map(|(to, message)| Outgoing::directed(*to, message))
+            .collect()
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: Message,
+        _outgoing: &mut Vec<Outgoing<Message>>,
+    ) -> Result<(), Blame<BRNGError>> {
+        let blame = |e: BRNGError| Blame::new(*from, e);
+        if !self.dealers().contains(from) {
+            return Err(blame(BRNGError::InvalidDealer));
+        }
+        check_message(self.k, self.b, &self.params, &message).map_err(blame)?;
+        match self.accepted.check(from, &message) {
+            Seen::New => (),
+            Seen::Same => return Err(blame(BRNGError::DuplicateDealer)),
+            Seen::Different(_) => return Err(blame(BRNGError::Equivocation)),
+        }
+        self.accepted.insert(*from, message);
+
+        if self.accepted.len() == self.k {
+            let accepted = &self.accepted;
+            self.output = Some(output_sharing_batch((0..self.b).map(|i| {
+                accepted
+                    .messages()
+                    .map(move |(directed_vshare_batch, commitment_batch)| {
+                        (&directed_vshare_batch[i].vshare, &commitment_batch[i])
+                    })
+            })));
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<(Vec<VShare>, Vec<SharingCommitment>)> {
+        self.output.clone()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,548 @@
+use crate::beaver::{self, Triple, TripleCommitment};
+use crate::shared::{SharedScalar, SharedScalarBatch};
+use secp256k1::scalar::Scalar;
+use shamir::vss::{SharingCommitment, VShare};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum CircuitError {
+    WrongNumberOfInputs,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Wire(usize);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Gate {
+    Input(usize),
+    Constant(Scalar),
+    Add(Wire, Wire),
+    MulConst(Wire, Scalar),
+    Mul(Wire, Wire),
+    Open(Wire),
+}
+
+// An arithmetic circuit over shared and public values. Gates are stored in the order that they
+// are created, which is always a topological order. Whether a wire is public and the number of
+// rounds of interaction needed before its value is known are determined when the gate is added:
+// a multiplication of two shared values and the opening of a shared value each take one round, and
+// all other gates are computed locally.
+#[derive(Clone, Debug, Default)]
+pub struct Circuit {
+    gates: Vec<Gate>,
+    public: Vec<bool>,
+    rounds: Vec<usize>,
+    num_inputs: usize,
+    outputs: Vec<Wire>,
+}
+
+impl Circuit {
+    pub fn new() -> Self {
+        Circuit::default()
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.rounds.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn outputs(&self) -> &[Wire] {
+        &self.outputs
+    }
+
+    pub fn is_public(&self, wire: Wire) -> bool {
+        self.check_wire(wire);
+        self.public[wire.0]
+    }
+
+    pub fn input(&mut self) -> Wire {
+        let i = self.num_inputs;
+        self.num_inputs += 1;
+        self.push_gate(Gate::Input(i), false, 0)
+    }
+
+    pub fn constant(&mut self, constant: Scalar) -> Wire {
+        self.push_gate(Gate::Constant(constant), true, 0)
+    }
+
+    pub fn add(&mut self, x: Wire, y: Wire) -> Wire {
+        self.check_wire(x);
+        self.check_wire(y);
+        let public = self.public[x.0] && self.public[y.0];
+        let round = self.rounds[x.0].max(self.rounds[y.0]);
+        self.push_gate(Gate::Add(x, y), public, round)
+    }
+
+    pub fn mul_const(&mut self, x: Wire, constant: Scalar) -> Wire {
+        self.check_wire(x);
+        let public = self.public[x.0];
+        let round = self.rounds[x.0];
+        self.push_gate(Gate::MulConst(x, constant), public, round)
+    }
+
+    pub fn mul(&mut self, x: Wire, y: Wire) -> Wire {
+        self.check_wire(x);
+        self.check_wire(y);
+        let public = self.public[x.0] && self.public[y.0];
+        let mut round = self.rounds[x.0].max(self.rounds[y.0]);
+        if !self.public[x.0] && !self.public[y.0] {
+            round += 1;
+        }
+        self.push_gate(Gate::Mul(x, y), public, round)
+    }
+
+    pub fn open(&mut self, x: Wire) -> Wire {
+        self.check_wire(x);
+        let mut round = self.rounds[x.0];
+        if !self.public[x.0] {
+            round += 1;
+        }
+        self.push_gate(Gate::Open(x), true, round)
+    }
+
+    pub fn output(&mut self, x: Wire) {
+        self.check_wire(x);
+        self.outputs.push(x);
+    }
+
+    fn push_gate(&mut self, gate: Gate, public: bool, round: usize) -> Wire {
+        self.gates.push(gate);
+        self.public.push(public);
+        self.rounds.push(round);
+        Wire(self.gates.len() - 1)
+    }
+
+    fn check_wire(&self, wire: Wire) {
+        assert!(
+            wire.0 < self.gates.len(),
+            "wire does not belong to this circuit"
+        );
+    }
+
+    fn is_interactive(&self, i: usize) -> bool {
+        match self.gates[i] {
+            Gate::Mul(x, y) => !self.public[x.0] && !self.public[y.0],
+            Gate::Open(x) => !self.public[x.0],
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Public(Scalar),
+    Shared(SharedScalar),
+}
+
+fn add_values(x: &Value, y: &Value) -> Value {
+    match (x, y) {
+        (Value::Public(a), Value::Public(b)) => Value::Public(a + b),
+        (Value::Shared(s), Value::Public(c)) | (Value::Public(c), Value::Shared(s)) => {
+            let mut sum = s.clone();
+            sum.add_constant_assign_mut(c);
+            Value::Shared(sum)
+        }
+        (Value::Shared(s), Value::Shared(t)) => {
+            let mut sum = s.clone();
+            sum.add_assign_mut(t);
+            Value::Shared(sum)
+        }
+    }
+}
+
+fn scale_value(x: &Value, scale: &Scalar) -> Value {
+    match x {
+        Value::Public(a) => Value::Public(a * scale),
+        Value::Shared(s) => {
+            let mut product = s.clone();
+            product.scale_assign_mut(scale);
+            Value::Shared(product)
+        }
+    }
+}
+
+// Evaluates a circuit for one player. Each round, all of the multiplications and openings that
+// are scheduled for that round are combined into a single batch to be opened: the masked values
+// for the Beaver multiplications (see beaver::masked_vshare_batch) followed by the shared values
+// that are to be opened.
+pub struct State {
+    circuit: Circuit,
+    values: Vec<Option<Value>>,
+    round: usize,
+}
+
+impl State {
+    pub fn new(circuit: Circuit, inputs: SharedScalarBatch) -> Result<Self, CircuitError> {
+        if inputs.len() != circuit.num_inputs() {
+            return Err(CircuitError::WrongNumberOfInputs);
+        }
+        let mut values = Vec::with_capacity(circuit.gates.len());
+        for gate in circuit.gates.iter() {
+            match gate {
+                Gate::Input(i) => values.push(inputs.get(*i).map(Value::Shared)),
+                _ => values.push(None),
+            }
+        }
+        let mut state = State {
+            circuit,
+            values,
+            round: 0,
+        };
+        state.evaluate_local_gates();
+        Ok(state)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.round == self.circuit.num_rounds()
+    }
+
+    pub fn num_multiplications(&self) -> usize {
+        self.scheduled_gates().0.len()
+    }
+
+    pub fn num_openings(&self) -> usize {
+        self.scheduled_gates().1.len()
+    }
+
+    pub fn num_triples(&self) -> usize {
+        (0..self.values.len())
+            .filter(|i| {
+                self.circuit.rounds[*i] > self.round
+                    && self.circuit.is_interactive(*i)
+                    && matches!(self.circuit.gates[*i], Gate::Mul(_, _))
+            })
+            .count()
+    }
+
+    pub fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare> {
+        let (muls, opens) = self.scheduled_gates();
+        let (xs, ys): (Vec<_>, Vec<_>) = muls
+            .iter()
+            .map(|(x, y)| (*self.shared(*x).vshare(), *self.shared(*y).vshare()))
+            .unzip();
+        let mut batch = beaver::masked_vshare_batch(&xs, &ys, triple_batch);
+        batch.extend(opens.iter().map(|x| *self.shared(*x).vshare()));
+        batch
+    }
+
+    pub fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment> {
+        let (muls, opens) = self.scheduled_gates();
+        let (xs, ys): (Vec<_>, Vec<_>) = muls
+            .iter()
+            .map(|(x, y)| {
+                (
+                    self.shared(*x).commitment().clone(),
+                    self.shared(*y).commitment().clone(),
+                )
+            })
+            .unzip();
+        let mut batch = beaver::masked_commitment_batch(&xs, &ys, triple_commitment_batch);
+        batch.extend(opens.iter().map(|x| self.shared(*x).commitment().clone()));
+        batch
+    }
+
+    pub fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    ) {
+        let m = self.num_multiplications();
+        assert_eq!(triple_batch.len(), m);
+        assert_eq!(triple_commitment_batch.len(), m);
+        assert_eq!(opened_values.len(), 2 * m + self.num_openings());
+
+        let (masked_values, opened_values) = opened_values.split_at(2 * m);
+        let products = beaver::output_vshare_batch(triple_batch, masked_values);
+        let product_commitments =
+            beaver::output_commitment_batch(triple_commitment_batch, masked_values);
+
+        let mut products = products.into_iter().zip(product_commitments.into_iter());
+        let mut opened_values = opened_values.iter();
+        for i in 0..self.values.len() {
+            if self.circuit.rounds[i] != self.round + 1 || !self.circuit.is_interactive(i) {
+                continue;
+            }
+            let value = match self.circuit.gates[i] {
+                Gate::Mul(_, _) => {
+                    let (vshare, commitment) = products.next().unwrap();
+                    Value::Shared(SharedScalar::new(vshare, commitment))
+                }
+                Gate::Open(_) => Value::Public(opened_values.next().unwrap().0),
+                _ => unreachable!(),
+            };
+            self.values[i] = Some(value);
+        }
+        self.round += 1;
+        self.evaluate_local_gates();
+    }
+
+    pub fn value(&self, wire: Wire) -> Option<&Value> {
+        self.values.get(wire.0)?.as_ref()
+    }
+
+    pub fn output(&self) -> Option<Vec<Value>> {
+        if !self.is_done() {
+            return None;
+        }
+        self.circuit
+            .outputs()
+            .iter()
+            .map(|wire| self.value(*wire).cloned())
+            .collect()
+    }
+
+    fn scheduled_gates(&self) -> (Vec<(Wire, Wire)>, Vec<Wire>) {
+        let mut muls = Vec::new();
+        let mut opens = Vec::new();
+        for (i, gate) in self.circuit.gates.iter().enumerate() {
+            if self.circuit.rounds[i] != self.round + 1 || !self.circuit.is_interactive(i) {
+                continue;
+            }
+            match gate {
+                Gate::Mul(x, y) => muls.push((*x, *y)),
+                Gate::Open(x) => opens.push(*x),
+                _ => unreachable!(),
+            }
+        }
+        (muls, opens)
+    }
+
+    fn shared(&self, wire: Wire) -> &SharedScalar {
+        match self.value(wire) {
+            Some(Value::Shared(s)) => s,
+            _ => panic!("wire should have a shared value"),
+        }
+    }
+
+    fn evaluate_local_gates(&mut self) {
+        for i in 0..self.values.len() {
+            if self.values[i].is_some()
+                || self.circuit.rounds[i] > self.round
+                || self.circuit.is_interactive(i)
+            {
+                continue;
+            }
+            let value = match &self.circuit.gates[i] {
+                Gate::Input(_) => unreachable!(),
+                Gate::Constant(c) => Value::Public(*c),
+                Gate::Add(x, y) => add_values(self.operand(*x), self.operand(*y)),
+                Gate::MulConst(x, c) => scale_value(self.operand(*x), c),
+                Gate::Mul(x, y) => match (self.operand(*x), self.operand(*y)) {
+                    (Value::Public(c), v) | (v, Value::Public(c)) => scale_value(v, c),
+                    _ => unreachable!(),
+                },
+                Gate::Open(x) => self.operand(*x).clone(),
+            };
+            self.values[i] = Some(value);
+        }
+    }
+
+    fn operand(&self, wire: Wire) -> &Value {
+        self.value(wire)
+            .expect("operands should be evaluated before their gates")
+    }
+}
+
+impl beaver::Rounds for State {
+    type Output = Vec<Value>;
+
+    fn is_done(&self) -> bool {
+        State::is_done(self)
+    }
+
+    fn num_multiplications(&self) -> usize {
+        State::num_multiplications(self)
+    }
+
+    fn num_triples(&self) -> usize {
+        State::num_triples(self)
+    }
+
+    fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare> {
+        State::masked_vshare_batch(self, triple_batch)
+    }
+
+    fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment> {
+        State::masked_commitment_batch(self, triple_commitment_batch)
+    }
+
+    fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    ) {
+        State::handle_opened_values(self, triple_batch, triple_commitment_batch, opened_values)
+    }
+
+    fn output(&self) -> Option<Vec<Value>> {
+        State::output(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use crate::util;
+    use secp256k1::group::Gej;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    fn run_states(states: &mut [State], k: usize, indices: &[Scalar], h: &Gej) {
+        let n = indices.len();
+        let mut remaining_triples = states[0].num_triples();
+        while !states[0].is_done() {
+            let m = states[0].num_multiplications();
+            assert_eq!(states[0].num_triples(), remaining_triples);
+            remaining_triples -= m;
+            // A round that only opens values does not need any triples.
+            let (triples_by_player, triple_commitments) = if m == 0 {
+                (vec![Vec::new(); n], Vec::new())
+            } else {
+                testutil::beaver_triple_batch(n, k, m, indices, h)
+            };
+            let mut masked_by_player = Vec::with_capacity(n);
+            for (state, triple_batch) in states.iter().zip(triples_by_player.iter()) {
+                masked_by_player.push(state.masked_vshare_batch(triple_batch));
+            }
+            let opened_values = testutil::open_by_player(
+                &masked_by_player,
+                states[0].masked_commitment_batch(&triple_commitments),
+                indices,
+                h,
+            );
+            for (state, triple_batch) in states.iter_mut().zip(triples_by_player.iter()) {
+                state.handle_opened_values(triple_batch, &triple_commitments, &opened_values);
+            }
+        }
+        assert_eq!(remaining_triples, 0);
+    }
+
+    #[test]
+    fn circuit_outputs_are_correct() {
+        let n = 10;
+        let k = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let three = util::scalar_from_u64(3);
+        let five = util::scalar_from_u64(5);
+
+        // o1 = open((xy + 3)z), o2 = open(5x + y), w = o2 * z.
+        let mut circuit = Circuit::new();
+        let x = circuit.input();
+        let y = circuit.input();
+        let z = circuit.input();
+        let xy = circuit.mul(x, y);
+        let c = circuit.constant(three);
+        let xy_plus_c = circuit.add(xy, c);
+        let product = circuit.mul(xy_plus_c, z);
+        let o1 = circuit.open(product);
+        let scaled = circuit.mul_const(x, five);
+        let sum = circuit.add(scaled, y);
+        let o2 = circuit.open(sum);
+        let w = circuit.mul(o2, z);
+        circuit.output(o1);
+        circuit.output(o2);
+        circuit.output(w);
+        assert_eq!(circuit.num_rounds(), 3);
+        assert!(!circuit.is_public(w));
+
+        let (input_shares_by_player, input_commitments, secrets, _) =
+            testutil::random_sharing_batch(n, k, 3, &indices, &h);
+        let mut states = Vec::with_capacity(n);
+        for input_batch in input_shares_by_player {
+            let inputs = SharedScalarBatch::new(input_batch, input_commitments.clone());
+            states.push(State::new(circuit.clone(), inputs).unwrap());
+        }
+        run_states(&mut states, k, &indices, &h);
+
+        let expected_o1 = &(&(&secrets[0] * &secrets[1]) + &three) * &secrets[2];
+        let expected_o2 = &(&secrets[0] * &five) + &secrets[1];
+        let mut w_vshares = Vec::with_capacity(n);
+        for state in states.iter() {
+            let outputs = state.output().unwrap();
+            assert_eq!(outputs[0], Value::Public(expected_o1));
+            assert_eq!(outputs[1], Value::Public(expected_o2));
+            match &outputs[2] {
+                Value::Shared(shared) => {
+                    assert!(shared.is_valid(&h));
+                    w_vshares.push(*shared.vshare());
+                }
+                _ => panic!("expected a shared output"),
+            }
+        }
+        let (w_value, _) = vss::interpolate_shares_at_zero(w_vshares.iter());
+        assert_eq!(w_value, &expected_o2 * &secrets[2]);
+    }
+
+    #[test]
+    fn rounds_without_multiplications() {
+        let n = 10;
+        let k = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        // o = open(x + y), w = o * x.
+        let mut circuit = Circuit::new();
+        let x = circuit.input();
+        let y = circuit.input();
+        let sum = circuit.add(x, y);
+        let o = circuit.open(sum);
+        let w = circuit.mul(o, x);
+        circuit.output(o);
+        circuit.output(w);
+        assert_eq!(circuit.num_rounds(), 1);
+
+        let (input_shares_by_player, input_commitments, secrets, _) =
+            testutil::random_sharing_batch(n, k, 2, &indices, &h);
+        let mut states = Vec::with_capacity(n);
+        for input_batch in input_shares_by_player {
+            let inputs = SharedScalarBatch::new(input_batch, input_commitments.clone());
+            let state = State::new(circuit.clone(), inputs).unwrap();
+            assert_eq!(state.num_multiplications(), 0);
+            assert_eq!(state.num_openings(), 1);
+            states.push(state);
+        }
+        run_states(&mut states, k, &indices, &h);
+
+        let expected_o = &secrets[0] + &secrets[1];
+        let mut w_vshares = Vec::with_capacity(n);
+        for state in states.iter() {
+            let outputs = state.output().unwrap();
+            assert_eq!(outputs[0], Value::Public(expected_o));
+            match &outputs[1] {
+                Value::Shared(shared) => {
+                    assert!(shared.is_valid(&h));
+                    w_vshares.push(*shared.vshare());
+                }
+                _ => panic!("expected a shared output"),
+            }
+        }
+        let (w_value, _) = vss::interpolate_shares_at_zero(w_vshares.iter());
+        assert_eq!(w_value, &expected_o * &secrets[0]);
+    }
+
+    #[test]
+    fn wrong_number_of_inputs_is_rejected() {
+        let mut circuit = Circuit::new();
+        let x = circuit.input();
+        circuit.output(x);
+        let inputs = SharedScalarBatch::new(Vec::new(), Vec::new());
+        assert_eq!(
+            State::new(circuit, inputs).err(),
+            Some(CircuitError::WrongNumberOfInputs)
+        );
+    }
+}
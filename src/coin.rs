@@ -0,0 +1,186 @@
+use crate::open::{self, OpenError};
+use crate::params::Parameters;
+use crate::protocol::{Blame, Outgoing, Protocol};
+use secp256k1::scalar::Scalar;
+use shamir::vss::{SharingCommitment, VShare};
+use std::collections::VecDeque;
+
+// A common coin is obtained by opening a random sharing that was generated ahead of time by rng.
+// Since no player knows the secret before it is opened, the result is unbiased, and since the
+// shares are checked against the commitment every honest player outputs the same value.
+pub struct Coin {
+    vshare: VShare,
+    inst_params: open::InstanceParams,
+    state: open::State,
+}
+
+impl Coin {
+    pub fn new(vshare: VShare, commitment: SharingCommitment) -> Self {
+        let inst_params = open::InstanceParams::new(vec![commitment]);
+        let state = open::State::new(&inst_params);
+        Coin {
+            vshare,
+            inst_params,
+            state,
+        }
+    }
+
+    // The message that the player should send to every other player (including themselves).
+    pub fn message(&self) -> VShare {
+        self.vshare
+    }
+
+    pub fn handle_vshare(
+        &mut self,
+        params: &Parameters,
+        vshare: VShare,
+    ) -> Result<Option<Scalar>, OpenError> {
+        let res = self
+            .state
+            .handle_vshare_batch(&self.inst_params, params, vec![vshare])?;
+        Ok(res.map(|values| values[0].0))
+    }
+}
+
+// Sharings from rng are produced in batches, but coins are usually needed one at a time. Coins are
+// taken from the pool in the order that they were added, so as long as all players add the same
+// batches in the same order, they will agree on which sharing is used for each coin.
+#[derive(Default)]
+pub struct Pool {
+    vshares: VecDeque<VShare>,
+    commitments: VecDeque<SharingCommitment>,
+}
+
+impl Pool {
+    pub fn new() -> Self {
+        Pool::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vshares.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vshares.is_empty()
+    }
+
+    pub fn add_batch(
+        &mut self,
+        vshare_batch: Vec<VShare>,
+        commitment_batch: Vec<SharingCommitment>,
+    ) {
+        assert_eq!(vshare_batch.len(), commitment_batch.len());
+        self.vshares.extend(vshare_batch);
+        self.commitments.extend(commitment_batch);
+    }
+
+    pub fn next_coin(&mut self) -> Option<Coin> {
+        let vshare = self.vshares.pop_front()?;
+        let commitment = self.commitments.pop_front()?;
+        Some(Coin::new(vshare, commitment))
+    }
+}
+
+pub struct Machine {
+    params: Parameters,
+    coin: Coin,
+    output: Option<Scalar>,
+}
+
+impl Machine {
+    pub fn new(params: Parameters, coin: Coin) -> Self {
+        Machine {
+            params,
+            coin,
+            output: None,
+        }
+    }
+}
+
+impl Protocol for Machine {
+    type Message = VShare;
+    type Output = Scalar;
+    type Error = OpenError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<VShare>> {
+        vec![Outgoing::broadcast(self.coin.message())]
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        vshare: VShare,
+        _outgoing: &mut Vec<Outgoing<VShare>>,
+    ) -> Result<(), Blame<OpenError>> {
+        if &vshare.share.index != from {
+            return Err(Blame::new(*from, OpenError::InconsistentIndices));
+        }
+        let res = self
+            .coin
+            .handle_vshare(&self.params, vshare)
+            .map_err(|e| Blame::new(*from, e))?;
+        if res.is_some() {
+            self.output = res;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<Scalar> {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::group::Gej;
+    use secp256k1::scalar;
+
+    #[test]
+    fn all_players_get_the_same_coins() {
+        let n = 10;
+        let k = 3;
+        let b = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        let mut pools = Vec::with_capacity(n);
+        for vshare_batch in vshares_by_player {
+            let mut pool = Pool::new();
+            pool.add_batch(vshare_batch, commitment_batch.clone());
+            pools.push(pool);
+        }
+
+        for secret in secrets.iter() {
+            let mut coins: Vec<Coin> = pools
+                .iter_mut()
+                .map(|pool| pool.next_coin().unwrap())
+                .collect();
+            let messages: Vec<VShare> = coins.iter().map(Coin::message).collect();
+            for (coin, index) in coins.iter_mut().zip(indices.iter()) {
+                let params = Parameters::new(indices.clone(), *index, h).unwrap();
+                let mut value = None;
+                for vshare in messages.iter() {
+                    if let Some(v) = coin.handle_vshare(&params, *vshare).unwrap() {
+                        value = Some(v);
+                    }
+                }
+                assert_eq!(value, Some(*secret));
+            }
+        }
+        assert!(pools.iter().all(Pool::is_empty));
+        assert!(pools[0].next_coin().is_none());
+    }
+}
@@ -0,0 +1,582 @@
+use crate::beaver::{self, Triple, TripleCommitment};
+use crate::shared::{self, Linear};
+use crate::util;
+use secp256k1::scalar::Scalar;
+use shamir::vss::{SharingCommitment, VShare};
+
+// Comparisons are computed for values in the range [0, 2^l). A value is masked by a random value
+// with STATISTICAL_SECURITY more bits than the value being masked, which is then opened. The
+// comparison then reduces to a bitwise comparison of the public opened value with the shared
+// random bits, which is computed in a logarithmic number of rounds of Beaver multiplications.
+pub const STATISTICAL_SECURITY: usize = 40;
+pub const MAX_BIT_LENGTH: usize = 200;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum CompareError {
+    InvalidBitLength,
+    WrongNumberOfRandomBits,
+}
+
+pub fn num_random_bits_eqz(l: usize) -> usize {
+    l + STATISTICAL_SECURITY
+}
+
+pub fn num_random_bits_lt(l: usize) -> usize {
+    l + 1 + STATISTICAL_SECURITY
+}
+
+fn check_inputs(l: usize, num_random_bits: usize, expected: usize) -> Result<(), CompareError> {
+    if l == 0 || l > MAX_BIT_LENGTH {
+        return Err(CompareError::InvalidBitLength);
+    }
+    if num_random_bits != expected {
+        return Err(CompareError::WrongNumberOfRandomBits);
+    }
+    Ok(())
+}
+
+fn weighted_bit_sum<T: Linear>(bits: &[T]) -> T {
+    let mut sum = bits[0].clone();
+    for (i, bit) in bits.iter().enumerate().skip(1) {
+        let mut term = bit.clone();
+        term.scale_assign_mut(&util::scalar_pow_two(i));
+        sum.add_assign_mut(&term);
+    }
+    sum
+}
+
+fn low_bits_value(bits: &[bool], l: usize) -> Scalar {
+    let mut value = Scalar::zero();
+    for (i, _) in bits.iter().enumerate().take(l).filter(|(_, bit)| **bit) {
+        value = value + util::scalar_pow_two(i);
+    }
+    value
+}
+
+pub fn eqz_masked_vshare(
+    x_vshare: &VShare,
+    random_bit_vshares: &[VShare],
+    l: usize,
+) -> Result<VShare, CompareError> {
+    eqz_masked(x_vshare, random_bit_vshares, l)
+}
+
+pub fn eqz_masked_commitment(
+    x_commitment: &SharingCommitment,
+    random_bit_commitments: &[SharingCommitment],
+    l: usize,
+) -> Result<SharingCommitment, CompareError> {
+    eqz_masked(x_commitment, random_bit_commitments, l)
+}
+
+fn eqz_masked<T: Linear>(x: &T, random_bits: &[T], l: usize) -> Result<T, CompareError> {
+    check_inputs(l, random_bits.len(), num_random_bits_eqz(l))?;
+    let mut masked = weighted_bit_sum(random_bits);
+    masked.add_assign_mut(x);
+    Ok(masked)
+}
+
+pub fn eqz_state(
+    opened_value: &Scalar,
+    random_bit_vshares: &[VShare],
+    random_bit_commitments: &[SharingCommitment],
+    l: usize,
+) -> Result<BitwiseState, CompareError> {
+    check_inputs(l, random_bit_vshares.len(), num_random_bits_eqz(l))?;
+    check_inputs(l, random_bit_commitments.len(), num_random_bits_eqz(l))?;
+    Ok(BitwiseState::new(
+        Comparison::Equal,
+        opened_value,
+        &random_bit_vshares[..l],
+        &random_bit_commitments[..l],
+    ))
+}
+
+// For x, y in [0, 2^l), the value a = x - y + 2^l is in [0, 2^(l+1)) and x < y exactly when bit l
+// of a is zero.
+pub fn lt_masked_vshare(
+    x_vshare: &VShare,
+    y_vshare: &VShare,
+    random_bit_vshares: &[VShare],
+    l: usize,
+) -> Result<VShare, CompareError> {
+    lt_masked(x_vshare, y_vshare, random_bit_vshares, l)
+}
+
+pub fn lt_masked_commitment(
+    x_commitment: &SharingCommitment,
+    y_commitment: &SharingCommitment,
+    random_bit_commitments: &[SharingCommitment],
+    l: usize,
+) -> Result<SharingCommitment, CompareError> {
+    lt_masked(x_commitment, y_commitment, random_bit_commitments, l)
+}
+
+fn lt_masked<T: Linear>(x: &T, y: &T, random_bits: &[T], l: usize) -> Result<T, CompareError> {
+    check_inputs(l, random_bits.len(), num_random_bits_lt(l))?;
+    let mut masked = weighted_bit_sum(random_bits);
+    masked.add_assign_mut(x);
+    masked.add_assign_mut(&shared::negated(y));
+    masked.add_constant_assign_mut(&util::scalar_pow_two(l));
+    Ok(masked)
+}
+
+pub fn bit_lt_state(
+    opened_value: &Scalar,
+    random_bit_vshares: &[VShare],
+    random_bit_commitments: &[SharingCommitment],
+    l: usize,
+) -> Result<BitwiseState, CompareError> {
+    check_inputs(l, random_bit_vshares.len(), num_random_bits_lt(l))?;
+    check_inputs(l, random_bit_commitments.len(), num_random_bits_lt(l))?;
+    Ok(BitwiseState::new(
+        Comparison::LessThan,
+        opened_value,
+        &random_bit_vshares[..l],
+        &random_bit_commitments[..l],
+    ))
+}
+
+// If c is the opened value, c' = c mod 2^l, r' = r mod 2^l and u = [c' < r'], then
+// a mod 2^l = c' - r' + 2^l u and so x < y is 1 - (a - (a mod 2^l)) / 2^l.
+pub fn lt_output_vshare(
+    x_vshare: &VShare,
+    y_vshare: &VShare,
+    random_bit_vshares: &[VShare],
+    opened_value: &Scalar,
+    bit_lt_vshare: &VShare,
+    l: usize,
+) -> VShare {
+    lt_output(
+        x_vshare,
+        y_vshare,
+        random_bit_vshares,
+        opened_value,
+        bit_lt_vshare,
+        l,
+    )
+}
+
+pub fn lt_output_commitment(
+    x_commitment: &SharingCommitment,
+    y_commitment: &SharingCommitment,
+    random_bit_commitments: &[SharingCommitment],
+    opened_value: &Scalar,
+    bit_lt_commitment: &SharingCommitment,
+    l: usize,
+) -> SharingCommitment {
+    lt_output(
+        x_commitment,
+        y_commitment,
+        random_bit_commitments,
+        opened_value,
+        bit_lt_commitment,
+        l,
+    )
+}
+
+fn lt_output<T: Linear>(
+    x: &T,
+    y: &T,
+    random_bits: &[T],
+    opened_value: &Scalar,
+    bit_lt: &T,
+    l: usize,
+) -> T {
+    let c_low = low_bits_value(&util::scalar_to_bits_le(opened_value), l);
+    let mut output = weighted_bit_sum(&random_bits[..l]);
+    output.add_assign_mut(x);
+    output.add_assign_mut(&shared::negated(y));
+    output.add_constant_assign_mut(&(util::scalar_pow_two(l) - c_low));
+    output.scale_assign_mut(&neg_inv_pow_two(l));
+    output.add_assign_mut(bit_lt);
+    output.add_constant_assign_mut(&util::scalar_from_u64(1));
+    output
+}
+
+fn neg_inv_pow_two(l: usize) -> Scalar {
+    let mut scale = util::scalar_pow_two(l);
+    scale.inverse_assign();
+    scale.negate_assign_mut();
+    scale
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Comparison {
+    Equal,
+    LessThan,
+}
+
+// Compares a public value c with shared bits r, least significant bit first. Each bit position
+// is a node (g, p), where g is whether position generates a borrow in the subtraction c - r and p
+// is whether the position propagates a borrow, i.e. whether c and r agree. Adjacent nodes are
+// combined as (g_h + p_h g_l, p_h p_l) until one node remains; the final g is then [c < r] and the
+// final p is [c = r]. Only the p values are tracked for an equality comparison.
+#[derive(Clone)]
+pub struct BitwiseState {
+    comparison: Comparison,
+    g_vshares: Vec<VShare>,
+    g_commitments: Vec<SharingCommitment>,
+    p_vshares: Vec<VShare>,
+    p_commitments: Vec<SharingCommitment>,
+}
+
+impl BitwiseState {
+    fn new(
+        comparison: Comparison,
+        opened_value: &Scalar,
+        bit_vshares: &[VShare],
+        bit_commitments: &[SharingCommitment],
+    ) -> Self {
+        let (g_vshares, p_vshares) = bit_nodes(comparison, opened_value, bit_vshares);
+        let (g_commitments, p_commitments) = bit_nodes(comparison, opened_value, bit_commitments);
+        BitwiseState {
+            comparison,
+            g_vshares,
+            g_commitments,
+            p_vshares,
+            p_commitments,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.p_vshares.len() == 1
+    }
+
+    pub fn num_multiplications(&self) -> usize {
+        let pairs = self.p_vshares.len() / 2;
+        match self.comparison {
+            Comparison::Equal => pairs,
+            Comparison::LessThan => 2 * pairs,
+        }
+    }
+
+    // Each round combines the nodes in pairs, so the number of nodes is halved, rounding up.
+    pub fn num_triples(&self) -> usize {
+        let per_pair = match self.comparison {
+            Comparison::Equal => 1,
+            Comparison::LessThan => 2,
+        };
+        let mut nodes = self.p_vshares.len();
+        let mut triples = 0;
+        while nodes > 1 {
+            triples += per_pair * (nodes / 2);
+            nodes -= nodes / 2;
+        }
+        triples
+    }
+
+    pub fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare> {
+        let (xs, ys) = pair_operands(&self.g_vshares, &self.p_vshares, self.comparison);
+        beaver::masked_vshare_batch(&xs, &ys, triple_batch)
+    }
+
+    pub fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment> {
+        let (xs, ys) = pair_operands(&self.g_commitments, &self.p_commitments, self.comparison);
+        beaver::masked_commitment_batch(&xs, &ys, triple_commitment_batch)
+    }
+
+    pub fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    ) {
+        let products = beaver::output_vshare_batch(triple_batch, opened_values);
+        let (g_vshares, p_vshares) =
+            combine_pairs(&self.g_vshares, &self.p_vshares, products, self.comparison);
+        self.g_vshares = g_vshares;
+        self.p_vshares = p_vshares;
+
+        let products = beaver::output_commitment_batch(triple_commitment_batch, opened_values);
+        let (g_commitments, p_commitments) = combine_pairs(
+            &self.g_commitments,
+            &self.p_commitments,
+            products,
+            self.comparison,
+        );
+        self.g_commitments = g_commitments;
+        self.p_commitments = p_commitments;
+    }
+
+    pub fn output(&self) -> Option<(VShare, SharingCommitment)> {
+        if !self.is_done() {
+            return None;
+        }
+        match self.comparison {
+            Comparison::Equal => Some((self.p_vshares[0], self.p_commitments[0].clone())),
+            Comparison::LessThan => Some((self.g_vshares[0], self.g_commitments[0].clone())),
+        }
+    }
+}
+
+impl beaver::Rounds for BitwiseState {
+    type Output = (VShare, SharingCommitment);
+
+    fn is_done(&self) -> bool {
+        BitwiseState::is_done(self)
+    }
+
+    fn num_multiplications(&self) -> usize {
+        BitwiseState::num_multiplications(self)
+    }
+
+    fn num_triples(&self) -> usize {
+        BitwiseState::num_triples(self)
+    }
+
+    fn masked_vshare_batch(&self, triple_batch: &[Triple]) -> Vec<VShare> {
+        BitwiseState::masked_vshare_batch(self, triple_batch)
+    }
+
+    fn masked_commitment_batch(
+        &self,
+        triple_commitment_batch: &[TripleCommitment],
+    ) -> Vec<SharingCommitment> {
+        BitwiseState::masked_commitment_batch(self, triple_commitment_batch)
+    }
+
+    fn handle_opened_values(
+        &mut self,
+        triple_batch: &[Triple],
+        triple_commitment_batch: &[TripleCommitment],
+        opened_values: &[(Scalar, Scalar)],
+    ) {
+        BitwiseState::handle_opened_values(
+            self,
+            triple_batch,
+            triple_commitment_batch,
+            opened_values,
+        )
+    }
+
+    fn output(&self) -> Option<(VShare, SharingCommitment)> {
+        BitwiseState::output(self)
+    }
+}
+
+// The initial nodes for each bit r_i: g = (1 - c_i) r_i and p = 1 - (c_i xor r_i). The g nodes are
+// only needed for a less than comparison.
+fn bit_nodes<T: Linear>(
+    comparison: Comparison,
+    opened_value: &Scalar,
+    bits: &[T],
+) -> (Vec<T>, Vec<T>) {
+    let l = bits.len();
+    let one = util::scalar_from_u64(1);
+    let c_bits = util::scalar_to_bits_le(opened_value);
+    let mut g_nodes = Vec::with_capacity(l);
+    let mut p_nodes = Vec::with_capacity(l);
+    for (c_bit, bit) in c_bits.iter().zip(bits.iter()) {
+        let mut g_node = bit.clone();
+        let mut p_node = bit.clone();
+        if *c_bit {
+            g_node.scale_assign_mut(&Scalar::zero());
+        } else {
+            p_node.negate_assign_mut();
+            p_node.add_constant_assign_mut(&one);
+        }
+        if comparison == Comparison::LessThan {
+            g_nodes.push(g_node);
+        }
+        p_nodes.push(p_node);
+    }
+    (g_nodes, p_nodes)
+}
+
+fn pair_operands<T: Clone>(g: &[T], p: &[T], comparison: Comparison) -> (Vec<T>, Vec<T>) {
+    let pairs = p.len() / 2;
+    let mut xs = Vec::with_capacity(2 * pairs);
+    let mut ys = Vec::with_capacity(2 * pairs);
+    if comparison == Comparison::LessThan {
+        for j in 0..pairs {
+            xs.push(p[2 * j + 1].clone());
+            ys.push(g[2 * j].clone());
+        }
+    }
+    for j in 0..pairs {
+        xs.push(p[2 * j + 1].clone());
+        ys.push(p[2 * j].clone());
+    }
+    (xs, ys)
+}
+
+fn combine_pairs<T: Linear>(
+    g: &[T],
+    p: &[T],
+    products: Vec<T>,
+    comparison: Comparison,
+) -> (Vec<T>, Vec<T>) {
+    let pairs = p.len() / 2;
+    let (pg_products, pp_products) = match comparison {
+        Comparison::Equal => (&products[..0], &products[..]),
+        Comparison::LessThan => products.split_at(pairs),
+    };
+    let mut new_g = Vec::with_capacity(pairs + 1);
+    let mut new_p = Vec::with_capacity(pairs + 1);
+    for j in 0..pairs {
+        if comparison == Comparison::LessThan {
+            let mut g_node = g[2 * j + 1].clone();
+            g_node.add_assign_mut(&pg_products[j]);
+            new_g.push(g_node);
+        }
+        new_p.push(pp_products[j].clone());
+    }
+    if p.len() % 2 == 1 {
+        if comparison == Comparison::LessThan {
+            new_g.push(g[p.len() - 1].clone());
+        }
+        new_p.push(p[p.len() - 1].clone());
+    }
+    (new_g, new_p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::group::Gej;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    fn run_bitwise_states(states: &mut [BitwiseState], k: usize, indices: &[Scalar], h: &Gej) {
+        let n = indices.len();
+        let mut remaining_triples = states[0].num_triples();
+        while !states[0].is_done() {
+            let m = states[0].num_multiplications();
+            assert_eq!(states[0].num_triples(), remaining_triples);
+            remaining_triples -= m;
+            let (triples_by_player, triple_commitments) =
+                testutil::beaver_triple_batch(n, k, m, indices, h);
+            let mut masked_by_player = Vec::with_capacity(n);
+            for (state, triple_batch) in states.iter().zip(triples_by_player.iter()) {
+                masked_by_player.push(state.masked_vshare_batch(triple_batch));
+            }
+            let opened_values = testutil::open_by_player(
+                &masked_by_player,
+                states[0].masked_commitment_batch(&triple_commitments),
+                indices,
+                h,
+            );
+            for (state, triple_batch) in states.iter_mut().zip(triples_by_player.iter()) {
+                state.handle_opened_values(triple_batch, &triple_commitments, &opened_values);
+            }
+        }
+        assert_eq!(remaining_triples, 0);
+    }
+
+    fn check_output(states: &[BitwiseState], h: &Gej) -> Scalar {
+        let mut vshares = Vec::with_capacity(states.len());
+        for state in states {
+            let (vshare, commitment) = state.output().unwrap();
+            assert!(vss::vshare_is_valid(&vshare, &commitment, h));
+            vshares.push(vshare);
+        }
+        vss::interpolate_shares_at_zero(vshares.iter()).0
+    }
+
+    #[test]
+    fn equality_to_zero() {
+        let n = 10;
+        let k = 3;
+        let l = 16;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        for (x, expected) in [(0, 1), (1, 0), (12345, 0), (65535, 0)].iter() {
+            let (x_vshares, x_commitment) =
+                vss::vshare_secret(&h, &indices, &util::scalar_from_u64(*x), k);
+            let (bits_by_player, bit_commitments, _) =
+                testutil::random_bit_sharing_batch(k, num_random_bits_eqz(l), &indices, &h);
+
+            let mut masked_by_player = Vec::with_capacity(n);
+            for (x_vshare, bits) in x_vshares.iter().zip(bits_by_player.iter()) {
+                masked_by_player.push(vec![eqz_masked_vshare(x_vshare, bits, l).unwrap()]);
+            }
+            let masked_commitment = eqz_masked_commitment(&x_commitment, &bit_commitments, l);
+            let opened_values = testutil::open_by_player(
+                &masked_by_player,
+                vec![masked_commitment.unwrap()],
+                &indices,
+                &h,
+            );
+
+            let mut states = Vec::with_capacity(n);
+            for bits in bits_by_player.iter() {
+                states.push(eqz_state(&opened_values[0].0, bits, &bit_commitments, l).unwrap());
+            }
+            run_bitwise_states(&mut states, k, &indices, &h);
+            assert_eq!(check_output(&states, &h), util::scalar_from_u64(*expected));
+        }
+    }
+
+    #[test]
+    fn less_than() {
+        let n = 10;
+        let k = 3;
+        let l = 16;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        for (x, y, expected) in [(3, 5, 1), (5, 3, 0), (7, 7, 0), (0, 65535, 1)].iter() {
+            let (x_vshares, x_commitment) =
+                vss::vshare_secret(&h, &indices, &util::scalar_from_u64(*x), k);
+            let (y_vshares, y_commitment) =
+                vss::vshare_secret(&h, &indices, &util::scalar_from_u64(*y), k);
+            let (bits_by_player, bit_commitments, _) =
+                testutil::random_bit_sharing_batch(k, num_random_bits_lt(l), &indices, &h);
+
+            let mut masked_by_player = Vec::with_capacity(n);
+            for ((x_vshare, y_vshare), bits) in x_vshares
+                .iter()
+                .zip(y_vshares.iter())
+                .zip(bits_by_player.iter())
+            {
+                masked_by_player.push(vec![lt_masked_vshare(x_vshare, y_vshare, bits, l).unwrap()]);
+            }
+            let masked_commitment =
+                lt_masked_commitment(&x_commitment, &y_commitment, &bit_commitments, l);
+            let opened_values = testutil::open_by_player(
+                &masked_by_player,
+                vec![masked_commitment.unwrap()],
+                &indices,
+                &h,
+            );
+            let c = opened_values[0].0;
+
+            let mut states = Vec::with_capacity(n);
+            for bits in bits_by_player.iter() {
+                states.push(bit_lt_state(&c, bits, &bit_commitments, l).unwrap());
+            }
+            run_bitwise_states(&mut states, k, &indices, &h);
+
+            let mut outputs = Vec::with_capacity(n);
+            for (((x_vshare, y_vshare), bits), state) in x_vshares
+                .iter()
+                .zip(y_vshares.iter())
+                .zip(bits_by_player.iter())
+                .zip(states.iter())
+            {
+                let (u_vshare, u_commitment) = state.output().unwrap();
+                let output = lt_output_vshare(x_vshare, y_vshare, bits, &c, &u_vshare, l);
+                let commitment = lt_output_commitment(
+                    &x_commitment,
+                    &y_commitment,
+                    &bit_commitments,
+                    &c,
+                    &u_commitment,
+                    l,
+                );
+                assert!(vss::vshare_is_valid(&output, &commitment, &h));
+                outputs.push(output);
+            }
+            let (lt, _) = vss::interpolate_shares_at_zero(outputs.iter());
+            assert_eq!(lt, util::scalar_from_u64(*expected));
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::shared::Linear;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use sha2::{Digest, Sha512};
+use shamir::vss::{SharingCommitment, VShare};
+
+pub const HARDENED_OFFSET: u32 = 1 << 31;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum DeriveError {
+    HardenedIndex,
+    InvalidTweak,
+}
+
+// Non-hardened BIP32 child key derivation. The child public key is g^t y for a tweak t that
+// depends only on the parent public key, the chain code and the child index, so it can be computed
+// by anyone. Since the child secret key is s + t, each player can get a share of it by adding the
+// tweak to their share of s, and no interaction is needed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtendedPublicKey {
+    pub pub_key: Gej,
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedPublicKey {
+    pub fn new(pub_key: Gej, chain_code: [u8; 32]) -> Self {
+        ExtendedPublicKey {
+            pub_key,
+            chain_code,
+        }
+    }
+
+    // Returns the child key together with the tweak that needs to be added to the shares of the
+    // parent secret key.
+    pub fn derive_child(&self, i: u32) -> Result<(ExtendedPublicKey, Scalar), DeriveError> {
+        if i >= HARDENED_OFFSET {
+            return Err(DeriveError::HardenedIndex);
+        }
+        let mut data = [0_u8; 37];
+        self.pub_key.put_bytes(&mut data);
+        data[33..].copy_from_slice(&i.to_be_bytes());
+        let hash = hmac_sha512(&self.chain_code, &data);
+
+        let mut tweak_bytes = [0_u8; 32];
+        tweak_bytes.copy_from_slice(&hash[..32]);
+        let mut tweak = Scalar::default();
+        tweak.set_b32(&tweak_bytes);
+        // The tweak is invalid if it is not less than the group order, which is detected by the
+        // value having been reduced.
+        let mut reduced = [0_u8; 32];
+        tweak.put_b32(&mut reduced);
+        if reduced != tweak_bytes {
+            return Err(DeriveError::InvalidTweak);
+        }
+
+        let mut pub_key = Gej::default();
+        pub_key.scalar_base_mul(&tweak);
+        pub_key.add_assign(&self.pub_key);
+        if pub_key.is_infinity() {
+            return Err(DeriveError::InvalidTweak);
+        }
+        let mut chain_code = [0_u8; 32];
+        chain_code.copy_from_slice(&hash[32..]);
+        Ok((ExtendedPublicKey::new(pub_key, chain_code), tweak))
+    }
+
+    // Derives along a path of child indices, returning the sum of the tweaks.
+    pub fn derive_path(&self, path: &[u32]) -> Result<(ExtendedPublicKey, Scalar), DeriveError> {
+        let mut key = self.clone();
+        let mut tweak = Scalar::zero();
+        for i in path {
+            let (child, child_tweak) = key.derive_child(*i)?;
+            key = child;
+            tweak = &tweak + &child_tweak;
+        }
+        Ok((key, tweak))
+    }
+}
+
+pub fn tweak_vshare(vshare: &mut VShare, tweak: &Scalar) {
+    apply_tweak(vshare, tweak);
+}
+
+pub fn tweak_commitment(commitment: &mut SharingCommitment, tweak: &Scalar) {
+    apply_tweak(commitment, tweak);
+}
+
+// A child key is the parent key plus the tweak, so the shares and the commitment of the child key
+// are those of the parent key with the tweak added as a constant.
+pub fn apply_tweak<T: Linear>(shared: &mut T, tweak: &Scalar) {
+    shared.add_constant_assign_mut(tweak);
+}
+
+const SHA512_BLOCK_SIZE: usize = 128;
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    assert!(key.len() <= SHA512_BLOCK_SIZE);
+    let mut inner_pad = [0x36_u8; SHA512_BLOCK_SIZE];
+    let mut outer_pad = [0x5c_u8; SHA512_BLOCK_SIZE];
+    for (i, byte) in key.iter().enumerate() {
+        inner_pad[i] ^= byte;
+        outer_pad[i] ^= byte;
+    }
+
+    let mut hasher = Sha512::new();
+    hasher.update(&inner_pad[..]);
+    hasher.update(data);
+    let inner = hasher.finalize();
+
+    let mut hasher = Sha512::new();
+    hasher.update(&outer_pad[..]);
+    hasher.update(inner.as_slice());
+    let mut out = [0_u8; 64];
+    out.copy_from_slice(hasher.finalize().as_slice());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    #[test]
+    fn hmac_sha512_matches_rfc4231() {
+        // Test case 2 from RFC 4231.
+        let out = hmac_sha512(b"Jefe", b"what do ya want for nothing?");
+        let expected = [
+            0x16, 0x4b, 0x7a, 0x7b, 0xfc, 0xf8, 0x19, 0xe2, 0xe3, 0x95, 0xfb, 0xe7, 0x3b, 0x56,
+            0xe0, 0xa3, 0x87, 0xbd, 0x64, 0x22, 0x2e, 0x83, 0x1f, 0xd6, 0x10, 0x27, 0x0c, 0xd7,
+            0xea, 0x25, 0x05, 0x54, 0x97, 0x58, 0xbf, 0x75, 0xc0, 0x5a, 0x99, 0x4a, 0x6d, 0x03,
+            0x4f, 0x65, 0xf8, 0xf0, 0xe6, 0xfd, 0xca, 0xea, 0xb1, 0xa3, 0x4d, 0x4a, 0x6b, 0x4b,
+            0x63, 0x6e, 0x07, 0x0a, 0x38, 0xbc, 0xe7, 0x37,
+        ];
+        assert_eq!(out[..], expected[..]);
+    }
+
+    fn extended_key(pub_key: [u8; 33], chain_code: [u8; 32]) -> ExtendedPublicKey {
+        let mut key = Gej::default();
+        assert!(key.set_bytes(&pub_key).is_ok());
+        ExtendedPublicKey::new(key, chain_code)
+    }
+
+    #[test]
+    fn public_derivation_matches_bip32_test_vector_1() {
+        // The public keys and chain codes of test vector 1 from BIP32, for the chains that have a
+        // non-hardened last step.
+        let m_0h = extended_key(
+            [
+                0x03, 0x5a, 0x78, 0x46, 0x62, 0xa4, 0xa2, 0x0a, 0x65, 0xbf, 0x6a, 0xab, 0x9a, 0xe9,
+                0x8a, 0x6c, 0x06, 0x8a, 0x81, 0xc5, 0x2e, 0x4b, 0x03, 0x2c, 0x0f, 0xb5, 0x40, 0x0c,
+                0x70, 0x6c, 0xfc, 0xcc, 0x56,
+            ],
+            [
+                0x47, 0xfd, 0xac, 0xbd, 0x0f, 0x10, 0x97, 0x04, 0x3b, 0x78, 0xc6, 0x3c, 0x20, 0xc3,
+                0x4e, 0xf4, 0xed, 0x9a, 0x11, 0x1d, 0x98, 0x00, 0x47, 0xad, 0x16, 0x28, 0x2c, 0x7a,
+                0xe6, 0x23, 0x61, 0x41,
+            ],
+        );
+        let m_0h_1 = extended_key(
+            [
+                0x03, 0x50, 0x1e, 0x45, 0x4b, 0xf0, 0x07, 0x51, 0xf2, 0x4b, 0x1b, 0x48, 0x9a, 0xa9,
+                0x25, 0x21, 0x5d, 0x66, 0xaf, 0x22, 0x34, 0xe3, 0x89, 0x1c, 0x3b, 0x21, 0xa5, 0x2b,
+                0xed, 0xb3, 0xcd, 0x71, 0x1c,
+            ],
+            [
+                0x2a, 0x78, 0x57, 0x63, 0x13, 0x86, 0xba, 0x23, 0xda, 0xca, 0xc3, 0x41, 0x80, 0xdd,
+                0x19, 0x83, 0x73, 0x4e, 0x44, 0x4f, 0xdb, 0xf7, 0x74, 0x04, 0x15, 0x78, 0xe9, 0xb6,
+                0xad, 0xb3, 0x7c, 0x19,
+            ],
+        );
+        let m_0h_1_2h = extended_key(
+            [
+                0x03, 0x57, 0xbf, 0xe1, 0xe3, 0x41, 0xd0, 0x1c, 0x69, 0xfe, 0x56, 0x54, 0x30, 0x99,
+                0x56, 0xcb, 0xea, 0x51, 0x68, 0x22, 0xfb, 0xa8, 0xa6, 0x01, 0x74, 0x3a, 0x01, 0x2a,
+                0x78, 0x96, 0xee, 0x8d, 0xc2,
+            ],
+            [
+                0x04, 0x46, 0x6b, 0x9c, 0xc8, 0xe1, 0x61, 0xe9, 0x66, 0x40, 0x9c, 0xa5, 0x29, 0x86,
+                0xc5, 0x84, 0xf0, 0x7e, 0x9d, 0xc8, 0x1f, 0x73, 0x5d, 0xb6, 0x83, 0xc3, 0xff, 0x6e,
+                0xc7, 0xb1, 0x50, 0x3f,
+            ],
+        );
+        let m_0h_1_2h_2 = extended_key(
+            [
+                0x02, 0xe8, 0x44, 0x50, 0x82, 0xa7, 0x2f, 0x29, 0xb7, 0x5c, 0xa4, 0x87, 0x48, 0xa9,
+                0x14, 0xdf, 0x60, 0x62, 0x2a, 0x60, 0x9c, 0xac, 0xfc, 0xe8, 0xed, 0x0e, 0x35, 0x80,
+                0x45, 0x60, 0x74, 0x1d, 0x29,
+            ],
+            [
+                0xcf, 0xb7, 0x18, 0x83, 0xf0, 0x16, 0x76, 0xf5, 0x87, 0xd0, 0x23, 0xcc, 0x53, 0xa3,
+                0x5b, 0xc7, 0xf8, 0x8f, 0x72, 0x4b, 0x1f, 0x8c, 0x28, 0x92, 0xac, 0x12, 0x75, 0xac,
+                0x82, 0x2a, 0x3e, 0xdd,
+            ],
+        );
+        let m_0h_1_2h_2_1000000000 = extended_key(
+            [
+                0x02, 0x2a, 0x47, 0x14, 0x24, 0xda, 0x5e, 0x65, 0x74, 0x99, 0xd1, 0xff, 0x51, 0xcb,
+                0x43, 0xc4, 0x74, 0x81, 0xa0, 0x3b, 0x1e, 0x77, 0xf9, 0x51, 0xfe, 0x64, 0xce, 0xc9,
+                0xf5, 0xa4, 0x8f, 0x70, 0x11,
+            ],
+            [
+                0xc7, 0x83, 0xe6, 0x7b, 0x92, 0x1d, 0x2b, 0xeb, 0x8f, 0x6b, 0x38, 0x9c, 0xc6, 0x46,
+                0xd7, 0x26, 0x3b, 0x41, 0x45, 0x70, 0x1d, 0xad, 0xd2, 0x16, 0x15, 0x48, 0xa8, 0xb0,
+                0x78, 0xe6, 0x5e, 0x9e,
+            ],
+        );
+
+        let (child, tweak) = m_0h.derive_child(1).unwrap();
+        assert_eq!(child, m_0h_1);
+        let mut expected_tweak = Scalar::default();
+        expected_tweak.set_b32(&[
+            0x4e, 0xb9, 0xd7, 0x81, 0x57, 0xba, 0xe7, 0xa2, 0x41, 0x15, 0x00, 0x16, 0x21, 0xc4,
+            0xd9, 0x1e, 0x3a, 0x31, 0x10, 0xe1, 0x1e, 0x14, 0x3c, 0x52, 0x59, 0xea, 0xa4, 0xe5,
+            0x5c, 0x5e, 0xc4, 0xbf,
+        ]);
+        assert_eq!(tweak, expected_tweak);
+
+        assert_eq!(m_0h_1_2h.derive_child(2).unwrap().0, m_0h_1_2h_2);
+        assert_eq!(
+            m_0h_1_2h_2.derive_child(1_000_000_000).unwrap().0,
+            m_0h_1_2h_2_1000000000
+        );
+        assert_eq!(
+            m_0h_1_2h.derive_path(&[2, 1_000_000_000]).unwrap().0,
+            m_0h_1_2h_2_1000000000
+        );
+    }
+
+    #[test]
+    fn tweaked_shares_are_shares_of_the_child_key() {
+        let n = 10;
+        let k = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitments, secrets, _) =
+            testutil::random_sharing_batch(n, k, 1, &indices, &h);
+        let mut pub_key = Gej::default();
+        pub_key.scalar_base_mul(&secrets[0]);
+
+        let master = ExtendedPublicKey::new(pub_key, [7_u8; 32]);
+        let (child, tweak) = master.derive_path(&[0, 1, 42]).unwrap();
+        let (step, _) = master.derive_child(0).unwrap();
+        let (step, _) = step.derive_child(1).unwrap();
+        let (step, _) = step.derive_child(42).unwrap();
+        assert_eq!(step, child);
+
+        let mut commitment = commitments[0].clone();
+        tweak_commitment(&mut commitment, &tweak);
+        let mut child_vshares = Vec::with_capacity(n);
+        for vshare_batch in vshares_by_player {
+            let mut vshare = vshare_batch[0];
+            tweak_vshare(&mut vshare, &tweak);
+            assert!(vss::vshare_is_valid(&vshare, &commitment, &h));
+            child_vshares.push(vshare);
+        }
+        let (child_secret, _) = vss::interpolate_shares_at_zero(child_vshares.iter());
+        let mut expected = Gej::default();
+        expected.scalar_base_mul(&child_secret);
+        assert_eq!(child.pub_key, expected);
+
+        assert_eq!(
+            master.derive_child(HARDENED_OFFSET),
+            Err(DeriveError::HardenedIndex)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use sha2::{Digest, Sha256};
+use shamir::ped;
+
+// A non-interactive proof that the discrete log of a value with respect to some base is equal to
+// the secret in a Pedersen commitment, i.e. that value = base^s for commitment = g^s h^t. The
+// commitment will usually be a sharing commitment evaluated at a player's index, so that the value
+// can be checked against the player's share without the share itself being revealed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proof {
+    a1: Gej,
+    a2: Gej,
+    z1: Scalar,
+    z2: Scalar,
+}
+
+pub fn prove(base: &Gej, secret: &Scalar, decommitment: &Scalar, h: &Gej) -> Proof {
+    let commitment = ped::ped_commit(h, secret, decommitment);
+    let mut value = Gej::default();
+    value.scalar_mul(base, secret);
+
+    let a = Scalar::new_random_using_thread_rng();
+    let b = Scalar::new_random_using_thread_rng();
+    let a1 = ped::ped_commit(h, &a, &b);
+    let mut a2 = Gej::default();
+    a2.scalar_mul(base, &a);
+
+    let e = compute_challenge(h, base, &commitment, &value, &a1, &a2);
+    Proof {
+        a1,
+        a2,
+        z1: &a + &(&e * secret),
+        z2: &b + &(&e * decommitment),
+    }
+}
+
+pub fn verify(proof: &Proof, base: &Gej, commitment: &Gej, value: &Gej, h: &Gej) -> bool {
+    let e = compute_challenge(h, base, commitment, value, &proof.a1, &proof.a2);
+
+    let lhs = ped::ped_commit(h, &proof.z1, &proof.z2);
+    let mut rhs = Gej::default();
+    rhs.scalar_mul(commitment, &e);
+    rhs.add_assign(&proof.a1);
+    if lhs != rhs {
+        return false;
+    }
+
+    let mut lhs = Gej::default();
+    lhs.scalar_mul(base, &proof.z1);
+    let mut rhs = Gej::default();
+    rhs.scalar_mul(value, &e);
+    rhs.add_assign(&proof.a2);
+    lhs == rhs
+}
+
+fn compute_challenge(
+    h: &Gej,
+    base: &Gej,
+    commitment: &Gej,
+    value: &Gej,
+    a1: &Gej,
+    a2: &Gej,
+) -> Scalar {
+    let mut challenge = Scalar::default();
+    let mut hasher = Sha256::new();
+    let mut bs = [0_u8; 198];
+    h.put_bytes(&mut bs);
+    base.put_bytes(&mut bs[33..]);
+    commitment.put_bytes(&mut bs[66..]);
+    value.put_bytes(&mut bs[99..]);
+    a1.put_bytes(&mut bs[132..]);
+    a2.put_bytes(&mut bs[165..]);
+    hasher.update(&bs);
+    let hash = hasher.finalize();
+    challenge.set_b32(hash.as_slice());
+    challenge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn honest_proofs_verify_and_modified_values_do_not() {
+        let h = Gej::new_random_using_thread_rng();
+        let base = Gej::new_random_using_thread_rng();
+        let secret = Scalar::new_random_using_thread_rng();
+        let decommitment = Scalar::new_random_using_thread_rng();
+        let commitment = ped::ped_commit(&h, &secret, &decommitment);
+        let mut value = Gej::default();
+        value.scalar_mul(&base, &secret);
+
+        let proof = prove(&base, &secret, &decommitment, &h);
+        assert!(verify(&proof, &base, &commitment, &value, &h));
+
+        let other = Gej::new_random_using_thread_rng();
+        assert!(!verify(&proof, &base, &commitment, &other, &h));
+        assert!(!verify(&proof, &base, &other, &value, &h));
+        assert!(!verify(&proof, &other, &commitment, &value, &h));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::dleq;
+use crate::exponent;
+use crate::params::Parameters;
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::vss::{self, SharingCommitment, VShare};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ElGamalError {
+    InvalidIndex,
+    DuplicateIndex,
+    InconsistentIndices,
+    InvalidBatchSize,
+    InvalidProof,
+}
+
+// An ElGamal ciphertext (g^r, m + y^r) for a message m encrypted to the public key y. The public
+// key will usually be the output of rkpg for a shared secret key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ciphertext {
+    pub c1: Gej,
+    pub c2: Gej,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialDecryption {
+    pub index: Scalar,
+    pub value: Gej,
+    pub proof: dleq::Proof,
+}
+
+pub fn encrypt(pub_key: &Gej, message: &Gej) -> Ciphertext {
+    let r = Scalar::new_random_using_thread_rng();
+    let mut c1 = Gej::default();
+    c1.scalar_base_mul(&r);
+    let mut c2 = Gej::default();
+    c2.scalar_mul(pub_key, &r);
+    c2.add_assign(message);
+    Ciphertext { c1, c2 }
+}
+
+// Each player raises c1 to their share of the secret key, and proves that the exponent is the same
+// as the secret in their share, so that the partial decryption can be checked against the sharing
+// commitment evaluated at the player's index.
+pub fn partial_decryption_batch(
+    vshare_batch: &[VShare],
+    ciphertext_batch: &[Ciphertext],
+    h: &Gej,
+) -> Vec<PartialDecryption> {
+    assert_eq!(vshare_batch.len(), ciphertext_batch.len());
+    vshare_batch
+        .iter()
+        .zip(ciphertext_batch.iter())
+        .map(|(vshare, ciphertext)| {
+            let mut value = Gej::default();
+            value.scalar_mul(&ciphertext.c1, &vshare.share.value);
+            let proof = dleq::prove(&ciphertext.c1, &vshare.share.value, &vshare.decommitment, h);
+            PartialDecryption {
+                index: vshare.share.index,
+                value,
+                proof,
+            }
+        })
+        .collect()
+}
+
+pub fn partial_decryption_is_valid(
+    partial: &PartialDecryption,
+    ciphertext: &Ciphertext,
+    commitment: &SharingCommitment,
+    h: &Gej,
+) -> bool {
+    let commitment_eval = vss::poly_eval_gej_slice_in_exponent(commitment, &partial.index);
+    dleq::verify(
+        &partial.proof,
+        &ciphertext.c1,
+        &commitment_eval,
+        &partial.value,
+        h,
+    )
+}
+
+pub struct InstanceParams {
+    ciphertext_batch: Vec<Ciphertext>,
+    commitment_batch: Vec<SharingCommitment>,
+}
+
+impl InstanceParams {
+    pub fn new(
+        ciphertext_batch: Vec<Ciphertext>,
+        commitment_batch: Vec<SharingCommitment>,
+    ) -> Self {
+        assert_eq!(ciphertext_batch.len(), commitment_batch.len());
+        assert!(commitment_batch
+            .windows(2)
+            .all(|sl| sl[0].len() == sl[1].len()));
+        Self {
+            ciphertext_batch,
+            commitment_batch,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.commitment_batch[0].len()
+    }
+}
+
+pub struct State {
+    indices: Vec<Scalar>,
+    value_bufs: Vec<Vec<Gej>>,
+}
+
+impl State {
+    pub fn new(inst_params: &InstanceParams) -> Self {
+        let k = inst_params.threshold();
+        let b = inst_params.ciphertext_batch.len();
+        let mut value_bufs = Vec::with_capacity(b);
+        for _ in 0..b {
+            value_bufs.push(Vec::with_capacity(k));
+        }
+        State {
+            indices: Vec::with_capacity(k),
+            value_bufs,
+        }
+    }
+
+    pub fn partials_received(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn handle_partial_decryption_batch(
+        &mut self,
+        inst_params: &InstanceParams,
+        params: &Parameters,
+        partial_batch: Vec<PartialDecryption>,
+    ) -> Result<Option<Vec<Gej>>, ElGamalError> {
+        use ElGamalError::*;
+
+        if partial_batch.len() != self.value_bufs.len() {
+            return Err(InvalidBatchSize);
+        }
+        let index = partial_batch
+            .first()
+            .map(|p| p.index)
+            .ok_or(InvalidBatchSize)?;
+        if !partial_batch.iter().all(|p| p.index == index) {
+            return Err(InconsistentIndices);
+        }
+        if !params.indices.contains(&index) {
+            return Err(InvalidIndex);
+        }
+        if self.indices.contains(&index) {
+            return Err(DuplicateIndex);
+        }
+        for ((partial, ciphertext), commitment) in partial_batch
+            .iter()
+            .zip(inst_params.ciphertext_batch.iter())
+            .zip(inst_params.commitment_batch.iter())
+        {
+            if !partial_decryption_is_valid(partial, ciphertext, commitment, &params.h) {
+                return Err(InvalidProof);
+            }
+        }
+
+        if self.partials_received() == inst_params.threshold() {
+            // The messages have already been decrypted.
+            return Ok(None);
+        }
+        self.indices.push(index);
+        for (buf, partial) in self.value_bufs.iter_mut().zip(partial_batch.into_iter()) {
+            buf.push(partial.value);
+        }
+
+        if self.partials_received() == inst_params.threshold() {
+            Ok(Some(self.decrypt(inst_params)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // The message is c2 - c1^s, where c1^s is obtained by interpolating the partial decryptions
+    // at zero in the exponent.
+    fn decrypt(&self, inst_params: &InstanceParams) -> Vec<Gej> {
+        let precompute = exponent::Precompute::new_at_zero(&self.indices);
+        let mut neg_one = util::scalar_from_u64(1);
+        neg_one.negate_assign_mut();
+
+        let mut messages = Vec::with_capacity(self.value_bufs.len());
+        for (buf, ciphertext) in self
+            .value_bufs
+            .iter()
+            .zip(inst_params.ciphertext_batch.iter())
+        {
+            let mut message = Gej::default();
+            message.scalar_mul(&precompute.interpolate(buf), &neg_one);
+            message.add_assign(&ciphertext.c2);
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+pub struct Machine {
+    params: Parameters,
+    inst_params: InstanceParams,
+    state: State,
+    partial_batch: Vec<PartialDecryption>,
+    output: Option<Vec<Gej>>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        ciphertext_batch: Vec<Ciphertext>,
+        commitment_batch: Vec<SharingCommitment>,
+        vshare_batch: &[VShare],
+    ) -> Self {
+        let partial_batch = partial_decryption_batch(vshare_batch, &ciphertext_batch, &params.h);
+        let inst_params = InstanceParams::new(ciphertext_batch, commitment_batch);
+        let state = State::new(&inst_params);
+        Machine {
+            params,
+            inst_params,
+            state,
+            partial_batch,
+            output: None,
+        }
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Vec<PartialDecryption>;
+    type Output = Vec<Gej>;
+    type Error = ElGamalError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<PartialDecryption>>> {
+        vec![Outgoing::broadcast(self.partial_batch.clone())]
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        partial_batch: Vec<PartialDecryption>,
+        _outgoing: &mut Vec<Outgoing<Vec<PartialDecryption>>>,
+    ) -> Result<(), Blame<ElGamalError>> {
+        if partial_batch.iter().any(|partial| &partial.index != from) {
+            return Err(Blame::new(*from, ElGamalError::InconsistentIndices));
+        }
+        let res = self
+            .state
+            .handle_partial_decryption_batch(&self.inst_params, &self.params, partial_batch)
+            .map_err(|e| Blame::new(*from, e))?;
+        if res.is_some() {
+            self.output = res;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<Vec<Gej>> {
+        self.output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+
+    #[test]
+    fn threshold_decryption_recovers_messages() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (key_shares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        let mut messages = Vec::with_capacity(b);
+        let mut ciphertexts = Vec::with_capacity(b);
+        for secret in secrets.iter() {
+            let mut pub_key = Gej::default();
+            pub_key.scalar_base_mul(secret);
+            let message = Gej::new_random_using_thread_rng();
+            ciphertexts.push(encrypt(&pub_key, &message));
+            messages.push(message);
+        }
+
+        let inst_params = InstanceParams::new(ciphertexts.clone(), commitment_batch);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let mut state = State::new(&inst_params);
+
+        // A partial decryption that does not match the player's share is rejected.
+        let mut bad_batch = partial_decryption_batch(&key_shares_by_player[0], &ciphertexts, &h);
+        bad_batch[1].value = Gej::new_random_using_thread_rng();
+        assert_eq!(
+            state.handle_partial_decryption_batch(&inst_params, &params, bad_batch),
+            Err(ElGamalError::InvalidProof)
+        );
+
+        for (i, key_share_batch) in key_shares_by_player.iter().take(k).enumerate() {
+            let partial_batch = partial_decryption_batch(key_share_batch, &ciphertexts, &h);
+            let res = state
+                .handle_partial_decryption_batch(&inst_params, &params, partial_batch)
+                .unwrap();
+            if i < k - 1 {
+                assert_eq!(res, None);
+            } else {
+                assert_eq!(res, Some(messages.clone()));
+            }
+        }
+
+        let partial_batch = partial_decryption_batch(&key_shares_by_player[0], &ciphertexts, &h);
+        assert_eq!(
+            state.handle_partial_decryption_batch(&inst_params, &params, partial_batch),
+            Err(ElGamalError::DuplicateIndex)
+        );
+    }
+}
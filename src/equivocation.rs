@@ -0,0 +1,84 @@
+use crate::secret::Wipe;
+use crate::transport::codec::Codec;
+use secp256k1::scalar::Scalar;
+use sha2::{Digest, Sha256};
+
+pub fn digest<M: Codec>(message: &M) -> [u8; 32] {
+    let mut buf = Vec::new();
+    message.encode(&mut buf);
+    let mut out = [0_u8; 32];
+    out.copy_from_slice(Sha256::digest(&buf).as_slice());
+    out
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Seen<M> {
+    New,
+    Same,
+    // The message that was accepted before, which differs from the new one.
+    Different(M),
+}
+
+// The messages that have been accepted from each sender in an instance of a protocol. Messages are
+// compared by their digests, and the messages themselves are kept so that a sender who sends two
+// different messages can be shown to have done so.
+pub struct Accepted<M> {
+    entries: Vec<(Scalar, [u8; 32], M)>,
+}
+
+impl<M: Codec + Clone> Default for Accepted<M> {
+    fn default() -> Self {
+        Accepted {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<M: Codec + Clone> Accepted<M> {
+    pub fn new() -> Self {
+        Accepted::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The accepted messages, in the order that they were accepted.
+    pub fn messages(&self) -> impl Iterator<Item = &M> {
+        self.entries.iter().map(|(_, _, message)| message)
+    }
+
+    pub fn check(&self, sender: &Scalar, message: &M) -> Seen<M> {
+        match self.entries.iter().find(|(index, _, _)| index == sender) {
+            None => Seen::New,
+            Some((_, accepted_digest, accepted)) => {
+                if accepted_digest == &digest(message) {
+                    Seen::Same
+                } else {
+                    Seen::Different(accepted.clone())
+                }
+            }
+        }
+    }
+
+    // Records the message as accepted. The caller should have checked that no message has been
+    // accepted from the sender before.
+    pub fn insert(&mut self, sender: Scalar, message: M) {
+        debug_assert!(self.entries.iter().all(|(index, _, _)| index != &sender));
+        let message_digest = digest(&message);
+        self.entries.push((sender, message_digest, message));
+    }
+}
+
+impl<M: Wipe> Wipe for Accepted<M> {
+    fn wipe(&mut self) {
+        for (_, _, message) in self.entries.iter_mut() {
+            message.wipe();
+        }
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,458 @@
+use crate::auth::{Directory, Signed};
+use crate::brng;
+use crate::mulopen;
+use crate::rng::DirectedVShare;
+use crate::transport::codec::Codec;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{self, SharingCommitment, VShare};
+
+// Messages that evidence of misbehaviour can be built from.
+pub trait Accusable: Codec + PartialEq {
+    // The share at the given position in the batch, if the message contains shares that can be
+    // checked against a commitment.
+    fn vshare(&self, _position: usize) -> Option<VShare> {
+        None
+    }
+
+    // The commitments that a dealer broadcasts together with the shares for each recipient, and
+    // signs with them.
+    fn commitment_batch(&self) -> Option<&[SharingCommitment]> {
+        None
+    }
+
+    // The commitment at the given position, if the sender signed it together with the shares.
+    fn commitment(&self, position: usize) -> Option<&SharingCommitment> {
+        self.commitment_batch()?.get(position)
+    }
+
+    // The round that the message was sent in, for protocols in which an honest player sends more
+    // than one message.
+    fn round(&self) -> usize {
+        0
+    }
+
+    // Whether the product at the given position is consistent with its commitment and proof, if
+    // the message contains products.
+    fn product_is_valid(
+        &self,
+        _position: usize,
+        _a_commitment: &SharingCommitment,
+        _b_commitment: &SharingCommitment,
+        _z_commitment: &SharingCommitment,
+        _h: &Gej,
+    ) -> Option<bool> {
+        None
+    }
+
+    // The player that the message was sent to, or None if it was broadcast. Sending different
+    // messages to different players is only equivocation for broadcast messages.
+    fn recipient(&self) -> Option<Scalar>;
+}
+
+// The shares broadcast in open.
+impl Accusable for Vec<VShare> {
+    fn vshare(&self, position: usize) -> Option<VShare> {
+        self.get(position).cloned()
+    }
+
+    fn recipient(&self) -> Option<Scalar> {
+        None
+    }
+}
+
+// The shares of shares sent in rng and rzg.
+impl Accusable for Vec<DirectedVShare> {
+    fn vshare(&self, position: usize) -> Option<VShare> {
+        self.get(position).map(|directed| directed.vshare)
+    }
+
+    fn recipient(&self) -> Option<Scalar> {
+        self.first().map(|directed| directed.to)
+    }
+}
+
+// The messages from dealers in brng and reshare.
+impl Accusable for brng::Message {
+    fn vshare(&self, position: usize) -> Option<VShare> {
+        self.0.get(position).map(|directed| directed.vshare)
+    }
+
+    fn commitment_batch(&self) -> Option<&[SharingCommitment]> {
+        Some(&self.1)
+    }
+
+    fn recipient(&self) -> Option<Scalar> {
+        self.0.first().map(|directed| directed.to)
+    }
+}
+
+// The masked shares broadcast in each round of the Beaver multiplications.
+impl Accusable for (usize, Vec<VShare>) {
+    fn vshare(&self, position: usize) -> Option<VShare> {
+        self.1.get(position).cloned()
+    }
+
+    fn round(&self) -> usize {
+        self.0
+    }
+
+    fn recipient(&self) -> Option<Scalar> {
+        None
+    }
+}
+
+// The decommitments broadcast in rkpg, which can only be used as evidence of equivocation.
+impl Accusable for Vec<Share> {
+    fn recipient(&self) -> Option<Scalar> {
+        None
+    }
+}
+
+impl Accusable for Vec<mulopen::Message> {
+    fn product_is_valid(
+        &self,
+        position: usize,
+        a_commitment: &SharingCommitment,
+        b_commitment: &SharingCommitment,
+        z_commitment: &SharingCommitment,
+        h: &Gej,
+    ) -> Option<bool> {
+        let message = self.get(position)?;
+        Some(mulopen::message_is_valid(
+            message,
+            a_commitment,
+            b_commitment,
+            z_commitment,
+            h,
+        ))
+    }
+
+    fn recipient(&self) -> Option<Scalar> {
+        None
+    }
+}
+
+// The public commitments for the instance of the protocol that a message was sent in. These are
+// given to the verifier separately from the evidence, since an accuser that could choose them
+// could make an honest message look invalid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Commitments<'a> {
+    // The commitments that the shares in a message are checked against, as in open and rng. These
+    // are not needed for messages from dealers, who sign their commitments.
+    Shares(&'a [SharingCommitment]),
+    // The commitments to the inputs and the masks in mulopen.
+    Products {
+        a: &'a [SharingCommitment],
+        b: &'a [SharingCommitment],
+        z: &'a [SharingCommitment],
+    },
+    None,
+}
+
+// Evidence that a player misbehaved, which can be checked by anyone that knows the identity keys
+// of the players and the public commitments for the instance.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Evidence<M> {
+    // A share that does not match the commitment for the sharing, in open, rng or brng.
+    InvalidShare { signed: Signed<M>, position: usize },
+    // A product share in mulopen that does not match its commitment or proof.
+    InvalidProduct { signed: Signed<M>, position: usize },
+    // Two different messages for the same recipient in the same round of a session, or two
+    // messages from a dealer with different commitments, even if they were for different
+    // recipients.
+    Equivocation { first: Signed<M>, second: Signed<M> },
+}
+
+impl<M: Accusable> Evidence<M> {
+    pub fn offender(&self) -> Scalar {
+        match self {
+            Evidence::InvalidShare { signed, .. } => signed.signer,
+            Evidence::InvalidProduct { signed, .. } => signed.signer,
+            Evidence::Equivocation { first, .. } => first.signer,
+        }
+    }
+
+    // Returns true if the evidence shows that the offender misbehaved in the given session, in
+    // which the messages were checked against the given commitments.
+    pub fn verify(
+        &self,
+        directory: &Directory,
+        session: &[u8],
+        commitments: Commitments,
+        h: &Gej,
+    ) -> bool {
+        match self {
+            Evidence::InvalidShare { signed, position } => {
+                if !signed.is_valid(directory, session) {
+                    return false;
+                }
+                let commitment = match (signed.message.commitment(*position), commitments) {
+                    (Some(signed_commitment), _) => signed_commitment,
+                    (None, Commitments::Shares(commitment_batch)) => {
+                        match commitment_batch.get(*position) {
+                            Some(commitment) => commitment,
+                            None => return false,
+                        }
+                    }
+                    (None, _) => return false,
+                };
+                match signed.message.vshare(*position) {
+                    Some(vshare) => !vss::vshare_is_valid(&vshare, commitment, h),
+                    None => false,
+                }
+            }
+            Evidence::InvalidProduct { signed, position } => {
+                let (a_commitment, b_commitment, z_commitment) = match commitments {
+                    Commitments::Products { a, b, z } => {
+                        match (a.get(*position), b.get(*position), z.get(*position)) {
+                            (Some(a), Some(b), Some(z)) => (a, b, z),
+                            _ => return false,
+                        }
+                    }
+                    _ => return false,
+                };
+                signed.is_valid(directory, session)
+                    && signed.message.product_is_valid(
+                        *position,
+                        a_commitment,
+                        b_commitment,
+                        z_commitment,
+                        h,
+                    ) == Some(false)
+            }
+            Evidence::Equivocation { first, second } => {
+                let (a, b) = (&first.message, &second.message);
+                let conflicting = a.round() == b.round()
+                    && ((a != b && a.recipient() == b.recipient())
+                        || a.commitment_batch() != b.commitment_batch());
+                first.signer == second.signer
+                    && conflicting
+                    && first.is_valid(directory, session)
+                    && second.is_valid(directory, session)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::SigningKey;
+    use crate::fault::Tamper;
+    use crate::params::Parameters;
+    use crate::testutil;
+    use secp256k1::scalar;
+
+    const SESSION: &[u8] = b"session";
+
+    fn keys_and_directory(params: &Parameters) -> (Vec<SigningKey>, Directory) {
+        let keys: Vec<SigningKey> = params
+            .indices
+            .iter()
+            .map(|_| SigningKey::new_random_using_thread_rng())
+            .collect();
+        let pub_keys = keys.iter().map(SigningKey::pub_key).collect();
+        (keys, Directory::new(params, pub_keys).unwrap())
+    }
+
+    #[test]
+    fn invalid_shares_can_be_proven() {
+        let n = 5;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let (keys, directory) = keys_and_directory(&params);
+        let (vshares_by_player, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        let honest = Signed::new(&keys[1], SESSION, indices[1], vshares_by_player[1].clone());
+        let mut corrupt_batch = vshares_by_player[1].clone();
+        corrupt_batch[1].corrupt_value();
+        let corrupt = Signed::new(&keys[1], SESSION, indices[1], corrupt_batch);
+
+        let commitments = Commitments::Shares(&commitment_batch);
+        let evidence = |signed: &Signed<Vec<VShare>>| Evidence::InvalidShare {
+            signed: signed.clone(),
+            position: 1,
+        };
+        assert!(evidence(&corrupt).verify(&directory, SESSION, commitments, &h));
+        assert_eq!(evidence(&corrupt).offender(), indices[1]);
+        assert!(!evidence(&corrupt).verify(&directory, b"other session", commitments, &h));
+        assert!(!evidence(&honest).verify(&directory, SESSION, commitments, &h));
+        assert!(!evidence(&corrupt).verify(&directory, SESSION, Commitments::None, &h));
+
+        // The accuser can not change the message.
+        let mut forged = corrupt.clone();
+        forged.signer = indices[2];
+        assert!(!evidence(&forged).verify(&directory, SESSION, commitments, &h));
+
+        // Dealers sign their commitments, so the public commitments are not needed.
+        let sharing_batch = brng::create_sharing_batch(b, k, &params);
+        let mut message = brng::messages(&sharing_batch, &indices).remove(2);
+        message.0[0].corrupt_value();
+        let signed = Signed::new(&keys[0], SESSION, indices[0], message);
+        let evidence = Evidence::InvalidShare {
+            signed,
+            position: 0,
+        };
+        assert!(evidence.verify(&directory, SESSION, Commitments::None, &h));
+    }
+
+    #[test]
+    fn honest_messages_can_not_be_accused() {
+        let n = 5;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let (keys, directory) = keys_and_directory(&params);
+        let (vshares_by_player, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let honest = Signed::new(&keys[1], SESSION, indices[1], vshares_by_player[1].clone());
+
+        // The evidence does not contain a commitment, so an honest message is always checked
+        // against the commitment for the instance.
+        let commitments = Commitments::Shares(&commitment_batch);
+        for position in 0..=b {
+            let evidence = Evidence::InvalidShare {
+                signed: honest.clone(),
+                position,
+            };
+            assert!(!evidence.verify(&directory, SESSION, commitments, &h));
+        }
+
+        // A dealer is checked against the commitments that they signed, even if the verifier is
+        // given the wrong ones.
+        let sharing_batch = brng::create_sharing_batch(b, k, &params);
+        let message = brng::messages(&sharing_batch, &indices).remove(2);
+        let evidence = Evidence::InvalidShare {
+            signed: Signed::new(&keys[0], SESSION, indices[0], message),
+            position: 0,
+        };
+        assert!(!evidence.verify(&directory, SESSION, commitments, &h));
+    }
+
+    #[test]
+    fn invalid_products_can_be_proven() {
+        let n = 5;
+        let k = 2;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let (keys, directory) = keys_and_directory(&params);
+        let (a_vshares_by_player, a_commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (b_vshares_by_player, b_commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (z_vshares_by_player, z_commitment_batch) =
+            testutil::zero_sharing_batch(n, k, b, &indices, &h);
+
+        let mut message_batch = mulopen::initial_message_batch(
+            a_vshares_by_player[3].clone(),
+            b_vshares_by_player[3].clone(),
+            z_vshares_by_player[3].clone(),
+            &h,
+        );
+        let honest = Signed::new(&keys[3], SESSION, indices[3], message_batch.clone());
+        message_batch.forge_proof();
+        let forged = Signed::new(&keys[3], SESSION, indices[3], message_batch);
+
+        let commitments = Commitments::Products {
+            a: &a_commitment_batch,
+            b: &b_commitment_batch,
+            z: &z_commitment_batch,
+        };
+        let evidence = |signed: &Signed<Vec<mulopen::Message>>| Evidence::InvalidProduct {
+            signed: signed.clone(),
+            position: 0,
+        };
+        assert!(evidence(&forged).verify(&directory, SESSION, commitments, &h));
+        assert!(!evidence(&honest).verify(&directory, SESSION, commitments, &h));
+        let commitments = Commitments::Shares(&z_commitment_batch);
+        assert!(!evidence(&forged).verify(&directory, SESSION, commitments, &h));
+    }
+
+    #[test]
+    fn equivocation_can_be_proven() {
+        let n = 5;
+        let k = 3;
+        let b = 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let (keys, directory) = keys_and_directory(&params);
+        let (vshares_by_player, _, _, _) = testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        let first = Signed::new(&keys[4], SESSION, indices[4], vshares_by_player[4].clone());
+        let mut other_batch = vshares_by_player[4].clone();
+        other_batch.corrupt_decommitment();
+        let second = Signed::new(&keys[4], SESSION, indices[4], other_batch);
+        let evidence = Evidence::Equivocation {
+            first: first.clone(),
+            second,
+        };
+        assert!(evidence.verify(&directory, SESSION, Commitments::None, &h));
+
+        // Signing the same message twice is not equivocation.
+        let again = Signed::new(&keys[4], SESSION, indices[4], vshares_by_player[4].clone());
+        let evidence = Evidence::Equivocation {
+            first,
+            second: again,
+        };
+        assert!(!evidence.verify(&directory, SESSION, Commitments::None, &h));
+
+        // Neither is a dealer sending different shares to different players.
+        let sharing_batch = brng::create_sharing_batch(b, k, &params);
+        let mut messages = brng::messages(&sharing_batch, &indices);
+        let signed: Vec<_> = messages
+            .drain(..2)
+            .map(|message| Signed::new(&keys[0], SESSION, indices[0], message))
+            .collect();
+        let evidence = Evidence::Equivocation {
+            first: signed[0].clone(),
+            second: signed[1].clone(),
+        };
+        assert!(!evidence.verify(&directory, SESSION, Commitments::None, &h));
+
+        // The commitments are broadcast, so sending different ones to different players is.
+        let other_sharing_batch = brng::create_sharing_batch(b, k, &params);
+        let other = brng::messages(&other_sharing_batch, &indices).remove(1);
+        let evidence = Evidence::Equivocation {
+            first: signed[0].clone(),
+            second: Signed::new(&keys[0], SESSION, indices[0], other),
+        };
+        assert!(evidence.verify(&directory, SESSION, Commitments::None, &h));
+
+        // Messages for different rounds of the Beaver multiplications are expected to differ.
+        let round = |round: usize, vshare_batch: &[VShare]| {
+            Signed::new(
+                &keys[4],
+                SESSION,
+                indices[4],
+                (round, vshare_batch.to_vec()),
+            )
+        };
+        let mut other_batch = vshares_by_player[4].clone();
+        other_batch.corrupt_decommitment();
+        let evidence = Evidence::Equivocation {
+            first: round(0, &vshares_by_player[4]),
+            second: round(1, &other_batch),
+        };
+        assert!(!evidence.verify(&directory, SESSION, Commitments::None, &h));
+        let evidence = Evidence::Equivocation {
+            first: round(1, &vshares_by_player[4]),
+            second: round(1, &other_batch),
+        };
+        assert!(evidence.verify(&directory, SESSION, Commitments::None, &h));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ExponentError {
+    NotEnoughPoints,
+    DuplicateIndex,
+    InconsistentPoints,
+}
+
+// Lagrange interpolation in the exponent. For points (i, g^f(i)) on a polynomial f of degree less
+// than the number of points, this computes g^f(x) as the product of the points raised to their
+// Lagrange coefficients.
+pub fn interpolate_at(points: &[(Scalar, Gej)], at: &Scalar) -> Gej {
+    let indices: Vec<Scalar> = points.iter().map(|(index, _)| *index).collect();
+    let values: Vec<Gej> = points.iter().map(|(_, value)| *value).collect();
+    Precompute::new(&indices, at).interpolate(&values)
+}
+
+pub fn interpolate_at_zero(points: &[(Scalar, Gej)]) -> Gej {
+    interpolate_at(points, &Scalar::zero())
+}
+
+// Removes the blinding from a Pedersen commitment g^s h^t whose decommitment t is known, which
+// gives g^s. Reconstructing the decommitment of a sharing at zero in this way gives the public key
+// for the secret, as in rkpg.
+pub fn unblind(commitment: &Gej, decommitment: &Scalar, h: &Gej) -> Gej {
+    let mut decommitment_neg = *decommitment;
+    decommitment_neg.negate_assign_mut();
+    let mut value = Gej::default();
+    value.scalar_mul(h, &decommitment_neg);
+    value.add_assign(commitment);
+    value
+}
+
+// Lagrange coefficients for a fixed set of indices and evaluation point, for when many sets of
+// values for the same indices need to be interpolated. The barycentric weights of the indices are
+// kept as well, so that the coefficients at other points only take O(k) multiplications.
+#[derive(Clone, Debug)]
+pub struct Precompute {
+    indices: Vec<Scalar>,
+    weights: Vec<Scalar>,
+    coeffs: Vec<Scalar>,
+}
+
+impl Precompute {
+    pub fn new(indices: &[Scalar], at: &Scalar) -> Self {
+        let weights = indices
+            .iter()
+            .map(|index| {
+                let mut weight = util::scalar_from_u64(1);
+                for other in indices.iter().filter(|other| *other != index) {
+                    weight = weight * (index - other);
+                }
+                weight.inverse_assign();
+                weight
+            })
+            .collect();
+        let mut precompute = Precompute {
+            indices: indices.to_vec(),
+            weights,
+            coeffs: Vec::new(),
+        };
+        precompute.coeffs = precompute.coeffs_at(at);
+        precompute
+    }
+
+    pub fn new_at_zero(indices: &[Scalar]) -> Self {
+        Precompute::new(indices, &Scalar::zero())
+    }
+
+    pub fn indices(&self) -> &[Scalar] {
+        &self.indices
+    }
+
+    // The coefficient of index i at x is w_i times the product of x - x_j for j != i, which is
+    // computed from prefix and suffix products so that x can also be one of the indices.
+    fn coeffs_at(&self, at: &Scalar) -> Vec<Scalar> {
+        let mut coeffs = Vec::with_capacity(self.indices.len());
+        let mut prefix = util::scalar_from_u64(1);
+        for index in self.indices.iter() {
+            coeffs.push(prefix);
+            prefix = prefix * (at - index);
+        }
+        let mut suffix = util::scalar_from_u64(1);
+        for ((coeff, weight), index) in coeffs
+            .iter_mut()
+            .zip(self.weights.iter())
+            .zip(self.indices.iter())
+            .rev()
+        {
+            *coeff = &(&*coeff * &suffix) * weight;
+            suffix = suffix * (at - index);
+        }
+        coeffs
+    }
+
+    // The values must be given in the same order as the indices.
+    pub fn interpolate(&self, values: &[Gej]) -> Gej {
+        combine(values, &self.coeffs)
+    }
+
+    // Interpolates at a point other than the one that the precompute was created for.
+    pub fn interpolate_at(&self, values: &[Gej], at: &Scalar) -> Gej {
+        combine(values, &self.coeffs_at(at))
+    }
+}
+
+fn combine(values: &[Gej], coeffs: &[Scalar]) -> Gej {
+    assert_eq!(values.len(), coeffs.len());
+    let mut acc = Gej::infinity();
+    let mut term = Gej::default();
+    for (value, coeff) in values.iter().zip(coeffs.iter()) {
+        term.scalar_mul(value, coeff);
+        acc.add_assign(&term);
+    }
+    acc
+}
+
+// Interpolates at zero using the first k points, and checks that every remaining point lies on the
+// same polynomial of degree k - 1. Unlike Reed-Solomon decoding of scalar shares, errors in the
+// exponent can only be detected and not corrected, so if any of the points are inconsistent an
+// error is returned.
+pub fn interpolate_at_zero_with_error_detection(
+    points: &[(Scalar, Gej)],
+    k: usize,
+) -> Result<Gej, ExponentError> {
+    if k == 0 || points.len() < k {
+        return Err(ExponentError::NotEnoughPoints);
+    }
+    for (i, (index, _)) in points.iter().enumerate() {
+        if points[..i].iter().any(|(other, _)| other == index) {
+            return Err(ExponentError::DuplicateIndex);
+        }
+    }
+
+    let (basis, rest) = points.split_at(k);
+    let indices: Vec<Scalar> = basis.iter().map(|(index, _)| *index).collect();
+    let values: Vec<Gej> = basis.iter().map(|(_, value)| *value).collect();
+    let precompute = Precompute::new_at_zero(&indices);
+    for (index, value) in rest {
+        if &precompute.interpolate_at(&values, index) != value {
+            return Err(ExponentError::InconsistentPoints);
+        }
+    }
+    Ok(precompute.interpolate(&values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::scalar;
+
+    fn points_in_exponent(coeffs: &[Scalar], indices: &[Scalar]) -> Vec<(Scalar, Gej)> {
+        indices
+            .iter()
+            .map(|index| {
+                let mut eval = Scalar::zero();
+                for coeff in coeffs.iter().rev() {
+                    eval = &(&eval * index) + coeff;
+                }
+                let mut value = Gej::default();
+                value.scalar_base_mul(&eval);
+                (*index, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interpolation_recovers_the_constant_term() {
+        let n = 10;
+        let k = 4;
+
+        let coeffs = scalar::random_scalars_using_thread_rng(k);
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let points = points_in_exponent(&coeffs, &indices);
+        let mut expected = Gej::default();
+        expected.scalar_base_mul(&coeffs[0]);
+
+        assert_eq!(interpolate_at_zero(&points[..k]), expected);
+        assert_eq!(interpolate_at_zero(&points[n - k..]), expected);
+        assert_eq!(
+            interpolate_at_zero_with_error_detection(&points, k),
+            Ok(expected)
+        );
+
+        let at = Scalar::new_random_using_thread_rng();
+        let expected_at = points_in_exponent(&coeffs, &[at])[0].1;
+        assert_eq!(interpolate_at(&points[..k], &at), expected_at);
+
+        let precompute = Precompute::new_at_zero(&indices[..k]);
+        let values: Vec<Gej> = points[..k].iter().map(|(_, value)| *value).collect();
+        assert_eq!(precompute.interpolate(&values), expected);
+        assert_eq!(precompute.interpolate_at(&values, &at), expected_at);
+        assert_eq!(precompute.interpolate_at(&values, &indices[1]), points[1].1);
+    }
+
+    #[test]
+    fn unblinding_a_commitment_gives_the_public_key() {
+        let h = Gej::new_random_using_thread_rng();
+        let secret = Scalar::new_random_using_thread_rng();
+        let decommitment = Scalar::new_random_using_thread_rng();
+        let mut expected = Gej::default();
+        expected.scalar_base_mul(&secret);
+        let mut commitment = Gej::default();
+        commitment.scalar_mul(&h, &decommitment);
+        commitment.add_assign(&expected);
+
+        assert_eq!(unblind(&commitment, &decommitment, &h), expected);
+    }
+
+    #[test]
+    fn inconsistent_points_are_detected() {
+        let n = 10;
+        let k = 4;
+
+        let coeffs = scalar::random_scalars_using_thread_rng(k);
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let mut points = points_in_exponent(&coeffs, &indices);
+        points[n - 1].1 = Gej::new_random_using_thread_rng();
+
+        assert_eq!(
+            interpolate_at_zero_with_error_detection(&points, k),
+            Err(ExponentError::InconsistentPoints)
+        );
+        assert_eq!(
+            interpolate_at_zero_with_error_detection(&points[..k - 1], k),
+            Err(ExponentError::NotEnoughPoints)
+        );
+    }
+}
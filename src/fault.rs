@@ -0,0 +1,559 @@
+use crate::brng;
+use crate::mulopen;
+use crate::protocol::{Blame, Protocol};
+use crate::rng::DirectedVShare;
+use crate::sim::{Adversary, Delivery, Envelope, Network};
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::VShare;
+use std::fmt::Debug;
+
+// Ways in which a corrupt player can deviate from a protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Behaviour {
+    CorruptValue,
+    CorruptDecommitment,
+    ForgeProof,
+    // Sends the correct message to some players and a message with a corrupt value to the others.
+    Equivocate,
+    // Claims to be a different player.
+    WrongIndex,
+    Withhold,
+}
+
+impl Behaviour {
+    pub const ALL: [Behaviour; 6] = [
+        Behaviour::CorruptValue,
+        Behaviour::CorruptDecommitment,
+        Behaviour::ForgeProof,
+        Behaviour::Equivocate,
+        Behaviour::WrongIndex,
+        Behaviour::Withhold,
+    ];
+}
+
+// Messages that an adversary knows how to change. Values are changed by adding a random offset, so
+// the result is different from the original with overwhelming probability.
+pub trait Tamper {
+    fn corrupt_value(&mut self);
+
+    fn corrupt_decommitment(&mut self);
+
+    // Changes the message so that it is consistent with the commitments but not with the proof.
+    // Messages that do not contain a proof just have their value corrupted.
+    fn forge_proof(&mut self) {
+        self.corrupt_value();
+    }
+
+    fn set_index(&mut self, index: &Scalar);
+}
+
+impl Tamper for Share {
+    fn corrupt_value(&mut self) {
+        self.value = &self.value + &Scalar::new_random_using_thread_rng();
+    }
+
+    // Shares on their own are used to send decommitments, as in rkpg.
+    fn corrupt_decommitment(&mut self) {
+        self.corrupt_value();
+    }
+
+    fn set_index(&mut self, index: &Scalar) {
+        self.index = *index;
+    }
+}
+
+impl Tamper for VShare {
+    fn corrupt_value(&mut self) {
+        self.share.corrupt_value();
+    }
+
+    fn corrupt_decommitment(&mut self) {
+        self.decommitment = &self.decommitment + &Scalar::new_random_using_thread_rng();
+    }
+
+    fn set_index(&mut self, index: &Scalar) {
+        self.share.index = *index;
+    }
+}
+
+impl Tamper for DirectedVShare {
+    fn corrupt_value(&mut self) {
+        self.vshare.corrupt_value();
+    }
+
+    fn corrupt_decommitment(&mut self) {
+        self.vshare.corrupt_decommitment();
+    }
+
+    fn set_index(&mut self, index: &Scalar) {
+        self.vshare.set_index(index);
+    }
+}
+
+impl Tamper for mulopen::Message {
+    fn corrupt_value(&mut self) {
+        self.vshare.corrupt_value();
+    }
+
+    fn corrupt_decommitment(&mut self) {
+        self.vshare.corrupt_decommitment();
+    }
+
+    // Shifts the product share and its commitment by the same amount, so that the share is
+    // consistent with the commitment but the proof is for the original product.
+    fn forge_proof(&mut self) {
+        let offset = Scalar::new_random_using_thread_rng();
+        self.vshare.share.value = &self.vshare.share.value + &offset;
+        let mut offset_commitment = Gej::default();
+        offset_commitment.scalar_base_mul(&offset);
+        self.commitment.add_assign(&offset_commitment);
+    }
+
+    fn set_index(&mut self, index: &Scalar) {
+        self.vshare.set_index(index);
+    }
+}
+
+// The messages from dealers in brng and reshare. Only the shares are changed, since a player that
+// receives commitments of the wrong form rejects them without checking the shares.
+impl Tamper for brng::Message {
+    fn corrupt_value(&mut self) {
+        self.0.corrupt_value();
+    }
+
+    fn corrupt_decommitment(&mut self) {
+        self.0.corrupt_decommitment();
+    }
+
+    fn set_index(&mut self, index: &Scalar) {
+        self.0.set_index(index);
+    }
+}
+
+impl<T: Tamper> Tamper for Vec<T> {
+    fn corrupt_value(&mut self) {
+        self.iter_mut().for_each(T::corrupt_value);
+    }
+
+    fn corrupt_decommitment(&mut self) {
+        self.iter_mut().for_each(T::corrupt_decommitment);
+    }
+
+    fn forge_proof(&mut self) {
+        self.iter_mut().for_each(T::forge_proof);
+    }
+
+    fn set_index(&mut self, index: &Scalar) {
+        for t in self.iter_mut() {
+            t.set_index(index);
+        }
+    }
+}
+
+// An adversary that makes every corrupt player behave in the same way. Players with the wrong
+// index claim to be the given honest player.
+pub struct Faulty {
+    corrupt: Vec<Scalar>,
+    behaviour: Behaviour,
+    claimed_index: Scalar,
+    count: usize,
+}
+
+impl Faulty {
+    pub fn new(corrupt: Vec<Scalar>, behaviour: Behaviour, claimed_index: Scalar) -> Self {
+        Faulty {
+            corrupt,
+            behaviour,
+            claimed_index,
+            count: 0,
+        }
+    }
+
+    pub fn tamper_message<M: Tamper>(&mut self, message: &mut M) -> bool {
+        use Behaviour::*;
+
+        match self.behaviour {
+            CorruptValue => message.corrupt_value(),
+            CorruptDecommitment => message.corrupt_decommitment(),
+            ForgeProof => message.forge_proof(),
+            Equivocate => {
+                self.count += 1;
+                if self.count % 2 == 0 {
+                    message.corrupt_value();
+                }
+            }
+            WrongIndex => message.set_index(&self.claimed_index),
+            Withhold => return false,
+        }
+        true
+    }
+}
+
+impl<M: Tamper> Adversary<M> for Faulty {
+    fn is_corrupt(&self, index: &Scalar) -> bool {
+        self.corrupt.contains(index)
+    }
+
+    fn tamper(&mut self, mut envelope: Envelope<M>) -> Vec<Envelope<M>> {
+        if self.tamper_message(&mut envelope.message) {
+            vec![envelope]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+// Runs the machines with the given players corrupted, and checks that only corrupt players are
+// blamed and that every honest player that finishes has an output for which is_correct returns
+// true. This is all that can be checked for protocols that need every player to take part, such
+// as brng, in which honest players can not finish without the shares of a corrupt dealer.
+pub fn assert_no_false_blame<P, F>(
+    machines: Vec<P>,
+    delivery: Delivery,
+    adversary: Faulty,
+    is_correct: F,
+) -> (Vec<(Scalar, Blame<P::Error>)>, Vec<P>)
+where
+    P: Protocol,
+    P::Message: Tamper,
+    P::Error: Debug,
+    F: Fn(&P::Output) -> bool,
+{
+    let corrupt = adversary.corrupt.clone();
+    let behaviour = adversary.behaviour;
+    let mut network = Network::new(machines, delivery);
+    network.set_adversary(Box::new(adversary));
+    let blames = network.run();
+
+    for (_, blame) in blames.iter() {
+        assert!(
+            corrupt.contains(&blame.culprit),
+            "honest player blamed for {:?} with {:?} and corrupt players {:?}: {:?}",
+            behaviour,
+            delivery,
+            corrupt,
+            blame.error
+        );
+    }
+    for machine in network.machines() {
+        if corrupt.contains(&machine.index()) {
+            continue;
+        }
+        if let Some(output) = machine.output() {
+            assert!(
+                is_correct(&output),
+                "incorrect output for {:?} with {:?} and corrupt players {:?}",
+                behaviour,
+                delivery,
+                corrupt
+            );
+        }
+    }
+    (blames, network.into_machines())
+}
+
+// Like assert_no_false_blame, but also checks that every honest player finishes.
+pub fn assert_safety<P, F>(
+    machines: Vec<P>,
+    delivery: Delivery,
+    adversary: Faulty,
+    is_correct: F,
+) -> Vec<(Scalar, Blame<P::Error>)>
+where
+    P: Protocol,
+    P::Message: Tamper,
+    P::Error: Debug,
+    F: Fn(&P::Output) -> bool,
+{
+    let corrupt = adversary.corrupt.clone();
+    let behaviour = adversary.behaviour;
+    let (blames, machines) = assert_no_false_blame(machines, delivery, adversary, is_correct);
+    for machine in machines.iter() {
+        assert!(
+            corrupt.contains(&machine.index()) || machine.is_done(),
+            "honest player did not finish for {:?} with {:?} and corrupt players {:?}",
+            behaviour,
+            delivery,
+            corrupt
+        );
+    }
+    blames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open;
+    use crate::params::Parameters;
+    use crate::rkpg;
+    use crate::rng;
+    use crate::testutil;
+    use secp256k1::scalar;
+    use shamir::vss::{self, SharingCommitment};
+
+    // Each behaviour is run this many times, with a random delivery order and set of corrupt
+    // players each time. Both are part of every failure message, so that a failing run can be
+    // reproduced.
+    const TRIALS: usize = 4;
+
+    fn random_u64() -> u64 {
+        let mut bs = [0_u8; 32];
+        Scalar::new_random_using_thread_rng().put_b32(&mut bs);
+        let mut word = [0_u8; 8];
+        word.copy_from_slice(&bs[..8]);
+        u64::from_be_bytes(word)
+    }
+
+    fn params_by_player(indices: &[Scalar], h: &Gej) -> Vec<Parameters> {
+        indices
+            .iter()
+            .map(|index| Parameters::new(indices.to_vec(), *index, *h).unwrap())
+            .collect()
+    }
+
+    // A random set of t corrupt players, and those with the wrong index claim to be one of the
+    // honest players.
+    fn random_adversary(indices: &[Scalar], t: usize, behaviour: Behaviour) -> Faulty {
+        let mut shuffled = indices.to_vec();
+        for i in (1..shuffled.len()).rev() {
+            let j = (random_u64() % (i as u64 + 1)) as usize;
+            shuffled.swap(i, j);
+        }
+        let claimed_index = shuffled[t];
+        shuffled.truncate(t);
+        Faulty::new(shuffled, behaviour, claimed_index)
+    }
+
+    // Runs the check for every behaviour with random adversaries and delivery orders.
+    fn for_random_runs<F: FnMut(Behaviour, Delivery, Faulty)>(
+        indices: &[Scalar],
+        t: usize,
+        mut f: F,
+    ) {
+        for behaviour in Behaviour::ALL.iter() {
+            for _ in 0..TRIALS {
+                let delivery = Delivery::Random(random_u64());
+                f(
+                    *behaviour,
+                    delivery,
+                    random_adversary(indices, t, *behaviour),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn open_is_safe_for_random_adversaries() {
+        let n = 10;
+        let k = 4;
+        let b = 2;
+        let t = k - 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        for_random_runs(&indices, t, |_, delivery, adversary| {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+                .map(|(params, vshare_batch)| {
+                    open::Machine::new(params, commitment_batch.clone(), vshare_batch.clone())
+                })
+                .collect();
+            assert_safety(
+                machines,
+                delivery,
+                adversary,
+                |output: &Vec<(Scalar, Scalar)>| {
+                    output.iter().map(|(s, _)| *s).eq(secrets.iter().cloned())
+                },
+            );
+        });
+    }
+
+    #[test]
+    fn rng_and_rzg_are_safe_for_random_adversaries() {
+        let n = 10;
+        let k = 4;
+        let b = 2;
+        let t = k - 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (inputs_by_player, commitments) = testutil::rxg_inputs(k, b, &indices, &h);
+        let is_valid =
+            |(vshare_batch, commitment_batch): &(Vec<VShare>, Vec<SharingCommitment>)| {
+                vshare_batch
+                    .iter()
+                    .zip(commitment_batch.iter())
+                    .all(|(vshare, commitment)| vss::vshare_is_valid(vshare, commitment, &h))
+            };
+
+        for_random_runs(&indices, t, |_, delivery, adversary| {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .map(|params| {
+                    let inputs = &inputs_by_player[&params.index];
+                    rng::Machine::new_rng(params, inputs, &commitments)
+                })
+                .collect();
+            assert_safety(machines, delivery, adversary, is_valid);
+        });
+        for_random_runs(&indices, t, |_, delivery, adversary| {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .map(|params| {
+                    let inputs = &inputs_by_player[&params.index];
+                    rng::Machine::new_rzg(params, inputs, &commitments)
+                })
+                .collect();
+            assert_safety(machines, delivery, adversary, is_valid);
+        });
+    }
+
+    #[test]
+    fn rkpg_is_safe_for_random_adversaries() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+        // Decoding needs n - k + 1 shares and can correct (n - k) / 2 errors, counting the shares
+        // that are missing, so this is the most players that can be corrupt.
+        let t = k - 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let expected: Vec<Gej> = secrets
+            .iter()
+            .map(|secret| {
+                let mut pub_key = Gej::default();
+                pub_key.scalar_base_mul(secret);
+                pub_key
+            })
+            .collect();
+
+        for_random_runs(&indices, t, |_, delivery, adversary| {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+                .map(|(params, vshare_batch)| {
+                    rkpg::Machine::new(params, commitment_batch.clone(), vshare_batch).unwrap()
+                })
+                .collect();
+            assert_safety(
+                machines,
+                delivery,
+                adversary,
+                |output: &Result<Vec<Gej>, rkpg::RKPGError>| output.as_ref() == Ok(&expected),
+            );
+        });
+    }
+
+    #[test]
+    fn mulopen_is_safe_for_random_adversaries() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+        let t = k - 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (a_vshares_by_player, a_commitment_batch, a_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (b_vshares_by_player, b_commitment_batch, b_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (z_vshares_by_player, z_commitment_batch) =
+            testutil::zero_sharing_batch(n, k, b, &indices, &h);
+        let expected: Vec<Scalar> = a_secrets
+            .iter()
+            .zip(b_secrets.iter())
+            .map(|(a, b)| a * b)
+            .collect();
+        let machines = || {
+            let mut machines = Vec::with_capacity(n);
+            for (i, params) in params_by_player(&indices, &h).into_iter().enumerate() {
+                machines.push(
+                    mulopen::Machine::new(
+                        params,
+                        a_vshares_by_player[i].clone(),
+                        b_vshares_by_player[i].clone(),
+                        z_vshares_by_player[i].clone(),
+                        a_commitment_batch.clone(),
+                        b_commitment_batch.clone(),
+                        z_commitment_batch.clone(),
+                    )
+                    .unwrap(),
+                );
+            }
+            machines
+        };
+        let is_correct = |output: &Vec<Scalar>| output == &expected;
+
+        for_random_runs(&indices, t, |behaviour, delivery, adversary| {
+            let blames = assert_safety(machines(), delivery, adversary, is_correct);
+            if behaviour == Behaviour::ForgeProof {
+                assert!(blames
+                    .iter()
+                    .all(|(_, blame)| blame.error == mulopen::MulOpenErr::InvalidZKP));
+            }
+        });
+
+        // When the corrupt players send first, every honest player sees the forgery.
+        let adversary = Faulty::new(indices[..t].to_vec(), Behaviour::ForgeProof, indices[t]);
+        let blames = assert_safety(machines(), Delivery::InOrder, adversary, is_correct);
+        assert!(!blames.is_empty());
+    }
+
+    #[test]
+    fn brng_blames_only_corrupt_dealers_for_random_adversaries() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+        let t = k - 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let is_valid =
+            |(vshare_batch, commitment_batch): &(Vec<VShare>, Vec<SharingCommitment>)| {
+                vshare_batch
+                    .iter()
+                    .zip(commitment_batch.iter())
+                    .all(|(vshare, commitment)| vss::vshare_is_valid(vshare, commitment, &h))
+            };
+
+        for_random_runs(&indices, t, |behaviour, delivery, adversary| {
+            // The first k players are the dealers, so the honest players can only all finish if
+            // none of them are corrupt.
+            let corrupt = adversary.corrupt.clone();
+            let honest_dealers = indices[..k].iter().all(|index| !corrupt.contains(index));
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .map(|params| brng::Machine::new(params, k, b).unwrap())
+                .collect();
+            let (_, machines) = assert_no_false_blame(machines, delivery, adversary, is_valid);
+
+            // Every honest player that finishes received the same commitments from each dealer.
+            let outputs: Vec<_> = machines
+                .iter()
+                .filter(|machine| !corrupt.contains(&machine.index()))
+                .filter_map(|machine| machine.output())
+                .collect();
+            assert!(outputs.windows(2).all(|pair| pair[0].1 == pair[1].1));
+            if honest_dealers {
+                assert_eq!(
+                    outputs.len(),
+                    n - t,
+                    "{:?} with {:?} and corrupt players {:?}",
+                    behaviour,
+                    delivery,
+                    corrupt
+                );
+            }
+        });
+    }
+}
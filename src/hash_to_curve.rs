@@ -0,0 +1,53 @@
+use secp256k1::group::Gej;
+use sha2::{Digest, Sha256};
+
+// Try-and-increment hashing onto secp256k1. The message is hashed together with the domain and a
+// counter to get a candidate x coordinate, which is accepted if it is the x coordinate of a point
+// on the curve (the point with even y is taken). Each candidate succeeds with probability about
+// 1/2, and the discrete log of the output with respect to any other point is unknown.
+//
+// This is not constant time, which is fine for the public inputs that it is used for.
+pub fn hash_to_curve(domain: &[u8], msg: &[u8]) -> Gej {
+    let mut counter: u32 = 0;
+    loop {
+        if let Some(point) = try_hash_to_curve(domain, msg, counter) {
+            return point;
+        }
+        counter = counter
+            .checked_add(1)
+            .expect("no point found for any counter");
+    }
+}
+
+fn try_hash_to_curve(domain: &[u8], msg: &[u8], counter: u32) -> Option<Gej> {
+    let mut hasher = Sha256::new();
+    hasher.update(&(domain.len() as u64).to_be_bytes());
+    hasher.update(domain);
+    hasher.update(msg);
+    hasher.update(&counter.to_be_bytes());
+    let hash = hasher.finalize();
+
+    let mut bs = [0_u8; 33];
+    bs[0] = 0x02;
+    bs[1..].copy_from_slice(hash.as_slice());
+    let mut point = Gej::default();
+    point.set_bytes(&bs).ok()?;
+    Some(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_deterministic_and_domain_separated() {
+        let point = hash_to_curve(b"domain", b"message");
+        assert_eq!(point, hash_to_curve(b"domain", b"message"));
+        assert_ne!(point, hash_to_curve(b"domain", b"other message"));
+        assert_ne!(point, hash_to_curve(b"other domain", b"message"));
+        // The domain length is included so that the boundary between the domain and the message
+        // can not be moved.
+        assert_ne!(point, hash_to_curve(b"domainm", b"essage"));
+        assert!(!point.is_infinity());
+    }
+}
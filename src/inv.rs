@@ -1,15 +1,27 @@
+use crate::shared::Linear;
 use secp256k1::scalar::Scalar;
-use shamir::vss::VShare;
+use shamir::vss::{SharingCommitment, VShare};
 
 pub fn inv_transform_mulopen_output(
-    mut vshare_batch: Vec<VShare>,
-    mut opened_values: Vec<Scalar>,
+    vshare_batch: Vec<VShare>,
+    opened_values: Vec<Scalar>,
 ) -> Vec<VShare> {
-    for (vshare, value) in vshare_batch.iter_mut().zip(opened_values.iter_mut()) {
+    inv_transform(vshare_batch, opened_values)
+}
+
+pub fn inv_transform_mulopen_output_commitment(
+    commitment_batch: Vec<SharingCommitment>,
+    opened_values: Vec<Scalar>,
+) -> Vec<SharingCommitment> {
+    inv_transform(commitment_batch, opened_values)
+}
+
+fn inv_transform<T: Linear>(mut batch: Vec<T>, mut opened_values: Vec<Scalar>) -> Vec<T> {
+    for (x, value) in batch.iter_mut().zip(opened_values.iter_mut()) {
         value.inverse_assign();
-        vshare.scale_assign_mut(value);
+        x.scale_assign_mut(value);
     }
-    vshare_batch
+    batch
 }
 
 #[cfg(test)]
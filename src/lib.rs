@@ -1,9 +1,34 @@
+pub mod auth;
+pub mod beaver;
+pub mod bitdec;
 pub mod brng;
+pub mod circuit;
+pub mod coin;
+pub mod compare;
+pub mod derive;
+pub mod dleq;
+pub mod elgamal;
+pub mod equivocation;
+pub mod evidence;
+pub mod exponent;
+#[cfg(test)]
+mod fault;
+pub mod hash_to_curve;
 pub mod inv;
 pub mod mulopen;
 pub mod open;
 pub mod params;
+pub mod prefix;
+pub mod protocol;
+pub mod randbit;
+pub mod recovery;
+pub mod reshare;
 pub mod rkpg;
 pub mod rng;
+pub mod secret;
+pub mod shared;
+pub mod sim;
 pub mod testutil;
+pub mod transport;
 mod util;
+pub mod vrf;
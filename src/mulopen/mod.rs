@@ -1,3 +1,8 @@
+use crate::equivocation::{Accepted, Seen};
+use crate::params::{Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::secret::Secret;
+use crate::transport::codec::Codec;
 use secp256k1::group::Gej;
 use secp256k1::scalar::Scalar;
 use sha2::{Digest, Sha256};
@@ -14,13 +19,68 @@ pub enum MulOpenErr {
     InconsistentShares,
     InvalidShares,
     InvalidZKP,
+    InvalidIndex,
+    DuplicateIndex,
+    // Two different message batches from the same player, in the order that they were received.
+    Equivocation(Vec<Message>, Vec<Message>),
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Message {
-    vshare: VShare,
-    commitment: Gej,
-    proof: Proof,
+    pub(crate) vshare: VShare,
+    pub(crate) commitment: Gej,
+    pub(crate) proof: Proof,
+}
+
+impl Codec for Message {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.vshare.encode(buf);
+        self.commitment.encode(buf);
+        self.proof.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Message {
+            vshare: VShare::decode(bs)?,
+            commitment: Gej::decode(bs)?,
+            proof: Proof::decode(bs)?,
+        })
+    }
+}
+
+// Checks that the product share in a single message is consistent with its commitment and that
+// the proof for the commitment is valid.
+pub fn check_message(
+    message: &Message,
+    a_commitment: &SharingCommitment,
+    b_commitment: &SharingCommitment,
+    z_commitment: &SharingCommitment,
+    h: &Gej,
+) -> Result<(), MulOpenErr> {
+    let index = message.vshare.share.index;
+    let mut com = vss::poly_eval_gej_slice_in_exponent(z_commitment, &index);
+    com.add_assign(&message.commitment);
+    if ped::ped_commit(h, &message.vshare.share.value, &message.vshare.decommitment) != com {
+        return Err(MulOpenErr::InvalidShares);
+    }
+    let a = vss::poly_eval_gej_slice_in_exponent(a_commitment, &index);
+    let b = vss::poly_eval_gej_slice_in_exponent(b_commitment, &index);
+    if !verify(&message.proof, &a, &b, &message.commitment, h) {
+        return Err(MulOpenErr::InvalidZKP);
+    }
+    Ok(())
+}
+
+// Checks a single message in the same way as handle_message_batch, for use by players that are
+// not running the protocol.
+pub fn message_is_valid(
+    message: &Message,
+    a_commitment: &SharingCommitment,
+    b_commitment: &SharingCommitment,
+    z_commitment: &SharingCommitment,
+    h: &Gej,
+) -> bool {
+    check_message(message, a_commitment, b_commitment, z_commitment, h).is_ok()
 }
 
 pub fn initial_message_batch(
@@ -59,13 +119,13 @@ pub fn initial_message_batch(
             decommitment: sigma,
         } = b_vshare_batch[batch];
         let z_vshare = z_vshare_batch[batch];
-        let tau = Scalar::new_random_using_thread_rng();
+        let tau = Secret::new(Scalar::new_random_using_thread_rng());
 
         let a = ped::ped_commit(h, &alpha, &rho);
         let b = ped::ped_commit(h, &beta, &sigma);
         let c = ped::ped_commit(h, &(alpha * beta), &tau);
 
-        let witness = Witness::new(alpha, beta, rho, sigma, tau);
+        let witness = Witness::new(alpha, beta, rho, sigma, *tau);
         let proof = prove(&witness, &a, &b, &c, h);
 
         let vshare = VShare {
@@ -73,7 +133,7 @@ pub fn initial_message_batch(
                 index,
                 value: (alpha * beta) + z_vshare.share.value,
             },
-            decommitment: tau + z_vshare.decommitment,
+            decommitment: *tau + z_vshare.decommitment,
         };
 
         message_batch.push(Message {
@@ -94,74 +154,99 @@ pub fn handle_message_batch(
     z_commitment_batch: &[SharingCommitment],
     h: &Gej,
 ) -> Result<Option<Vec<Scalar>>, MulOpenErr> {
-    use MulOpenErr::*;
+    check_message_batch(
+        &message_batch,
+        a_commitment_batch,
+        b_commitment_batch,
+        z_commitment_batch,
+        h,
+    )?;
+    assert_eq!(state.len(), message_batch.len());
+    let k = a_commitment_batch[0].len();
+    Ok(accept_message_batch(state, &message_batch, k))
+}
 
+pub fn check_message_batch(
+    message_batch: &[Message],
+    a_commitment_batch: &[SharingCommitment],
+    b_commitment_batch: &[SharingCommitment],
+    z_commitment_batch: &[SharingCommitment],
+    h: &Gej,
+) -> Result<(), MulOpenErr> {
     let b = message_batch.len();
     assert!(b > 0);
     assert_eq!(a_commitment_batch.len(), b);
     assert_eq!(b_commitment_batch.len(), b);
     assert_eq!(z_commitment_batch.len(), b);
-    assert_eq!(state.len(), b);
 
     let k = a_commitment_batch.first().unwrap().len();
     assert!(a_commitment_batch.iter().all(|com| com.len() == k));
     assert!(b_commitment_batch.iter().all(|com| com.len() == k));
-    assert!(z_commitment_batch.iter().all(|com| com.len() == k));
+    // The mask can have the same degree as the product, so that it hides all of its coefficients.
+    assert!(z_commitment_batch.iter().all(|com| com.len() <= 2 * k - 1));
 
     let index = message_batch[0].vshare.share.index;
     if message_batch
         .iter()
         .any(|msg| msg.vshare.share.index != index)
     {
-        return Err(InconsistentShares);
+        return Err(MulOpenErr::InconsistentShares);
     }
 
-    if !message_batch
-        .iter()
-        .zip(z_commitment_batch.iter())
-        .all(|(message, z_commitment)| {
-            let mut com = vss::poly_eval_gej_slice_in_exponent(&z_commitment, &index);
-            com.add_assign(&message.commitment);
-            ped::ped_commit(h, &message.vshare.share.value, &message.vshare.decommitment) == com
-        })
-    {
-        return Err(InvalidShares);
-    }
-
-    if !message_batch
-        .iter()
-        .zip(a_commitment_batch.iter().zip(b_commitment_batch.iter()))
-        .all(|(message, (a_commitment, b_commitment))| {
-            let a = vss::poly_eval_gej_slice_in_exponent(&a_commitment, &index);
-            let b = vss::poly_eval_gej_slice_in_exponent(&b_commitment, &index);
-            verify(&message.proof, &a, &b, &message.commitment, h)
-        })
-    {
-        return Err(InvalidZKP);
+    for (message, ((a_commitment, b_commitment), z_commitment)) in message_batch.iter().zip(
+        a_commitment_batch
+            .iter()
+            .zip(b_commitment_batch.iter())
+            .zip(z_commitment_batch.iter()),
+    ) {
+        check_message(message, a_commitment, b_commitment, z_commitment, h)?;
     }
+    Ok(())
+}
 
+// Adds a batch that has already been checked, and returns the opened products once there are
+// enough shares to interpolate the product sharings, which have threshold 2k - 1.
+fn accept_message_batch(
+    state: &mut Vec<Vec<Share>>,
+    message_batch: &[Message],
+    k: usize,
+) -> Option<Vec<Scalar>> {
     for (buf, message) in state.iter_mut().zip(message_batch.iter()) {
         buf.push(message.vshare.share);
     }
 
     let threshold = 2 * k - 1;
     if state.first().expect("state should not be empty").len() == threshold {
-        let mut secrets = Vec::with_capacity(b);
+        let mut secrets = Vec::with_capacity(state.len());
         for buf in state {
             secrets.push(sss::interpolate_shares_at_zero(buf.iter()));
         }
-        return Ok(Some(secrets));
+        return Some(secrets);
     }
 
-    Ok(None)
+    None
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Proof {
     message: zkp::Message,
     response: Response,
 }
 
+impl Codec for Proof {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.message.encode(buf);
+        self.response.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Proof {
+            message: zkp::Message::decode(bs)?,
+            response: Response::decode(bs)?,
+        })
+    }
+}
+
 pub fn prove(witness: &Witness, a: &Gej, b: &Gej, c: &Gej, h: &Gej) -> Proof {
     let (message, nonce) = zkp::message_and_nonce(b, h);
     let challenge = compute_challenge(&message, a, b, c);
@@ -188,6 +273,143 @@ fn compute_challenge(message: &zkp::Message, a: &Gej, b: &Gej, c: &Gej) -> Scala
     challenge
 }
 
+// Opens the products of a batch of pairs of sharings by broadcasting the product shares together
+// with their proofs.
+pub struct Machine {
+    params: Parameters,
+    a_commitment_batch: Vec<SharingCommitment>,
+    b_commitment_batch: Vec<SharingCommitment>,
+    z_commitment_batch: Vec<SharingCommitment>,
+    message_batch: Vec<Message>,
+    accepted: Accepted<Vec<Message>>,
+    state: Vec<Vec<Share>>,
+    output: Option<Vec<Scalar>>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        a_vshare_batch: Vec<VShare>,
+        b_vshare_batch: Vec<VShare>,
+        z_vshare_batch: Vec<VShare>,
+        a_commitment_batch: Vec<SharingCommitment>,
+        b_commitment_batch: Vec<SharingCommitment>,
+        z_commitment_batch: Vec<SharingCommitment>,
+    ) -> Result<Self, ParamsError> {
+        let k = match a_commitment_batch.first() {
+            Some(commitment) => commitment.len(),
+            None => return Err(ParamsError::EmptyBatch),
+        };
+        params.check_mulopen_threshold(k)?;
+        Ok(Machine::new_unchecked(
+            params,
+            a_vshare_batch,
+            b_vshare_batch,
+            z_vshare_batch,
+            a_commitment_batch,
+            b_commitment_batch,
+            z_commitment_batch,
+        ))
+    }
+
+    // Skips the checks in new, for composite protocols that have already checked the batch size
+    // and threshold of sharings that they only compute later.
+    pub(crate) fn new_unchecked(
+        params: Parameters,
+        a_vshare_batch: Vec<VShare>,
+        b_vshare_batch: Vec<VShare>,
+        z_vshare_batch: Vec<VShare>,
+        a_commitment_batch: Vec<SharingCommitment>,
+        b_commitment_batch: Vec<SharingCommitment>,
+        z_commitment_batch: Vec<SharingCommitment>,
+    ) -> Self {
+        let b = a_commitment_batch.len();
+        let message_batch =
+            initial_message_batch(a_vshare_batch, b_vshare_batch, z_vshare_batch, &params.h);
+        let mut state = Vec::with_capacity(b);
+        state.resize_with(b, Vec::new);
+        Machine {
+            params,
+            a_commitment_batch,
+            b_commitment_batch,
+            z_commitment_batch,
+            message_batch,
+            accepted: Accepted::new(),
+            state,
+            output: None,
+        }
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Vec<Message>;
+    type Output = Vec<Scalar>;
+    type Error = MulOpenErr;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<Message>>> {
+        vec![Outgoing::broadcast(self.message_batch.clone())]
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message_batch: Vec<Message>,
+        _outgoing: &mut Vec<Outgoing<Vec<Message>>>,
+    ) -> Result<(), Blame<MulOpenErr>> {
+        if message_batch.len() != self.state.len()
+            || message_batch
+                .iter()
+                .any(|message| &message.vshare.share.index != from)
+        {
+            return Err(Blame::new(*from, MulOpenErr::InconsistentShares));
+        }
+        if !self.params.indices.contains(from) {
+            return Err(Blame::new(*from, MulOpenErr::InvalidIndex));
+        }
+        // Invalid batches are reported as such even if the sender has sent a valid batch before, so
+        // that only two valid batches count as equivocation.
+        check_message_batch(
+            &message_batch,
+            &self.a_commitment_batch,
+            &self.b_commitment_batch,
+            &self.z_commitment_batch,
+            &self.params.h,
+        )
+        .map_err(|e| Blame::new(*from, e))?;
+        match self.accepted.check(from, &message_batch) {
+            Seen::New => (),
+            Seen::Same => return Err(Blame::new(*from, MulOpenErr::DuplicateIndex)),
+            Seen::Different(accepted) => {
+                return Err(Blame::new(
+                    *from,
+                    MulOpenErr::Equivocation(accepted, message_batch),
+                ))
+            }
+        }
+        // Batches are remembered even once the products are opened, so that equivocation can
+        // always be detected.
+        self.accepted.insert(*from, message_batch.clone());
+        if self.output.is_some() {
+            return Ok(());
+        }
+        let k = self.a_commitment_batch[0].len();
+        self.output = accept_message_batch(&mut self.state, &message_batch, k);
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<Vec<Scalar>> {
+        self.output.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,4 +494,103 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn resent_and_conflicting_batches_are_detected() {
+        let n = 5;
+        let k = 2;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (a_shares_by_player, a_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (b_shares_by_player, b_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (z_shares_by_player, z_commitments) =
+            testutil::zero_sharing_batch(n, k, b, &indices, &h);
+        let mut machine = Machine::new(
+            Parameters::new(indices.clone(), indices[0], h).unwrap(),
+            a_shares_by_player[0].clone(),
+            b_shares_by_player[0].clone(),
+            z_shares_by_player[0].clone(),
+            a_commitments,
+            b_commitments,
+            z_commitments,
+        )
+        .unwrap();
+
+        // The product commitments are randomised, so computing the messages again gives a
+        // different batch that is also valid.
+        let message_batch = |i: usize| {
+            initial_message_batch(
+                a_shares_by_player[i].clone(),
+                b_shares_by_player[i].clone(),
+                z_shares_by_player[i].clone(),
+                &h,
+            )
+        };
+        let mut handle = |i: usize, message_batch: &Vec<Message>| {
+            machine
+                .handle_message(&indices[i], message_batch.clone(), &mut Vec::new())
+                .map_err(|blame| blame.error)
+        };
+        let first = message_batch(1);
+        let second = message_batch(1);
+        let mut invalid = second.clone();
+        invalid[0].vshare.share.value = Scalar::new_random_using_thread_rng();
+        assert_eq!(handle(1, &first), Ok(()));
+        assert_eq!(handle(1, &first), Err(MulOpenErr::DuplicateIndex));
+        assert_eq!(handle(1, &invalid), Err(MulOpenErr::InvalidShares));
+        assert_eq!(
+            handle(1, &second),
+            Err(MulOpenErr::Equivocation(first.clone(), second.clone()))
+        );
+
+        // Conflicting batches are still detected once the products have been opened.
+        let first = message_batch(2);
+        assert_eq!(handle(2, &first), Ok(()));
+        assert_eq!(handle(3, &message_batch(3)), Ok(()));
+        assert!(machine.is_done());
+        let second = message_batch(2);
+        assert_eq!(
+            machine
+                .handle_message(&indices[2], second.clone(), &mut Vec::new())
+                .map_err(|blame| blame.error),
+            Err(MulOpenErr::Equivocation(first, second))
+        );
+    }
+
+    #[test]
+    fn empty_batches_and_large_thresholds_are_rejected() {
+        let n = 5;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let new = |vshares: Vec<VShare>, commitments: Vec<SharingCommitment>| {
+            Machine::new(
+                params.clone(),
+                vshares.clone(),
+                vshares.clone(),
+                vshares,
+                commitments.clone(),
+                commitments.clone(),
+                commitments,
+            )
+            .err()
+        };
+
+        let (vshares_by_player, commitments, _, _) =
+            testutil::random_sharing_batch(n, 3, b, &indices, &h);
+        assert_eq!(new(vshares_by_player[0].clone(), commitments), None);
+        assert_eq!(new(Vec::new(), Vec::new()), Some(ParamsError::EmptyBatch));
+        let (vshares_by_player, commitments, _, _) =
+            testutil::random_sharing_batch(n, 4, b, &indices, &h);
+        assert_eq!(
+            new(vshares_by_player[0].clone(), commitments),
+            Some(ParamsError::TooFewPlayers)
+        );
+    }
 }
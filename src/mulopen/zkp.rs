@@ -1,8 +1,11 @@
+#[cfg(feature = "zeroize")]
+use crate::secret::Wipe;
+use crate::transport::codec::Codec;
 use secp256k1::group::Gej;
 use secp256k1::scalar::Scalar;
 use shamir::ped;
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Message {
     m: Gej,
     m1: Gej,
@@ -17,6 +20,22 @@ impl Message {
     }
 }
 
+impl Codec for Message {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.m.encode(buf);
+        self.m1.encode(buf);
+        self.m2.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Message {
+            m: Gej::decode(bs)?,
+            m1: Gej::decode(bs)?,
+            m2: Gej::decode(bs)?,
+        })
+    }
+}
+
 pub struct Nonce {
     d: Scalar,
     s: Scalar,
@@ -25,7 +44,7 @@ pub struct Nonce {
     s2: Scalar,
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Response {
     y: Scalar,
     w: Scalar,
@@ -34,6 +53,24 @@ pub struct Response {
     w2: Scalar,
 }
 
+impl Codec for Response {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for x in [&self.y, &self.w, &self.z, &self.w1, &self.w2].iter() {
+            x.encode(buf);
+        }
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Response {
+            y: Scalar::decode(bs)?,
+            w: Scalar::decode(bs)?,
+            z: Scalar::decode(bs)?,
+            w1: Scalar::decode(bs)?,
+            w2: Scalar::decode(bs)?,
+        })
+    }
+}
+
 pub struct Witness {
     alpha: Scalar,
     beta: Scalar,
@@ -42,6 +79,17 @@ pub struct Witness {
     tau: Scalar,
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for Nonce {
+    fn drop(&mut self) {
+        self.d.wipe();
+        self.s.wipe();
+        self.x.wipe();
+        self.s1.wipe();
+        self.s2.wipe();
+    }
+}
+
 impl Witness {
     pub fn new(alpha: Scalar, beta: Scalar, rho: Scalar, sigma: Scalar, tau: Scalar) -> Self {
         Witness {
@@ -54,6 +102,17 @@ impl Witness {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for Witness {
+    fn drop(&mut self) {
+        self.alpha.wipe();
+        self.beta.wipe();
+        self.rho.wipe();
+        self.sigma.wipe();
+        self.tau.wipe();
+    }
+}
+
 pub fn message_and_nonce(b: &Gej, h: &Gej) -> (Message, Nonce) {
     let d = Scalar::new_random_using_thread_rng();
     let s = Scalar::new_random_using_thread_rng();
@@ -2,7 +2,11 @@ use crate::util;
 use secp256k1::scalar::Scalar;
 use shamir::vss::{self, SharingCommitment, VShare};
 
+use crate::equivocation::{Accepted, Seen};
 use crate::params::Parameters;
+use crate::protocol::{Blame, Outgoing, Protocol};
+#[cfg(feature = "zeroize")]
+use crate::secret::Wipe;
 
 pub type OpenResult = Result<Option<Vec<(Scalar, Scalar)>>, OpenError>;
 
@@ -13,6 +17,8 @@ pub enum OpenError {
     InvalidShare,
     InconsistentIndices,
     InvalidBatchSize,
+    // Two different share batches from the same player, in the order that they were received.
+    Equivocation(Vec<VShare>, Vec<VShare>),
 }
 
 pub struct InstanceParams {
@@ -32,9 +38,9 @@ impl InstanceParams {
     }
 }
 
-#[derive(Clone)]
 pub struct State {
     vshare_bufs: Vec<Vec<VShare>>,
+    accepted: Accepted<Vec<VShare>>,
 }
 
 impl State {
@@ -44,7 +50,10 @@ impl State {
         for _ in 0..b {
             vshare_bufs.push(Vec::with_capacity(inst_params.threshold()));
         }
-        State { vshare_bufs }
+        State {
+            vshare_bufs,
+            accepted: Accepted::new(),
+        }
     }
 
     pub fn contains_vshare_with_index(&self, index: &Scalar) -> bool {
@@ -92,18 +101,25 @@ impl State {
             return Err(InconsistentIndices);
         }
 
-        let index = &vshare_batch[0].share.index;
-        if !params.indices.contains(index) {
+        let index = vshare_batch[0].share.index;
+        if !params.indices.contains(&index) {
             return Err(InvalidIndex);
         }
-        if self.contains_vshare_with_index(index) {
-            return Err(DuplicateIndex);
-        }
+        // Invalid shares are reported as such even if the sender has sent a valid batch before, so
+        // that only two valid batches count as equivocation.
         for (vshare, commitment) in vshare_batch.iter().zip(inst_params.commitment_batch.iter()) {
             if !vss::vshare_is_valid(vshare, commitment, &params.h) {
                 return Err(InvalidShare);
             }
         }
+        match self.accepted.check(&index, &vshare_batch) {
+            Seen::New => (),
+            Seen::Same => return Err(DuplicateIndex),
+            Seen::Different(accepted) => return Err(Equivocation(accepted, vshare_batch)),
+        }
+        // Share batches are remembered even once there are enough to reconstruct, so that
+        // equivocation can always be detected.
+        self.accepted.insert(index, vshare_batch.clone());
 
         // Add the share batch to the buffer only if we don't have enough for reconstruction, and only
         // return the reconstructed values upon adding the last share batch.
@@ -121,6 +137,85 @@ impl State {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for State {
+    fn drop(&mut self) {
+        self.vshare_bufs.wipe();
+        self.accepted.wipe();
+    }
+}
+
+// Opens a batch of sharings by broadcasting the player's shares.
+pub struct Machine {
+    params: Parameters,
+    inst_params: InstanceParams,
+    state: State,
+    vshare_batch: Vec<VShare>,
+    output: Option<Vec<(Scalar, Scalar)>>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        commitment_batch: Vec<SharingCommitment>,
+        vshare_batch: Vec<VShare>,
+    ) -> Self {
+        let inst_params = InstanceParams::new(commitment_batch);
+        let state = State::new(&inst_params);
+        Machine {
+            params,
+            inst_params,
+            state,
+            vshare_batch,
+            output: None,
+        }
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Vec<VShare>;
+    type Output = Vec<(Scalar, Scalar)>;
+    type Error = OpenError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<VShare>>> {
+        vec![Outgoing::broadcast(self.vshare_batch.clone())]
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        vshare_batch: Vec<VShare>,
+        _outgoing: &mut Vec<Outgoing<Vec<VShare>>>,
+    ) -> Result<(), Blame<OpenError>> {
+        if vshare_batch
+            .iter()
+            .any(|vshare| &vshare.share.index != from)
+        {
+            return Err(Blame::new(*from, OpenError::InconsistentIndices));
+        }
+        let res = self
+            .state
+            .handle_vshare_batch(&self.inst_params, &self.params, vshare_batch)
+            .map_err(|e| Blame::new(*from, e))?;
+        if res.is_some() {
+            self.output = res;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<Vec<(Scalar, Scalar)>> {
+        self.output.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,7 +236,7 @@ mod tests {
             testutil::random_sharing_batch(n, k, b, &indices, &h);
 
         let inst_params = InstanceParams::new(commitment_batch);
-        let params = Parameters { indices, index, h };
+        let params = Parameters::new(indices, index, h).unwrap();
         let state = State::new(&inst_params);
 
         vshare_batches
@@ -161,4 +256,54 @@ mod tests {
                 state
             });
     }
+
+    #[test]
+    fn equivocation_is_detected() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let index = indices[0];
+        // The discrete log of h is known, so that a different batch that is still valid can be
+        // made.
+        let log_h = Scalar::new_random_using_thread_rng();
+        let mut h = Gej::default();
+        h.scalar_base_mul(&log_h);
+        let mut log_h_inv = log_h;
+        log_h_inv.inverse_assign();
+        let (vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        let inst_params = InstanceParams::new(commitment_batch);
+        let params = Parameters::new(indices, index, h).unwrap();
+        let mut state = State::new(&inst_params);
+
+        // The last player sends after there are already enough shares to reconstruct.
+        for vshare_batch in vshare_batches.iter() {
+            assert!(state
+                .handle_vshare_batch(&inst_params, &params, vshare_batch.clone())
+                .is_ok());
+        }
+        for vshare_batch in [&vshare_batches[0], &vshare_batches[n - 1]].iter() {
+            let mut invalid = (*vshare_batch).clone();
+            invalid[1].decommitment = Scalar::new_random_using_thread_rng();
+            let offset = Scalar::new_random_using_thread_rng();
+            let mut different = (*vshare_batch).clone();
+            different[1].share.value = &different[1].share.value + &offset;
+            different[1].decommitment = different[1].decommitment - (&offset * &log_h_inv);
+            assert_eq!(
+                state.handle_vshare_batch(&inst_params, &params, (*vshare_batch).clone()),
+                Err(OpenError::DuplicateIndex)
+            );
+            assert_eq!(
+                state.handle_vshare_batch(&inst_params, &params, invalid),
+                Err(OpenError::InvalidShare)
+            );
+            assert_eq!(
+                state.handle_vshare_batch(&inst_params, &params, different.clone()),
+                Err(OpenError::Equivocation((*vshare_batch).clone(), different))
+            );
+        }
+    }
 }
@@ -1,8 +1,267 @@
+use crate::hash_to_curve::hash_to_curve;
+use crate::util;
 use secp256k1::group::Gej;
 use secp256k1::scalar::Scalar;
+use shamir::vss::SharingCommitment;
 
+pub const H_DOMAIN: &[u8] = b"mpc-rs/pedersen-h";
+
+// Multiples of G up to this in absolute value are rejected for h, since they are likely to have
+// been used as placeholders and their discrete logarithms are known.
+const SMALL_MULTIPLES: u64 = 16;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ParamsError {
+    EmptyIndices,
+    ZeroIndex,
+    DuplicateIndex,
+    IndexOutOfRange,
+    HIsInfinity,
+    HIsKnownMultipleOfG,
+    InvalidThreshold,
+    TooFewPlayers,
+    EmptyBatch,
+    NotADealer,
+    IndexInNewCommittee,
+    LostIndexIsHelper,
+    InvalidCommitmentThreshold,
+    NotEnoughTriples,
+    WrongNumberOfKeys,
+}
+
+// The parameters of a player. These can only be constructed from outside of the crate by
+// Parameters::new, so the protocols can assume that they are valid.
+#[derive(Clone)]
 pub struct Parameters {
-    pub indices: Vec<Scalar>,
-    pub index: Scalar,
-    pub h: Gej,
+    pub(crate) indices: Vec<Scalar>,
+    pub(crate) index: Scalar,
+    pub(crate) h: Gej,
+}
+
+impl Parameters {
+    pub fn new(indices: Vec<Scalar>, index: Scalar, h: Gej) -> Result<Self, ParamsError> {
+        use ParamsError::*;
+
+        check_indices(&indices)?;
+        if !indices.contains(&index) {
+            return Err(IndexOutOfRange);
+        }
+        if h.is_infinity() {
+            return Err(HIsInfinity);
+        }
+        if is_small_multiple_of_g(&h) {
+            return Err(HIsKnownMultipleOfG);
+        }
+        Ok(Parameters { indices, index, h })
+    }
+
+    // Constructs the parameters with h derived from a public seed, see h_from_seed.
+    pub fn new_from_seed(
+        indices: Vec<Scalar>,
+        index: Scalar,
+        seed: &[u8],
+    ) -> Result<Self, ParamsError> {
+        Parameters::new(indices, index, h_from_seed(seed))
+    }
+
+    pub fn indices(&self) -> &[Scalar] {
+        &self.indices
+    }
+
+    pub fn index(&self) -> Scalar {
+        self.index
+    }
+
+    pub fn h(&self) -> Gej {
+        self.h
+    }
+
+    pub fn n(&self) -> usize {
+        self.indices.len()
+    }
+
+    // Checks that a sharing with threshold k, i.e. of a polynomial of degree k - 1, can be
+    // reconstructed by the players.
+    pub fn check_threshold(&self, k: usize) -> Result<(), ParamsError> {
+        if k == 0 {
+            return Err(ParamsError::InvalidThreshold);
+        }
+        if k > self.n() {
+            return Err(ParamsError::TooFewPlayers);
+        }
+        Ok(())
+    }
+
+    // The product of two sharings with threshold k has threshold 2k - 1, so opening it in mulopen
+    // needs n >= 2k - 1.
+    pub fn check_mulopen_threshold(&self, k: usize) -> Result<(), ParamsError> {
+        self.check_threshold(k)?;
+        self.check_threshold(2 * k - 1)
+    }
+
+    // The number of shares that rkpg waits for before reconstructing, n - k + 1, which needs to be
+    // enough to determine a polynomial of degree k - 1.
+    pub fn rkpg_threshold(&self, k: usize) -> Result<usize, ParamsError> {
+        self.check_threshold(k)?;
+        let available = self.n() - k + 1;
+        if available < k {
+            return Err(ParamsError::TooFewPlayers);
+        }
+        Ok(available)
+    }
+}
+
+// Checks the indices of a committee, for when a player needs the indices of a committee that they
+// are not a member of.
+pub(crate) fn check_indices(indices: &[Scalar]) -> Result<(), ParamsError> {
+    use ParamsError::*;
+
+    if indices.is_empty() {
+        return Err(EmptyIndices);
+    }
+    if indices.iter().any(Scalar::is_zero) {
+        return Err(ZeroIndex);
+    }
+    for (i, player) in indices.iter().enumerate() {
+        if indices[i + 1..].contains(player) {
+            return Err(DuplicateIndex);
+        }
+    }
+    Ok(())
+}
+
+// Checks that every commitment in a batch is for a sharing with threshold k, for inputs whose
+// degree matters for privacy, such as masks.
+pub fn check_commitment_threshold(
+    commitment_batch: &[SharingCommitment],
+    k: usize,
+) -> Result<(), ParamsError> {
+    if commitment_batch
+        .iter()
+        .any(|commitment| commitment.len() != k)
+    {
+        return Err(ParamsError::InvalidCommitmentThreshold);
+    }
+    Ok(())
+}
+
+// Derives the Pedersen base h by hashing a public seed onto the curve. Nobody knows the discrete
+// logarithm of the result with respect to G, which is what the hiding and binding of Pedersen
+// commitments rely on, and anyone that knows the seed can check this with verify_h.
+pub fn h_from_seed(seed: &[u8]) -> Gej {
+    hash_to_curve(H_DOMAIN, seed)
+}
+
+// Returns true if h was derived from the seed by h_from_seed.
+pub fn verify_h(seed: &[u8], h: &Gej) -> bool {
+    &h_from_seed(seed) == h
+}
+
+fn is_small_multiple_of_g(h: &Gej) -> bool {
+    let mut multiple = Gej::default();
+    for m in 1..=SMALL_MULTIPLES {
+        let mut scalar = util::scalar_from_u64(m);
+        multiple.scalar_base_mul(&scalar);
+        if &multiple == h {
+            return true;
+        }
+        scalar.negate_assign_mut();
+        multiple.scalar_base_mul(&scalar);
+        if &multiple == h {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::scalar;
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        use ParamsError::*;
+
+        let indices = scalar::random_scalars_using_thread_rng(5);
+        let h = Gej::new_random_using_thread_rng();
+        let new = |indices: &[Scalar], index: Scalar, h: Gej| {
+            Parameters::new(indices.to_vec(), index, h).err()
+        };
+
+        assert_eq!(new(&indices, indices[2], h), None);
+        assert_eq!(new(&[], indices[0], h), Some(EmptyIndices));
+        let mut with_zero = indices.clone();
+        with_zero[1] = Scalar::zero();
+        assert_eq!(new(&with_zero, indices[0], h), Some(ZeroIndex));
+        let mut with_duplicate = indices.clone();
+        with_duplicate[4] = indices[1];
+        assert_eq!(new(&with_duplicate, indices[0], h), Some(DuplicateIndex));
+        let other = Scalar::new_random_using_thread_rng();
+        assert_eq!(new(&indices, other, h), Some(IndexOutOfRange));
+
+        assert_eq!(
+            new(&indices, indices[0], Gej::infinity()),
+            Some(HIsInfinity)
+        );
+        let mut three_g = Gej::default();
+        three_g.scalar_base_mul(&util::scalar_from_u64(3));
+        assert_eq!(
+            new(&indices, indices[0], three_g),
+            Some(HIsKnownMultipleOfG)
+        );
+        let mut minus_g = Gej::default();
+        let mut minus_one = util::scalar_from_u64(1);
+        minus_one.negate_assign_mut();
+        minus_g.scalar_base_mul(&minus_one);
+        assert_eq!(
+            new(&indices, indices[0], minus_g),
+            Some(HIsKnownMultipleOfG)
+        );
+    }
+
+    #[test]
+    fn h_can_be_verified_from_the_seed() {
+        let indices = scalar::random_scalars_using_thread_rng(5);
+        let params = Parameters::new_from_seed(indices.clone(), indices[0], b"seed").unwrap();
+
+        assert!(verify_h(b"seed", &params.h()));
+        assert!(!verify_h(b"other seed", &params.h()));
+        assert!(!verify_h(b"seed", &Gej::new_random_using_thread_rng()));
+    }
+
+    #[test]
+    fn h_from_seed_matches_known_answer() {
+        // Pins the derivation so that h does not change between versions. The first two counters
+        // do not give a point on the curve for this seed, so the counter is also covered.
+        let mut bs = [0_u8; 33];
+        h_from_seed(b"mpc-rs").put_bytes(&mut bs);
+        let expected = [
+            0x02, 0x28, 0x9e, 0xab, 0xda, 0x4f, 0x6c, 0x40, 0xb2, 0x56, 0x50, 0x7b, 0x9e, 0x0b,
+            0x83, 0xd7, 0xb2, 0x5f, 0x94, 0x2e, 0xb4, 0x87, 0x87, 0xa7, 0x6a, 0x84, 0x3c, 0x35,
+            0xb9, 0xe2, 0x3e, 0xc0, 0xe9,
+        ];
+        assert_eq!(bs[..], expected[..]);
+    }
+
+    #[test]
+    fn thresholds_are_checked() {
+        let indices = scalar::random_scalars_using_thread_rng(5);
+        let h = Gej::new_random_using_thread_rng();
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+
+        assert_eq!(
+            params.check_threshold(0),
+            Err(ParamsError::InvalidThreshold)
+        );
+        assert_eq!(params.check_threshold(5), Ok(()));
+        assert_eq!(params.check_threshold(6), Err(ParamsError::TooFewPlayers));
+        assert_eq!(params.check_mulopen_threshold(3), Ok(()));
+        assert_eq!(
+            params.check_mulopen_threshold(4),
+            Err(ParamsError::TooFewPlayers)
+        );
+        assert_eq!(params.rkpg_threshold(3), Ok(3));
+        assert_eq!(params.rkpg_threshold(4), Err(ParamsError::TooFewPlayers));
+    }
 }
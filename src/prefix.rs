@@ -0,0 +1,522 @@
+use crate::beaver::{self, Triple, TripleCommitment};
+use crate::inv;
+use crate::mulopen::{self, MulOpenErr};
+use crate::open::{self, OpenError};
+use crate::params::{self, Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::shared::{Linear, SharedScalarBatch};
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{SharingCommitment, VShare};
+
+// Constant round prefix products (Bar-Ilan and Beaver) for a vector x_1, ..., x_m of non-zero
+// shared values. With random masks r_1, ..., r_m and r_0 = 1, the values
+//
+//     m_i = r_{i-1} * x_i * r_i^-1
+//
+// are opened, so that x_1 * ... * x_i = m_1 * ... * m_i * r_i can be computed locally. This takes
+// two rounds:
+//
+// 1. Open u_i = r_i * s_i with mulopen for random s_i, and in parallel compute sharings of
+//    w_i = r_{i-1} * s_i using Beaver triples. Then r_{i-1} * r_i^-1 = w_i * u_i^-1 (and
+//    r_1^-1 = s_1 * u_1^-1).
+// 2. Open m_i with mulopen.
+
+pub fn masks_message_batch(
+    r_vshare_batch: Vec<VShare>,
+    s_vshare_batch: Vec<VShare>,
+    z_vshare_batch: Vec<VShare>,
+    h: &Gej,
+) -> Vec<mulopen::Message> {
+    mulopen::initial_message_batch(r_vshare_batch, s_vshare_batch, z_vshare_batch, h)
+}
+
+pub fn handle_masks_message_batch(
+    state: &mut Vec<Vec<Share>>,
+    message_batch: Vec<mulopen::Message>,
+    r_commitment_batch: &[SharingCommitment],
+    s_commitment_batch: &[SharingCommitment],
+    z_commitment_batch: &[SharingCommitment],
+    h: &Gej,
+) -> Result<Option<Vec<Scalar>>, MulOpenErr> {
+    mulopen::handle_message_batch(
+        state,
+        message_batch,
+        r_commitment_batch,
+        s_commitment_batch,
+        z_commitment_batch,
+        h,
+    )
+}
+
+pub fn chain_masked_vshare_batch(
+    r_vshare_batch: &[VShare],
+    s_vshare_batch: &[VShare],
+    triple_batch: &[Triple],
+) -> Vec<VShare> {
+    let m = r_vshare_batch.len();
+    assert_eq!(s_vshare_batch.len(), m);
+    beaver::masked_vshare_batch(&r_vshare_batch[..m - 1], &s_vshare_batch[1..], triple_batch)
+}
+
+pub fn chain_masked_commitment_batch(
+    r_commitment_batch: &[SharingCommitment],
+    s_commitment_batch: &[SharingCommitment],
+    triple_commitment_batch: &[TripleCommitment],
+) -> Vec<SharingCommitment> {
+    let m = r_commitment_batch.len();
+    assert_eq!(s_commitment_batch.len(), m);
+    beaver::masked_commitment_batch(
+        &r_commitment_batch[..m - 1],
+        &s_commitment_batch[1..],
+        triple_commitment_batch,
+    )
+}
+
+pub fn mask_ratio_vshare_batch(
+    s_vshare_batch: &[VShare],
+    w_vshare_batch: Vec<VShare>,
+    opened_masks: Vec<Scalar>,
+) -> Vec<VShare> {
+    let mut numerators = Vec::with_capacity(opened_masks.len());
+    numerators.push(s_vshare_batch[0]);
+    numerators.extend(w_vshare_batch);
+    inv::inv_transform_mulopen_output(numerators, opened_masks)
+}
+
+pub fn mask_ratio_commitment_batch(
+    s_commitment_batch: &[SharingCommitment],
+    w_commitment_batch: Vec<SharingCommitment>,
+    opened_masks: Vec<Scalar>,
+) -> Vec<SharingCommitment> {
+    let mut numerators = Vec::with_capacity(opened_masks.len());
+    numerators.push(s_commitment_batch[0].clone());
+    numerators.extend(w_commitment_batch);
+    inv::inv_transform_mulopen_output_commitment(numerators, opened_masks)
+}
+
+pub fn masked_inputs_message_batch(
+    ratio_vshare_batch: Vec<VShare>,
+    x_vshare_batch: Vec<VShare>,
+    z_vshare_batch: Vec<VShare>,
+    h: &Gej,
+) -> Vec<mulopen::Message> {
+    mulopen::initial_message_batch(ratio_vshare_batch, x_vshare_batch, z_vshare_batch, h)
+}
+
+pub fn handle_masked_inputs_message_batch(
+    state: &mut Vec<Vec<Share>>,
+    message_batch: Vec<mulopen::Message>,
+    ratio_commitment_batch: &[SharingCommitment],
+    x_commitment_batch: &[SharingCommitment],
+    z_commitment_batch: &[SharingCommitment],
+    h: &Gej,
+) -> Result<Option<Vec<Scalar>>, MulOpenErr> {
+    mulopen::handle_message_batch(
+        state,
+        message_batch,
+        ratio_commitment_batch,
+        x_commitment_batch,
+        z_commitment_batch,
+        h,
+    )
+}
+
+fn running_products(opened_values: &[Scalar]) -> Vec<Scalar> {
+    let mut products = Vec::with_capacity(opened_values.len());
+    let mut acc = opened_values[0];
+    products.push(acc);
+    for value in opened_values.iter().skip(1) {
+        acc = &acc * value;
+        products.push(acc);
+    }
+    products
+}
+
+pub fn output_vshare_batch(r_vshare_batch: Vec<VShare>, opened_values: &[Scalar]) -> Vec<VShare> {
+    output_batch(r_vshare_batch, opened_values)
+}
+
+pub fn output_commitment_batch(
+    r_commitment_batch: &[SharingCommitment],
+    opened_values: &[Scalar],
+) -> Vec<SharingCommitment> {
+    output_batch(r_commitment_batch.to_vec(), opened_values)
+}
+
+fn output_batch<T: Linear>(r_batch: Vec<T>, opened_values: &[Scalar]) -> Vec<T> {
+    assert_eq!(r_batch.len(), opened_values.len());
+    let mut output_batch = r_batch;
+    for (output, product) in output_batch
+        .iter_mut()
+        .zip(running_products(opened_values).iter())
+    {
+        output.scale_assign_mut(product);
+    }
+    output_batch
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PrefixError {
+    Params(ParamsError),
+    InvalidBatchSize,
+    MulOpen(MulOpenErr),
+    Open(OpenError),
+    UnexpectedMessage,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Masks(Vec<mulopen::Message>),
+    ChainMasks(Vec<VShare>),
+    MaskedInputs(Vec<mulopen::Message>),
+}
+
+// Runs both rounds of the prefix products for one player. The random sharings r and s and the
+// zero sharings z1 and z2 (for the two mulopens) each have the same length m as the input x, and
+// m - 1 triples are needed. Masked inputs that arrive before the first round has finished are kept
+// until it has.
+pub struct Machine {
+    params: Parameters,
+    x: SharedScalarBatch,
+    r: SharedScalarBatch,
+    s: SharedScalarBatch,
+    z2: SharedScalarBatch,
+    triple_batch: Vec<Triple>,
+    triple_commitment_batch: Vec<TripleCommitment>,
+    masks: mulopen::Machine,
+    chain_masks: Option<open::Machine>,
+    masked_inputs: Option<mulopen::Machine>,
+    pending: Vec<(Scalar, Vec<mulopen::Message>)>,
+    output: Option<(Vec<VShare>, Vec<SharingCommitment>)>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        x: SharedScalarBatch,
+        r: SharedScalarBatch,
+        s: SharedScalarBatch,
+        z1: SharedScalarBatch,
+        z2: SharedScalarBatch,
+        (triple_batch, triple_commitment_batch): (Vec<Triple>, Vec<TripleCommitment>),
+    ) -> Result<Self, PrefixError> {
+        let m = x.len();
+        if m == 0 {
+            return Err(PrefixError::Params(ParamsError::EmptyBatch));
+        }
+        if [r.len(), s.len(), z1.len(), z2.len()]
+            .iter()
+            .any(|len| *len != m)
+            || triple_batch.len() != m - 1
+            || triple_commitment_batch.len() != m - 1
+        {
+            return Err(PrefixError::InvalidBatchSize);
+        }
+        // The masked inputs are opened with a mulopen whose first operand has the threshold of s,
+        // so every threshold is checked here rather than when the second round starts.
+        let k = r.commitments()[0].len();
+        params
+            .check_mulopen_threshold(k)
+            .map_err(PrefixError::Params)?;
+        for (commitment_batch, threshold) in [
+            (x.commitments(), k),
+            (r.commitments(), k),
+            (s.commitments(), k),
+            (z1.commitments(), 2 * k - 1),
+            (z2.commitments(), 2 * k - 1),
+        ]
+        .iter()
+        {
+            params::check_commitment_threshold(commitment_batch, *threshold)
+                .map_err(PrefixError::Params)?;
+        }
+
+        let (z1_vshare_batch, z1_commitment_batch) = z1.into_parts();
+        let masks = mulopen::Machine::new(
+            params.clone(),
+            r.vshares(),
+            s.vshares(),
+            z1_vshare_batch,
+            r.commitments(),
+            s.commitments(),
+            z1_commitment_batch,
+        )
+        .map_err(PrefixError::Params)?;
+        // There is nothing to open for the chain masks when there is only one input.
+        let chain_masks = if m > 1 {
+            Some(open::Machine::new(
+                params.clone(),
+                chain_masked_commitment_batch(
+                    &r.commitments(),
+                    &s.commitments(),
+                    &triple_commitment_batch,
+                ),
+                chain_masked_vshare_batch(&r.vshares(), &s.vshares(), &triple_batch),
+            ))
+        } else {
+            None
+        };
+        Ok(Machine {
+            params,
+            x,
+            r,
+            s,
+            z2,
+            triple_batch,
+            triple_commitment_batch,
+            masks,
+            chain_masks,
+            masked_inputs: None,
+            pending: Vec::new(),
+            output: None,
+        })
+    }
+
+    // Starts the second round once both of the openings in the first round have finished.
+    fn start_masked_inputs(
+        &mut self,
+        outgoing: &mut Vec<Outgoing<Message>>,
+    ) -> Result<(), Blame<PrefixError>> {
+        if self.masked_inputs.is_some() || !self.masks.is_done() {
+            return Ok(());
+        }
+        let opened_chain_masks = match &self.chain_masks {
+            Some(chain_masks) => match chain_masks.output() {
+                Some(opened_values) => opened_values,
+                None => return Ok(()),
+            },
+            None => Vec::new(),
+        };
+        let opened_masks = self.masks.output().unwrap();
+
+        let w_vshare_batch = beaver::output_vshare_batch(&self.triple_batch, &opened_chain_masks);
+        let w_commitment_batch =
+            beaver::output_commitment_batch(&self.triple_commitment_batch, &opened_chain_masks);
+        let ratio_vshare_batch =
+            mask_ratio_vshare_batch(&self.s.vshares(), w_vshare_batch, opened_masks.clone());
+        let ratio_commitment_batch =
+            mask_ratio_commitment_batch(&self.s.commitments(), w_commitment_batch, opened_masks);
+        let mut masked_inputs = mulopen::Machine::new_unchecked(
+            self.params.clone(),
+            ratio_vshare_batch,
+            self.x.vshares(),
+            self.z2.vshares(),
+            ratio_commitment_batch,
+            self.x.commitments(),
+            self.z2.commitments(),
+        );
+        outgoing.extend(
+            masked_inputs
+                .initial_messages()
+                .into_iter()
+                .map(|o| Outgoing::broadcast(Message::MaskedInputs(o.message))),
+        );
+        self.masked_inputs = Some(masked_inputs);
+
+        // Every pending message is handled, but only the first invalid one is reported.
+        let mut blame = None;
+        for (from, message_batch) in std::mem::take(&mut self.pending) {
+            if let Err(e) = self.handle_masked_inputs(&from, message_batch) {
+                blame.get_or_insert(e);
+            }
+        }
+        match blame {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_masked_inputs(
+        &mut self,
+        from: &Scalar,
+        message_batch: Vec<mulopen::Message>,
+    ) -> Result<(), Blame<PrefixError>> {
+        let masked_inputs = self
+            .masked_inputs
+            .as_mut()
+            .expect("round should have started");
+        masked_inputs
+            .handle_message(from, message_batch, &mut Vec::new())
+            .map_err(|e| Blame::new(*from, PrefixError::MulOpen(e.error)))?;
+        if self.output.is_none() {
+            if let Some(opened_values) = masked_inputs.output() {
+                self.output = Some((
+                    output_vshare_batch(self.r.vshares(), &opened_values),
+                    output_commitment_batch(&self.r.commitments(), &opened_values),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Message;
+    type Output = (Vec<VShare>, Vec<SharingCommitment>);
+    type Error = PrefixError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Message>> {
+        let mut outgoing: Vec<Outgoing<Message>> = self
+            .masks
+            .initial_messages()
+            .into_iter()
+            .map(|o| Outgoing::broadcast(Message::Masks(o.message)))
+            .collect();
+        if let Some(chain_masks) = self.chain_masks.as_mut() {
+            outgoing.extend(
+                chain_masks
+                    .initial_messages()
+                    .into_iter()
+                    .map(|o| Outgoing::broadcast(Message::ChainMasks(o.message))),
+            );
+        }
+        outgoing
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: Message,
+        outgoing: &mut Vec<Outgoing<Message>>,
+    ) -> Result<(), Blame<PrefixError>> {
+        match message {
+            Message::Masks(message_batch) => {
+                self.masks
+                    .handle_message(from, message_batch, &mut Vec::new())
+                    .map_err(|e| Blame::new(*from, PrefixError::MulOpen(e.error)))?;
+                self.start_masked_inputs(outgoing)
+            }
+            Message::ChainMasks(vshare_batch) => {
+                let chain_masks = match self.chain_masks.as_mut() {
+                    Some(chain_masks) => chain_masks,
+                    None => return Err(Blame::new(*from, PrefixError::UnexpectedMessage)),
+                };
+                chain_masks
+                    .handle_message(from, vshare_batch, &mut Vec::new())
+                    .map_err(|e| Blame::new(*from, PrefixError::Open(e.error)))?;
+                self.start_masked_inputs(outgoing)
+            }
+            Message::MaskedInputs(message_batch) => {
+                if self.masked_inputs.is_none() {
+                    self.pending.push((*from, message_batch));
+                    Ok(())
+                } else {
+                    self.handle_masked_inputs(from, message_batch)
+                }
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<(Vec<VShare>, Vec<SharingCommitment>)> {
+        self.output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    #[test]
+    fn outputs_are_prefix_products() {
+        let n = 10;
+        let k = 3;
+        let m = 4;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        let (x_shares_by_player, x_commitments, x_secrets, _) =
+            testutil::random_sharing_batch(n, k, m, &indices, &h);
+        let (r_shares_by_player, r_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, m, &indices, &h);
+        let (s_shares_by_player, s_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, m, &indices, &h);
+        let (z_shares_by_player, z_commitments) =
+            testutil::zero_sharing_batch(n, k, m, &indices, &h);
+        let (triples_by_player, triple_commitments) =
+            testutil::beaver_triple_batch(n, k, m - 1, &indices, &h);
+
+        // Round 1.
+        let opened_masks = testutil::mulopen_by_player(
+            &r_shares_by_player,
+            &s_shares_by_player,
+            &z_shares_by_player,
+            &r_commitments,
+            &s_commitments,
+            &z_commitments,
+            &h,
+        );
+        let mut chain_masked_by_player = Vec::with_capacity(n);
+        for ((r_batch, s_batch), triple_batch) in r_shares_by_player
+            .iter()
+            .zip(s_shares_by_player.iter())
+            .zip(triples_by_player.iter())
+        {
+            chain_masked_by_player.push(chain_masked_vshare_batch(r_batch, s_batch, triple_batch));
+        }
+        let opened_chain_masks = testutil::open_by_player(
+            &chain_masked_by_player,
+            chain_masked_commitment_batch(&r_commitments, &s_commitments, &triple_commitments),
+            &indices,
+            &h,
+        );
+        let w_commitments =
+            beaver::output_commitment_batch(&triple_commitments, &opened_chain_masks);
+        let ratio_commitments =
+            mask_ratio_commitment_batch(&s_commitments, w_commitments, opened_masks.clone());
+        let mut ratio_shares_by_player = Vec::with_capacity(n);
+        for (s_batch, triple_batch) in s_shares_by_player.iter().zip(triples_by_player.iter()) {
+            let w_batch = beaver::output_vshare_batch(triple_batch, &opened_chain_masks);
+            let ratio_batch = mask_ratio_vshare_batch(s_batch, w_batch, opened_masks.clone());
+            for (vshare, commitment) in ratio_batch.iter().zip(ratio_commitments.iter()) {
+                assert!(vss::vshare_is_valid(vshare, commitment, &h));
+            }
+            ratio_shares_by_player.push(ratio_batch);
+        }
+
+        // Round 2.
+        let (z_shares_by_player, z_commitments) =
+            testutil::zero_sharing_batch(n, k, m, &indices, &h);
+        let opened_values = testutil::mulopen_by_player(
+            &ratio_shares_by_player,
+            &x_shares_by_player,
+            &z_shares_by_player,
+            &ratio_commitments,
+            &x_commitments,
+            &z_commitments,
+            &h,
+        );
+
+        let output_commitments = output_commitment_batch(&r_commitments, &opened_values);
+        let mut outputs_by_player = Vec::with_capacity(n);
+        for r_batch in r_shares_by_player {
+            let output_batch = output_vshare_batch(r_batch, &opened_values);
+            for (vshare, commitment) in output_batch.iter().zip(output_commitments.iter()) {
+                assert!(vss::vshare_is_valid(vshare, commitment, &h));
+            }
+            outputs_by_player.push(output_batch);
+        }
+
+        let mut expected = x_secrets[0];
+        for (i, sharing) in testutil::transpose(outputs_by_player).iter().enumerate() {
+            if i > 0 {
+                expected = &expected * &x_secrets[i];
+            }
+            let (output, _) = vss::interpolate_shares_at_zero(sharing.iter());
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use secp256k1::scalar::Scalar;
+
+// A common interface for driving the protocols in this crate. Each protocol module provides a
+// Machine type that owns the instance parameters and state for one player, so that composite
+// protocols and networking code can drive any protocol in the same way:
+//
+// 1. Send the messages returned by initial_messages.
+// 2. Pass every message received to handle_message along with the index of the sender, and send
+//    any messages that it pushes to outgoing. Messages are pushed even if an error is returned, so
+//    that a single invalid message does not stop the player from progressing.
+// 3. Once is_done returns true, the output is available.
+//
+// A message sent to Recipient::All should also be delivered to the sender.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recipient {
+    All,
+    One(Scalar),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outgoing<M> {
+    pub to: Recipient,
+    pub message: M,
+}
+
+impl<M> Outgoing<M> {
+    pub fn broadcast(message: M) -> Self {
+        Outgoing {
+            to: Recipient::All,
+            message,
+        }
+    }
+
+    pub fn directed(to: Scalar, message: M) -> Self {
+        Outgoing {
+            to: Recipient::One(to),
+            message,
+        }
+    }
+}
+
+// An invalid message, together with the index of the player that sent it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blame<E> {
+    pub culprit: Scalar,
+    pub error: E,
+}
+
+impl<E> Blame<E> {
+    pub fn new(culprit: Scalar, error: E) -> Self {
+        Blame { culprit, error }
+    }
+}
+
+pub trait Protocol {
+    type Message: Clone;
+    type Output;
+    type Error;
+
+    fn index(&self) -> Scalar;
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Self::Message>>;
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: Self::Message,
+        outgoing: &mut Vec<Outgoing<Self::Message>>,
+    ) -> Result<(), Blame<Self::Error>>;
+
+    fn is_done(&self) -> bool;
+
+    fn output(&self) -> Option<Self::Output>;
+}
@@ -0,0 +1,273 @@
+use crate::mulopen::{self, MulOpenErr};
+use crate::params::{self, Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::shared::Linear;
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{SharingCommitment, VShare};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RandBitError {
+    ZeroSquare,
+}
+
+// A random bit is obtained by opening the square of a random sharing r. If s is the (canonical)
+// square root of the opened value then r/s is uniformly distributed in {-1, 1}, and so
+// (r/s + 1)/2 is a sharing of a uniform bit. The square is zero only if r is zero, which happens
+// with negligible probability; in this case the batch should be retried with fresh randomness.
+//
+// The zero sharing z that masks the square needs to have degree 2k - 2, e.g. from rzg with 2k - 2
+// coefficients, since a mask of degree k - 1 would leave the high coefficients of r(x)^2 visible in
+// the opened polynomial, and these determine r up to its sign.
+pub fn initial_message_batch(
+    r_vshare_batch: Vec<VShare>,
+    z_vshare_batch: Vec<VShare>,
+    h: &Gej,
+) -> Vec<mulopen::Message> {
+    mulopen::initial_message_batch(r_vshare_batch.clone(), r_vshare_batch, z_vshare_batch, h)
+}
+
+pub fn handle_message_batch(
+    state: &mut Vec<Vec<Share>>,
+    message_batch: Vec<mulopen::Message>,
+    r_commitment_batch: &[SharingCommitment],
+    z_commitment_batch: &[SharingCommitment],
+    h: &Gej,
+) -> Result<Option<Vec<Scalar>>, MulOpenErr> {
+    mulopen::handle_message_batch(
+        state,
+        message_batch,
+        r_commitment_batch,
+        r_commitment_batch,
+        z_commitment_batch,
+        h,
+    )
+}
+
+fn bit_transform_coefficients(squares: &[Scalar]) -> Result<Vec<(Scalar, Scalar)>, RandBitError> {
+    let mut inv_two = util::scalar_from_u64(2);
+    inv_two.inverse_assign();
+    let mut coeffs = Vec::with_capacity(squares.len());
+    for square in squares {
+        if square.is_zero() {
+            return Err(RandBitError::ZeroSquare);
+        }
+        let mut root_inv = util::scalar_sqrt(square).expect("opened value should be a square");
+        root_inv.inverse_assign();
+        coeffs.push((&root_inv * &inv_two, inv_two));
+    }
+    Ok(coeffs)
+}
+
+pub fn output_bit_batch(
+    r_vshare_batch: Vec<VShare>,
+    squares: &[Scalar],
+) -> Result<Vec<VShare>, RandBitError> {
+    output_batch(r_vshare_batch, squares)
+}
+
+pub fn output_commitment_batch(
+    r_commitment_batch: &[SharingCommitment],
+    squares: &[Scalar],
+) -> Result<Vec<SharingCommitment>, RandBitError> {
+    output_batch(r_commitment_batch.to_vec(), squares)
+}
+
+fn output_batch<T: Linear>(r_batch: Vec<T>, squares: &[Scalar]) -> Result<Vec<T>, RandBitError> {
+    assert_eq!(r_batch.len(), squares.len());
+    let coeffs = bit_transform_coefficients(squares)?;
+    let mut bit_batch = r_batch;
+    for (bit, (scale, constant)) in bit_batch.iter_mut().zip(coeffs.iter()) {
+        bit.scale_assign_mut(scale);
+        bit.add_constant_assign_mut(constant);
+    }
+    Ok(bit_batch)
+}
+
+// Generates a batch of random bits for one player from a batch of random sharings r and a batch of
+// zero sharings of degree 2k - 2. The output is an error if any of the opened squares is zero.
+pub struct Machine {
+    mulopen: mulopen::Machine,
+    r_vshare_batch: Vec<VShare>,
+    r_commitment_batch: Vec<SharingCommitment>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        r_vshare_batch: Vec<VShare>,
+        z_vshare_batch: Vec<VShare>,
+        r_commitment_batch: Vec<SharingCommitment>,
+        z_commitment_batch: Vec<SharingCommitment>,
+    ) -> Result<Self, ParamsError> {
+        if let Some(r_commitment) = r_commitment_batch.first() {
+            params::check_commitment_threshold(&z_commitment_batch, 2 * r_commitment.len() - 1)?;
+        }
+        let mulopen = mulopen::Machine::new(
+            params,
+            r_vshare_batch.clone(),
+            r_vshare_batch.clone(),
+            z_vshare_batch,
+            r_commitment_batch.clone(),
+            r_commitment_batch.clone(),
+            z_commitment_batch,
+        )?;
+        Ok(Machine {
+            mulopen,
+            r_vshare_batch,
+            r_commitment_batch,
+        })
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Vec<mulopen::Message>;
+    type Output = Result<(Vec<VShare>, Vec<SharingCommitment>), RandBitError>;
+    type Error = MulOpenErr;
+
+    fn index(&self) -> Scalar {
+        self.mulopen.index()
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<mulopen::Message>>> {
+        self.mulopen.initial_messages()
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message_batch: Vec<mulopen::Message>,
+        outgoing: &mut Vec<Outgoing<Vec<mulopen::Message>>>,
+    ) -> Result<(), Blame<MulOpenErr>> {
+        self.mulopen.handle_message(from, message_batch, outgoing)
+    }
+
+    fn is_done(&self) -> bool {
+        self.mulopen.is_done()
+    }
+
+    fn output(&self) -> Option<Self::Output> {
+        let squares = self.mulopen.output()?;
+        Some(
+            output_bit_batch(self.r_vshare_batch.clone(), &squares).and_then(|bit_batch| {
+                let commitment_batch = output_commitment_batch(&self.r_commitment_batch, &squares)?;
+                Ok((bit_batch, commitment_batch))
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+    use shamir::vss;
+
+    #[test]
+    fn output_sharings_are_of_bits() {
+        let n = 10;
+        let k = 3;
+        let b = 5;
+
+        let threshold = 2 * k - 1;
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        let (r_shares_by_player, r_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (z_shares_by_player, z_commitments) =
+            testutil::rzg_by_player(threshold, b, &indices, &h);
+        assert!(z_commitments.iter().all(|com| com.len() == threshold));
+
+        let mut state = Vec::with_capacity(b);
+        state.resize_with(b, || Vec::with_capacity(threshold));
+        let mut squares = None;
+        for (r_batch, z_batch) in r_shares_by_player
+            .iter()
+            .zip(z_shares_by_player.into_iter())
+            .take(threshold)
+        {
+            let message_batch = initial_message_batch(r_batch.clone(), z_batch, &h);
+            squares = handle_message_batch(
+                &mut state,
+                message_batch,
+                &r_commitments,
+                &z_commitments,
+                &h,
+            )
+            .unwrap();
+        }
+        let squares = squares.unwrap();
+
+        let bit_commitments = output_commitment_batch(&r_commitments, &squares).unwrap();
+        let mut bits_by_player = Vec::with_capacity(n);
+        for r_batch in r_shares_by_player {
+            let bit_batch = output_bit_batch(r_batch, &squares).unwrap();
+            for (vshare, commitment) in bit_batch.iter().zip(bit_commitments.iter()) {
+                assert!(vss::vshare_is_valid(vshare, commitment, &h));
+            }
+            bits_by_player.push(bit_batch);
+        }
+
+        let one = util::scalar_from_u64(1);
+        for sharing in testutil::transpose(bits_by_player) {
+            let (bit, _) = vss::interpolate_shares_at_zero(sharing.iter());
+            assert!(bit.is_zero() || bit == one);
+        }
+    }
+
+    #[test]
+    fn zero_square_is_detected() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (r_shares_by_player, r_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let r = Scalar::new_random_using_thread_rng();
+        let squares = vec![&r * &r, Scalar::zero()];
+
+        assert_eq!(
+            output_commitment_batch(&r_commitments, &squares),
+            Err(RandBitError::ZeroSquare)
+        );
+        assert_eq!(
+            output_bit_batch(r_shares_by_player[0].clone(), &squares),
+            Err(RandBitError::ZeroSquare)
+        );
+    }
+
+    #[test]
+    fn masks_of_the_wrong_degree_are_rejected() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let (r_shares_by_player, r_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let new = |z_threshold: usize| {
+            let (z_shares_by_player, z_commitments) =
+                testutil::rzg_by_player(z_threshold, b, &indices, &h);
+            Machine::new(
+                params.clone(),
+                r_shares_by_player[0].clone(),
+                z_shares_by_player[0].clone(),
+                r_commitments.clone(),
+                z_commitments,
+            )
+            .err()
+        };
+
+        assert_eq!(new(2 * k - 1), None);
+        // A mask of degree k - 1 would reveal r up to its sign.
+        assert_eq!(new(k), Some(ParamsError::InvalidCommitmentThreshold));
+    }
+}
@@ -0,0 +1,590 @@
+use crate::open::{self, OpenError};
+use crate::params::{Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::rng::{self, DirectedVShare};
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{self, SharingCommitment, VShare};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RecoveryError {
+    InvalidIndex,
+    DuplicateIndex,
+    InconsistentIndices,
+    InvalidBatchSize,
+    InvalidShare,
+    InvalidRecoveredShare,
+    Equivocation(Vec<VShare>, Vec<VShare>),
+}
+
+// The helpers first run an RZG style protocol to obtain a sharing that vanishes at the lost
+// player's index instead of at zero. Where RZG scales by the index `i`, this scales by
+// `i - lost_index`.
+pub fn initial_messages_batch(
+    coeff_shares_batch: &[Vec<VShare>],
+    indices: &[Scalar],
+    lost_index: &Scalar,
+) -> Vec<Vec<DirectedVShare>> {
+    let mut directed_vshares_batch = rng::initial_messages_batch_rng(coeff_shares_batch, indices);
+    for directed_vshares in directed_vshares_batch.iter_mut() {
+        for directed_vshare in directed_vshares.iter_mut() {
+            let scale = directed_vshare.to - lost_index;
+            directed_vshare.vshare.scale_assign_mut(&scale);
+        }
+    }
+    directed_vshares_batch
+}
+
+pub fn own_commitment_batch(
+    coeff_commitments_batch: &[Vec<SharingCommitment>],
+    own_index: &Scalar,
+    lost_index: &Scalar,
+) -> Vec<SharingCommitment> {
+    let scale = own_index - lost_index;
+    let mut own_commitment_batch =
+        rng::own_commitment_batch_rng(coeff_commitments_batch, own_index);
+    for commitment in own_commitment_batch.iter_mut() {
+        commitment.scale_assign_mut(&scale);
+    }
+    own_commitment_batch
+}
+
+pub fn output_commitment_batch(
+    coeff_commitments_batch: &[Vec<SharingCommitment>],
+    lost_index: &Scalar,
+) -> Vec<SharingCommitment> {
+    let mut neg_lost_index = *lost_index;
+    neg_lost_index.negate_assign_mut();
+    rng::output_commitment_batch_rng(coeff_commitments_batch)
+        .iter()
+        .map(|commitment| {
+            // Multiply the committed polynomial g(x) by (x - lost_index) in the exponent.
+            let k = commitment.len() + 1;
+            let mut output_commitment = SharingCommitment::with_capacity(k);
+            let mut tmp = Gej::default();
+            for i in 0..k {
+                let mut coeff = Gej::infinity();
+                if i > 0 {
+                    coeff.add_assign(&commitment[i - 1]);
+                }
+                if i < commitment.len() {
+                    tmp.scalar_mul(&commitment[i], &neg_lost_index);
+                    coeff.add_assign(&tmp);
+                }
+                output_commitment.push(coeff);
+            }
+            output_commitment
+        })
+        .collect()
+}
+
+pub fn blinded_vshare_batch(
+    vshare_batch: &[VShare],
+    vanishing_vshare_batch: &[VShare],
+) -> Vec<VShare> {
+    vshare_batch
+        .iter()
+        .zip(vanishing_vshare_batch.iter())
+        .map(|(vshare, vanishing_vshare)| {
+            let mut blinded = *vshare;
+            blinded.add_assign_mut(vanishing_vshare);
+            blinded
+        })
+        .collect()
+}
+
+pub struct InstanceParams {
+    lost_index: Scalar,
+    commitment_batch: Vec<SharingCommitment>,
+    blinded_commitment_batch: Vec<SharingCommitment>,
+}
+
+impl InstanceParams {
+    pub fn new(
+        lost_index: Scalar,
+        commitment_batch: Vec<SharingCommitment>,
+        vanishing_commitment_batch: &[SharingCommitment],
+    ) -> Self {
+        assert_eq!(commitment_batch.len(), vanishing_commitment_batch.len());
+        let blinded_commitment_batch = commitment_batch
+            .iter()
+            .zip(vanishing_commitment_batch.iter())
+            .map(|(commitment, vanishing_commitment)| {
+                sum_commitments(commitment, vanishing_commitment)
+            })
+            .collect();
+        Self {
+            lost_index,
+            commitment_batch,
+            blinded_commitment_batch,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.blinded_commitment_batch[0].len()
+    }
+}
+
+fn sum_commitments(a: &SharingCommitment, b: &SharingCommitment) -> SharingCommitment {
+    let len = a.len().max(b.len());
+    let mut sum = SharingCommitment::with_capacity(len);
+    for i in 0..len {
+        let mut coeff = Gej::infinity();
+        if i < a.len() {
+            coeff.add_assign(&a[i]);
+        }
+        if i < b.len() {
+            coeff.add_assign(&b[i]);
+        }
+        sum.push(coeff);
+    }
+    sum
+}
+
+pub struct State {
+    senders: Vec<Scalar>,
+    vshare_bufs: Vec<Vec<VShare>>,
+}
+
+impl State {
+    pub fn new(inst_params: &InstanceParams) -> Self {
+        let b = inst_params.commitment_batch.len();
+        let mut vshare_bufs = Vec::with_capacity(b);
+        for _ in 0..b {
+            vshare_bufs.push(Vec::with_capacity(inst_params.threshold()));
+        }
+        State {
+            senders: Vec::with_capacity(inst_params.threshold()),
+            vshare_bufs,
+        }
+    }
+
+    pub fn handle_blinded_vshare_batch(
+        &mut self,
+        inst_params: &InstanceParams,
+        params: &Parameters,
+        vshare_batch: Vec<VShare>,
+    ) -> Result<Option<Vec<VShare>>, RecoveryError> {
+        use RecoveryError::*;
+
+        if vshare_batch.len() != self.vshare_bufs.len() {
+            return Err(InvalidBatchSize);
+        }
+        if !util::all_indices_equal_in_vshare_batch(&vshare_batch) {
+            return Err(InconsistentIndices);
+        }
+        let index = vshare_batch[0].share.index;
+        if index == inst_params.lost_index || !params.indices.contains(&index) {
+            return Err(InvalidIndex);
+        }
+        if self.senders.contains(&index) {
+            return Err(DuplicateIndex);
+        }
+        for (vshare, commitment) in vshare_batch
+            .iter()
+            .zip(inst_params.blinded_commitment_batch.iter())
+        {
+            if !vss::vshare_is_valid(vshare, commitment, &params.h) {
+                return Err(InvalidShare);
+            }
+        }
+
+        if self.senders.len() == inst_params.threshold() {
+            return Ok(None);
+        }
+        // Shifting the indices by the lost index means that interpolating at zero gives the
+        // evaluation of the blinded polynomial at the lost index.
+        for (buf, mut vshare) in self.vshare_bufs.iter_mut().zip(vshare_batch.into_iter()) {
+            vshare.share.index = vshare.share.index - inst_params.lost_index;
+            buf.push(vshare);
+        }
+        self.senders.push(index);
+
+        if self.senders.len() != inst_params.threshold() {
+            return Ok(None);
+        }
+        let mut recovered = Vec::with_capacity(self.vshare_bufs.len());
+        for (buf, commitment) in self
+            .vshare_bufs
+            .iter()
+            .zip(inst_params.commitment_batch.iter())
+        {
+            let (value, decommitment) = vss::interpolate_shares_at_zero(buf.iter());
+            let vshare = VShare {
+                share: Share {
+                    index: inst_params.lost_index,
+                    value,
+                },
+                decommitment,
+            };
+            if !vss::vshare_is_valid(&vshare, commitment, &params.h) {
+                return Err(InvalidRecoveredShare);
+            }
+            recovered.push(vshare);
+        }
+        Ok(Some(recovered))
+    }
+}
+
+impl From<OpenError> for RecoveryError {
+    fn from(e: OpenError) -> Self {
+        match e {
+            OpenError::InvalidIndex => RecoveryError::InvalidIndex,
+            OpenError::DuplicateIndex => RecoveryError::DuplicateIndex,
+            OpenError::InvalidShare => RecoveryError::InvalidShare,
+            OpenError::InconsistentIndices => RecoveryError::InconsistentIndices,
+            OpenError::InvalidBatchSize => RecoveryError::InvalidBatchSize,
+            OpenError::Equivocation(accepted, received) => {
+                RecoveryError::Equivocation(accepted, received)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Vanishing(Vec<DirectedVShare>),
+    Blinded(Vec<VShare>),
+}
+
+enum Role {
+    Helper {
+        vshare_batch: Vec<VShare>,
+        messages: Vec<Vec<DirectedVShare>>,
+        inst_params: open::InstanceParams,
+        state: open::State,
+        sent_blinded: bool,
+    },
+    Lost {
+        inst_params: InstanceParams,
+        state: State,
+        output: Option<Vec<VShare>>,
+    },
+}
+
+// Runs recovery for either one of the helpers or the player that lost their shares. The helpers use
+// parameters whose indices are only the helpers, while the player that lost their shares uses
+// parameters whose indices are the helpers together with their own index, which is the lost index.
+// The helpers are done once they have sent their blinded shares, and only the player that lost
+// their shares has an output.
+pub struct Machine {
+    params: Parameters,
+    lost_index: Scalar,
+    role: Role,
+}
+
+impl Machine {
+    pub fn new_helper(
+        params: Parameters,
+        lost_index: Scalar,
+        vshare_batch: Vec<VShare>,
+        coeff_shares_batch: &[Vec<VShare>],
+        coeff_commitments_batch: &[Vec<SharingCommitment>],
+    ) -> Result<Self, ParamsError> {
+        if params.indices.contains(&lost_index) {
+            return Err(ParamsError::LostIndexIsHelper);
+        }
+        let messages = initial_messages_batch(coeff_shares_batch, &params.indices, &lost_index);
+        let inst_params = open::InstanceParams::new(own_commitment_batch(
+            coeff_commitments_batch,
+            &params.index,
+            &lost_index,
+        ));
+        let state = open::State::new(&inst_params);
+        Ok(Machine {
+            params,
+            lost_index,
+            role: Role::Helper {
+                vshare_batch,
+                messages,
+                inst_params,
+                state,
+                sent_blinded: false,
+            },
+        })
+    }
+
+    // The lost index is the index of the parameters, and the other indices are the helpers, of
+    // which there need to be enough to reconstruct the blinded sharings.
+    pub fn new_lost(
+        params: Parameters,
+        commitment_batch: Vec<SharingCommitment>,
+        coeff_commitments_batch: &[Vec<SharingCommitment>],
+    ) -> Result<Self, ParamsError> {
+        if commitment_batch.is_empty() {
+            return Err(ParamsError::EmptyBatch);
+        }
+        let lost_index = params.index;
+        let vanishing_commitment_batch =
+            output_commitment_batch(coeff_commitments_batch, &lost_index);
+        let inst_params =
+            InstanceParams::new(lost_index, commitment_batch, &vanishing_commitment_batch);
+        params.check_threshold(inst_params.threshold() + 1)?;
+        let state = State::new(&inst_params);
+        Ok(Machine {
+            params,
+            lost_index,
+            role: Role::Lost {
+                inst_params,
+                state,
+                output: None,
+            },
+        })
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Message;
+    type Output = Vec<VShare>;
+    type Error = RecoveryError;
+
+    fn index(&self) -> Scalar {
+        match self.role {
+            Role::Helper { .. } => self.params.index,
+            Role::Lost { .. } => self.lost_index,
+        }
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Message>> {
+        match &mut self.role {
+            Role::Helper { messages, .. } => self
+                .params
+                .indices
+                .iter()
+                .zip(messages.drain(..))
+                .map(|(to, message)| Outgoing::directed(*to, Message::Vanishing(message)))
+                .collect(),
+            Role::Lost { .. } => Vec::new(),
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: Message,
+        outgoing: &mut Vec<Outgoing<Message>>,
+    ) -> Result<(), Blame<RecoveryError>> {
+        let blame = |e: RecoveryError| Blame::new(*from, e);
+        match (&mut self.role, message) {
+            (
+                Role::Helper {
+                    vshare_batch,
+                    inst_params,
+                    state,
+                    sent_blinded,
+                    ..
+                },
+                Message::Vanishing(directed_vshare_batch),
+            ) => {
+                if directed_vshare_batch
+                    .iter()
+                    .any(|dv| &dv.vshare.share.index != from || dv.to != self.params.index)
+                {
+                    return Err(blame(RecoveryError::InconsistentIndices));
+                }
+                let res = rng::handle_directed_vshare_batch(
+                    state,
+                    inst_params,
+                    &self.params,
+                    directed_vshare_batch,
+                )
+                .map_err(|e| blame(e.into()))?;
+                if let Some(vanishing_vshare_batch) = res {
+                    *sent_blinded = true;
+                    let blinded = blinded_vshare_batch(vshare_batch, &vanishing_vshare_batch);
+                    outgoing.push(Outgoing::directed(
+                        self.lost_index,
+                        Message::Blinded(blinded),
+                    ));
+                }
+                Ok(())
+            }
+            (
+                Role::Lost {
+                    inst_params,
+                    state,
+                    output,
+                },
+                Message::Blinded(vshare_batch),
+            ) => {
+                if vshare_batch
+                    .iter()
+                    .any(|vshare| &vshare.share.index != from)
+                {
+                    return Err(blame(RecoveryError::InconsistentIndices));
+                }
+                let res = state
+                    .handle_blinded_vshare_batch(inst_params, &self.params, vshare_batch)
+                    .map_err(blame)?;
+                if res.is_some() {
+                    *output = res;
+                }
+                Ok(())
+            }
+            _ => Err(blame(RecoveryError::InvalidIndex)),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        match &self.role {
+            Role::Helper { sent_blinded, .. } => *sent_blinded,
+            Role::Lost { output, .. } => output.is_some(),
+        }
+    }
+
+    fn output(&self) -> Option<Vec<VShare>> {
+        match &self.role {
+            Role::Helper { .. } => None,
+            Role::Lost { output, .. } => output.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+
+    #[test]
+    fn lost_share_is_recovered() {
+        let n = 10;
+        let k = 4;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let lost_index = indices[0];
+        let helpers = indices[1..].to_vec();
+        let (vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        let (mut inputs_by_player, coeff_commitments) =
+            testutil::rxg_inputs(k - 1, b, &helpers, &h);
+        let vanishing_commitments = output_commitment_batch(&coeff_commitments, &lost_index);
+
+        let mut player_inst_params = Vec::with_capacity(n - 1);
+        let mut player_params = Vec::with_capacity(n - 1);
+        let mut states = Vec::with_capacity(n - 1);
+        for index in helpers.iter() {
+            let inst_params = open::InstanceParams::new(own_commitment_batch(
+                &coeff_commitments,
+                index,
+                &lost_index,
+            ));
+            states.push(open::State::new(&inst_params));
+            player_inst_params.push(inst_params);
+            player_params.push(Parameters::new(helpers.clone(), *index, h).unwrap());
+        }
+
+        let mut vanishing_vshare_batches = Vec::with_capacity(n - 1);
+        for (count, index) in helpers.iter().take(k - 1).enumerate() {
+            let messages = initial_messages_batch(
+                &inputs_by_player.remove(index).unwrap(),
+                &helpers,
+                &lost_index,
+            );
+            for (i, message) in messages.into_iter().enumerate() {
+                let res = rng::handle_directed_vshare_batch(
+                    &mut states[i],
+                    &player_inst_params[i],
+                    &player_params[i],
+                    message,
+                );
+                if count + 2 != k {
+                    assert_eq!(res, Ok(None));
+                } else {
+                    vanishing_vshare_batches.push(res.unwrap().unwrap());
+                }
+            }
+        }
+
+        let mut vanishing_at_lost_index = Vec::with_capacity(b);
+        vanishing_at_lost_index.resize_with(b, Vec::new);
+        for vanishing_vshare_batch in vanishing_vshare_batches.iter() {
+            for ((vshare, commitment), sharing) in vanishing_vshare_batch
+                .iter()
+                .zip(vanishing_commitments.iter())
+                .zip(vanishing_at_lost_index.iter_mut())
+            {
+                assert!(vss::vshare_is_valid(vshare, commitment, &h));
+                let mut shifted = *vshare;
+                shifted.share.index = shifted.share.index - lost_index;
+                sharing.push(shifted);
+            }
+        }
+        for sharing in vanishing_at_lost_index {
+            let (value, _) = vss::interpolate_shares_at_zero(sharing.iter());
+            assert!(value.is_zero());
+        }
+
+        let inst_params =
+            InstanceParams::new(lost_index, commitment_batch.clone(), &vanishing_commitments);
+        let params = Parameters::new(indices.clone(), lost_index, h).unwrap();
+        let mut state = State::new(&inst_params);
+        for (count, (vshare_batch, vanishing_vshare_batch)) in vshare_batches[1..]
+            .iter()
+            .zip(vanishing_vshare_batches.iter())
+            .enumerate()
+        {
+            let blinded = blinded_vshare_batch(vshare_batch, vanishing_vshare_batch);
+            let res = state.handle_blinded_vshare_batch(&inst_params, &params, blinded);
+            if count + 1 < k {
+                assert_eq!(res, Ok(None));
+            } else if count + 1 == k {
+                assert_eq!(res, Ok(Some(vshare_batches[0].clone())));
+            }
+        }
+    }
+
+    #[test]
+    fn roles_are_checked() {
+        let n = 6;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let lost_index = indices[0];
+        let helpers = indices[1..].to_vec();
+        let (vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (inputs_by_player, coeff_commitments) = testutil::rxg_inputs(k - 1, b, &helpers, &h);
+
+        let new_helper = |params: Parameters| {
+            let inputs = &inputs_by_player[&params.index];
+            Machine::new_helper(
+                params,
+                lost_index,
+                vshare_batches[1].clone(),
+                inputs,
+                &coeff_commitments,
+            )
+            .err()
+        };
+        assert_eq!(
+            new_helper(Parameters::new(helpers.clone(), helpers[0], h).unwrap()),
+            None
+        );
+        assert_eq!(
+            new_helper(Parameters::new(indices.clone(), helpers[0], h).unwrap()),
+            Some(ParamsError::LostIndexIsHelper)
+        );
+
+        let new_lost = |indices: &[Scalar], commitment_batch: Vec<SharingCommitment>| {
+            let params = Parameters::new(indices.to_vec(), lost_index, h).unwrap();
+            Machine::new_lost(params, commitment_batch, &coeff_commitments).err()
+        };
+        assert_eq!(new_lost(&indices, commitment_batch.clone()), None);
+        assert_eq!(
+            new_lost(&indices, Vec::new()),
+            Some(ParamsError::EmptyBatch)
+        );
+        assert_eq!(
+            new_lost(&indices[..k], commitment_batch),
+            Some(ParamsError::TooFewPlayers)
+        );
+    }
+}
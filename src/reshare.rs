@@ -0,0 +1,467 @@
+use crate::params::{self, Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::rng::DirectedVShare;
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{self, SharingCommitment, VShare};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ReshareError {
+    InvalidIndex,
+    DuplicateIndex,
+    WrongIndex,
+    InvalidBatchSize,
+    InvalidCommitment,
+    InvalidShare,
+}
+
+pub fn initial_messages_batch(
+    vshare_batch: &[VShare],
+    old_indices: &[Scalar],
+    new_indices: &[Scalar],
+    new_k: usize,
+    h: &Gej,
+) -> (Vec<Vec<DirectedVShare>>, Vec<SharingCommitment>) {
+    let n = new_indices.len();
+    let b = vshare_batch.len();
+    let mut directed_vshares_batch = Vec::with_capacity(n);
+    for _player in 0..n {
+        directed_vshares_batch.push(Vec::with_capacity(b));
+    }
+    let mut commitment_batch = Vec::with_capacity(b);
+
+    let mut vshares = Vec::with_capacity(n);
+    vshares.resize_with(n, VShare::default);
+    for vshare in vshare_batch {
+        let lambda = util::lagrange_coefficient_at_zero(&vshare.share.index, old_indices);
+        let mut commitment = SharingCommitment::default_with_len(new_k);
+        vss::vshare_secret_and_decommitment_in_place(
+            &mut vshares,
+            &mut commitment,
+            h,
+            new_indices,
+            &(lambda * vshare.share.value),
+            &(lambda * vshare.decommitment),
+        );
+        for (i, vshare) in vshares.iter().enumerate() {
+            directed_vshares_batch[i].push(DirectedVShare {
+                vshare: *vshare,
+                to: new_indices[i],
+            });
+        }
+        commitment_batch.push(commitment);
+    }
+
+    (directed_vshares_batch, commitment_batch)
+}
+
+pub struct InstanceParams {
+    old_indices: Vec<Scalar>,
+    old_commitment_batch: Vec<SharingCommitment>,
+    new_k: usize,
+}
+
+impl InstanceParams {
+    pub fn new(
+        old_indices: Vec<Scalar>,
+        old_commitment_batch: Vec<SharingCommitment>,
+        new_k: usize,
+    ) -> Self {
+        Self {
+            old_indices,
+            old_commitment_batch,
+            new_k,
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.old_commitment_batch.len()
+    }
+}
+
+#[derive(Clone)]
+pub struct State {
+    dealers: Vec<Scalar>,
+    vshare_batch: Vec<VShare>,
+    commitment_batch: Vec<SharingCommitment>,
+}
+
+impl State {
+    pub fn new(inst_params: &InstanceParams, params: &Parameters) -> Self {
+        let b = inst_params.batch_size();
+        let mut vshare_batch = Vec::with_capacity(b);
+        let mut commitment_batch = Vec::with_capacity(b);
+        for _ in 0..b {
+            vshare_batch.push(VShare {
+                share: Share {
+                    index: params.index,
+                    value: Scalar::zero(),
+                },
+                decommitment: Scalar::zero(),
+            });
+            commitment_batch.push(SharingCommitment::new_from_vec(vec![
+                Gej::infinity();
+                inst_params.new_k
+            ]));
+        }
+        State {
+            dealers: Vec::with_capacity(inst_params.old_indices.len()),
+            vshare_batch,
+            commitment_batch,
+        }
+    }
+
+    pub fn contributions_received(&self) -> usize {
+        self.dealers.len()
+    }
+
+    pub fn handle_contribution_batch(
+        &mut self,
+        inst_params: &InstanceParams,
+        params: &Parameters,
+        from: &Scalar,
+        directed_vshare_batch: Vec<DirectedVShare>,
+        commitment_batch: Vec<SharingCommitment>,
+    ) -> Result<Option<(Vec<VShare>, Vec<SharingCommitment>)>, ReshareError> {
+        use ReshareError::*;
+
+        let b = inst_params.batch_size();
+        if directed_vshare_batch.len() != b || commitment_batch.len() != b {
+            return Err(InvalidBatchSize);
+        }
+        if !inst_params.old_indices.contains(from) {
+            return Err(InvalidIndex);
+        }
+        if self.dealers.contains(from) {
+            return Err(DuplicateIndex);
+        }
+        if directed_vshare_batch
+            .iter()
+            .any(|dv| dv.to != params.index || dv.vshare.share.index != params.index)
+        {
+            return Err(WrongIndex);
+        }
+
+        let lambda = util::lagrange_coefficient_at_zero(from, &inst_params.old_indices);
+        for ((dv, commitment), old_commitment) in directed_vshare_batch
+            .iter()
+            .zip(commitment_batch.iter())
+            .zip(inst_params.old_commitment_batch.iter())
+        {
+            if commitment.len() != inst_params.new_k {
+                return Err(InvalidCommitment);
+            }
+            // The constant term of the dealer's commitment must be their old commitment scaled by
+            // their Lagrange weight, otherwise the dealer could change the shared secret.
+            let old_eval = vss::poly_eval_gej_slice_in_exponent(&old_commitment, from);
+            let mut expected = Gej::default();
+            expected.scalar_mul(&old_eval, &lambda);
+            if commitment[0] != expected {
+                return Err(InvalidCommitment);
+            }
+            if !vss::vshare_is_valid(&dv.vshare, commitment, &params.h) {
+                return Err(InvalidShare);
+            }
+        }
+
+        for ((acc_vshare, acc_commitment), (dv, commitment)) in self
+            .vshare_batch
+            .iter_mut()
+            .zip(self.commitment_batch.iter_mut())
+            .zip(directed_vshare_batch.iter().zip(commitment_batch.iter()))
+        {
+            acc_vshare.add_assign_mut(&dv.vshare);
+            acc_commitment.add_assign_mut(commitment);
+        }
+        self.dealers.push(*from);
+
+        if self.contributions_received() == inst_params.old_indices.len() {
+            Ok(Some((
+                self.vshare_batch.clone(),
+                self.commitment_batch.clone(),
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+pub type Message = (Vec<DirectedVShare>, Vec<SharingCommitment>);
+
+// Runs resharing for one player, who may be a dealer in the old committee, a receiver in the new
+// committee, or both. A player that is only a dealer is done once it has sent its initial messages,
+// and has no output.
+pub struct Machine {
+    params: Parameters,
+    inst_params: InstanceParams,
+    vshare_batch: Option<Vec<VShare>>,
+    state: Option<State>,
+    sent: bool,
+    output: Option<(Vec<VShare>, Vec<SharingCommitment>)>,
+}
+
+impl Machine {
+    // A player in the new committee, with the parameters of the new committee. The player is also a
+    // dealer if they have a share batch from the old committee.
+    pub fn new(
+        params: Parameters,
+        inst_params: InstanceParams,
+        vshare_batch: Option<Vec<VShare>>,
+    ) -> Result<Self, ParamsError> {
+        if vshare_batch.is_some() && !inst_params.old_indices.contains(&params.index) {
+            return Err(ParamsError::NotADealer);
+        }
+        let state = Some(State::new(&inst_params, &params));
+        Ok(Machine {
+            params,
+            inst_params,
+            vshare_batch,
+            state,
+            sent: false,
+            output: None,
+        })
+    }
+
+    // A dealer that is not in the new committee, with the parameters of the old committee.
+    pub fn new_dealer(
+        old_params: Parameters,
+        new_indices: Vec<Scalar>,
+        inst_params: InstanceParams,
+        vshare_batch: Vec<VShare>,
+    ) -> Result<Self, ParamsError> {
+        params::check_indices(&new_indices)?;
+        if !inst_params.old_indices.contains(&old_params.index) {
+            return Err(ParamsError::NotADealer);
+        }
+        if new_indices.contains(&old_params.index) {
+            return Err(ParamsError::IndexInNewCommittee);
+        }
+        let params = Parameters {
+            indices: new_indices,
+            index: old_params.index,
+            h: old_params.h,
+        };
+        Ok(Machine {
+            params,
+            inst_params,
+            vshare_batch: Some(vshare_batch),
+            state: None,
+            sent: false,
+            output: None,
+        })
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Message;
+    type Output = (Vec<VShare>, Vec<SharingCommitment>);
+    type Error = ReshareError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Message>> {
+        self.sent = true;
+        let vshare_batch = match self.vshare_batch.take() {
+            Some(vshare_batch) => vshare_batch,
+            None => return Vec::new(),
+        };
+        let (directed_vshares_batch, commitment_batch) = initial_messages_batch(
+            &vshare_batch,
+            &self.inst_params.old_indices,
+            &self.params.indices,
+            self.inst_params.new_k,
+            &self.params.h,
+        );
+        self.params
+            .indices
+            .iter()
+            .zip(directed_vshares_batch.into_iter())
+            .map(|(to, directed_vshare_batch)| {
+                Outgoing::directed(*to, (directed_vshare_batch, commitment_batch.clone()))
+            })
+            .collect()
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: Message,
+        _outgoing: &mut Vec<Outgoing<Message>>,
+    ) -> Result<(), Blame<ReshareError>> {
+        let state = match self.state.as_mut() {
+            Some(state) => state,
+            None => return Err(Blame::new(*from, ReshareError::WrongIndex)),
+        };
+        let (directed_vshare_batch, commitment_batch) = message;
+        let res = state
+            .handle_contribution_batch(
+                &self.inst_params,
+                &self.params,
+                from,
+                directed_vshare_batch,
+                commitment_batch,
+            )
+            .map_err(|e| Blame::new(*from, e))?;
+        if res.is_some() {
+            self.output = res;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        match self.state {
+            Some(_) => self.output.is_some(),
+            None => self.sent,
+        }
+    }
+
+    fn output(&self) -> Option<(Vec<VShare>, Vec<SharingCommitment>)> {
+        self.output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+
+    #[test]
+    fn reshared_secret_and_commitment_are_preserved() {
+        let old_n = 10;
+        let old_k = 4;
+        let new_n = 7;
+        let new_k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let old_indices = scalar::random_scalars_using_thread_rng(old_n);
+        let new_indices = scalar::random_scalars_using_thread_rng(new_n);
+        let (old_vshare_batches, old_commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(old_n, old_k, b, &old_indices, &h);
+
+        let dealers = old_indices[..old_k + 1].to_vec();
+        let inst_params = InstanceParams::new(dealers.clone(), old_commitment_batch.clone(), new_k);
+        let mut player_params = Vec::with_capacity(new_n);
+        let mut states = Vec::with_capacity(new_n);
+        for index in new_indices.iter() {
+            let params = Parameters::new(new_indices.clone(), *index, h).unwrap();
+            states.push(State::new(&inst_params, &params));
+            player_params.push(params);
+        }
+
+        let mut outputs = Vec::with_capacity(new_n);
+        for (count, (dealer, vshare_batch)) in
+            dealers.iter().zip(old_vshare_batches.iter()).enumerate()
+        {
+            let (messages, commitment_batch) =
+                initial_messages_batch(vshare_batch, &dealers, &new_indices, new_k, &h);
+            for ((state, params), message) in states
+                .iter_mut()
+                .zip(player_params.iter())
+                .zip(messages.into_iter())
+            {
+                let res = state.handle_contribution_batch(
+                    &inst_params,
+                    params,
+                    dealer,
+                    message,
+                    commitment_batch.clone(),
+                );
+                if count + 1 != dealers.len() {
+                    assert_eq!(res, Ok(None));
+                } else {
+                    outputs.push(res.unwrap().unwrap());
+                }
+            }
+        }
+
+        let new_commitment_batch = outputs[0].1.clone();
+        for (new_commitment, old_commitment) in
+            new_commitment_batch.iter().zip(old_commitment_batch.iter())
+        {
+            assert_eq!(new_commitment.len(), new_k);
+            assert_eq!(new_commitment[0], old_commitment[0]);
+        }
+        let mut new_sharings = Vec::with_capacity(new_n);
+        for (vshare_batch, commitment_batch) in outputs {
+            assert_eq!(commitment_batch, new_commitment_batch);
+            for (vshare, commitment) in vshare_batch.iter().zip(commitment_batch.iter()) {
+                assert!(vss::vshare_is_valid(vshare, commitment, &h));
+            }
+            new_sharings.push(vshare_batch);
+        }
+        let new_sharings = testutil::transpose(new_sharings);
+        for (sharing, secret) in new_sharings.iter().zip(secrets.iter()) {
+            let (output, _) = vss::interpolate_shares_at_zero(sharing[..new_k].iter());
+            assert_eq!(&output, secret);
+        }
+    }
+
+    #[test]
+    fn roles_are_checked() {
+        let old_n = 5;
+        let new_n = 4;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let old_indices = scalar::random_scalars_using_thread_rng(old_n);
+        let new_indices = scalar::random_scalars_using_thread_rng(new_n);
+        let (vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(old_n, 3, b, &old_indices, &h);
+        let dealers = old_indices[..3].to_vec();
+        let inst_params = || InstanceParams::new(dealers.clone(), commitment_batch.clone(), 2);
+
+        let new_dealer = |index: Scalar, new_indices: &[Scalar]| {
+            let old_params = Parameters::new(old_indices.clone(), index, h).unwrap();
+            Machine::new_dealer(
+                old_params,
+                new_indices.to_vec(),
+                inst_params(),
+                vshare_batches[0].clone(),
+            )
+            .err()
+        };
+        assert_eq!(new_dealer(old_indices[0], &new_indices), None);
+        assert_eq!(
+            new_dealer(old_indices[4], &new_indices),
+            Some(ParamsError::NotADealer)
+        );
+        let mut with_dealer = new_indices.clone();
+        with_dealer[1] = old_indices[0];
+        assert_eq!(
+            new_dealer(old_indices[0], &with_dealer),
+            Some(ParamsError::IndexInNewCommittee)
+        );
+        assert_eq!(
+            new_dealer(old_indices[0], &[]),
+            Some(ParamsError::EmptyIndices)
+        );
+
+        let new = |indices: &[Scalar], index: Scalar, vshare_batch: Option<Vec<VShare>>| {
+            let params = Parameters::new(indices.to_vec(), index, h).unwrap();
+            Machine::new(params, inst_params(), vshare_batch).err()
+        };
+        assert_eq!(new(&new_indices, new_indices[0], None), None);
+        assert_eq!(
+            new(
+                &new_indices,
+                new_indices[0],
+                Some(vshare_batches[0].clone())
+            ),
+            Some(ParamsError::NotADealer)
+        );
+        assert_eq!(
+            new(
+                &with_dealer,
+                old_indices[0],
+                Some(vshare_batches[0].clone())
+            ),
+            None
+        );
+    }
+}
@@ -1,26 +1,35 @@
+use crate::equivocation::{Accepted, Seen};
+use crate::exponent;
+use crate::params::{Parameters, ParamsError};
+use crate::protocol::{Blame, Outgoing, Protocol};
+#[cfg(feature = "zeroize")]
+use crate::secret::Wipe;
 use secp256k1::group::Gej;
 use secp256k1::scalar::Scalar;
 use shamir::rs::{self, Precompute};
 use shamir::sss::Share;
 use shamir::vss::{SharingCommitment, VShare};
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum RKPGError {
     IndexOutOfRange,
+    DuplicateIndex,
     EmptyBatch,
     InconsistentShareIndices,
     IncorrectBatchSize,
+    TooManyErrors,
+    // Two different share batches from the same player, in the order that they were received.
+    Equivocation(Vec<Share>, Vec<Share>),
 }
 
-#[derive(Clone)]
-pub struct State<'a> {
-    indices: &'a [Scalar],
+pub struct State {
+    indices: Vec<Scalar>,
     bufs: Vec<Vec<Share>>,
-    count: usize,
+    accepted: Accepted<Vec<Share>>,
 }
 
-impl<'a> State<'a> {
-    pub fn new(indices: &'a [Scalar], b: usize) -> Self {
+impl State {
+    pub fn new(indices: &[Scalar], b: usize) -> Self {
         let n = indices.len();
         let mut bufs = Vec::with_capacity(b);
         for _batch in 0..b {
@@ -34,9 +43,9 @@ impl<'a> State<'a> {
             bufs.push(buf);
         }
         State {
-            indices,
+            indices: indices.to_vec(),
             bufs,
-            count: 0,
+            accepted: Accepted::new(),
         }
     }
 
@@ -55,16 +64,31 @@ impl<'a> State<'a> {
             .iter()
             .position(|index| index == &share_index)
             .ok_or(IndexOutOfRange)?;
+        // The decommitments can not be checked on their own, so any well formed batch that differs
+        // from the accepted one is equivocation.
+        match self.accepted.check(&share_index, &batch) {
+            Seen::New => (),
+            Seen::Same => return Err(DuplicateIndex),
+            Seen::Different(accepted) => return Err(Equivocation(accepted, batch)),
+        }
 
+        self.accepted.insert(share_index, batch.clone());
         for (share, buf) in batch.into_iter().zip(self.bufs.iter_mut()) {
             buf[i] = share;
         }
-        self.count += 1;
         Ok(())
     }
 
     fn shares_count(&self) -> usize {
-        self.count
+        self.accepted.len()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for State {
+    fn drop(&mut self) {
+        self.bufs.wipe();
+        self.accepted.wipe();
     }
 }
 
@@ -103,17 +127,102 @@ pub fn handle_share_batch(
     let mut pub_keys = Vec::with_capacity(b);
     for (buf, commitment) in state.bufs.iter().zip(commitments.iter()) {
         let it = buf.iter().map(|share| (&share.index, &share.value));
-        let (poly, _errs) = rs::decode_with_precompute(rs_precompute, it, k).expect("TODO");
-        let mut decommitment_neg = poly[0];
-        decommitment_neg.negate_assign_mut();
-        let mut pub_key = Gej::default();
-        pub_key.scalar_mul(h, &decommitment_neg);
-        pub_key.add_assign(&commitment[0]);
-        pub_keys.push(pub_key);
+        let (poly, _errs) = match rs::decode_with_precompute(rs_precompute, it, k) {
+            Some(res) => res,
+            // The shares that have not been received yet count as errors, so decoding might
+            // succeed once more shares have been received.
+            None if state.shares_count() < n => return Ok(None),
+            None => return Err(RKPGError::TooManyErrors),
+        };
+        pub_keys.push(exponent::unblind(&commitment[0], &poly[0], h));
     }
     Ok(Some(pub_keys))
 }
 
+// Reconstructs the public keys for a batch of sharings by broadcasting the decommitments. The
+// output is an error if decoding fails once every batch has been received, since then no single
+// sender can be blamed.
+pub struct Machine {
+    params: Parameters,
+    state: State,
+    precompute: Precompute,
+    commitments: Vec<SharingCommitment>,
+    share_batch: Vec<Share>,
+    output: Option<Result<Vec<Gej>, RKPGError>>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        commitments: Vec<SharingCommitment>,
+        vshares: &[VShare],
+    ) -> Result<Self, ParamsError> {
+        let k = match commitments.first() {
+            Some(commitment) => commitment.len(),
+            None => return Err(ParamsError::EmptyBatch),
+        };
+        params.rkpg_threshold(k)?;
+        let state = State::new(&params.indices, commitments.len());
+        let precompute = Precompute::new(params.indices.iter());
+        Ok(Machine {
+            params,
+            state,
+            precompute,
+            commitments,
+            share_batch: initial_messages_batch(vshares),
+            output: None,
+        })
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Vec<Share>;
+    type Output = Result<Vec<Gej>, RKPGError>;
+    type Error = RKPGError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<Share>>> {
+        vec![Outgoing::broadcast(self.share_batch.clone())]
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        share_batch: Vec<Share>,
+        _outgoing: &mut Vec<Outgoing<Vec<Share>>>,
+    ) -> Result<(), Blame<RKPGError>> {
+        if share_batch.iter().any(|share| &share.index != from) {
+            return Err(Blame::new(*from, RKPGError::InconsistentShareIndices));
+        }
+        let res = match handle_share_batch(
+            &mut self.state,
+            share_batch,
+            &self.precompute,
+            &self.commitments,
+            &self.params.h,
+        ) {
+            Ok(res) => res.map(Ok),
+            Err(RKPGError::TooManyErrors) => Some(Err(RKPGError::TooManyErrors)),
+            Err(e) => return Err(Blame::new(*from, e)),
+        };
+        if self.output.is_none() {
+            self.output = res;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<Result<Vec<Gej>, RKPGError>> {
+        self.output.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,4 +274,147 @@ mod tests {
             assert_eq!(pubkeys, expected_pubkeys);
         }
     }
+
+    #[test]
+    fn equivocation_is_detected() {
+        let n = 5;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let precompute = Precompute::new(indices.iter());
+        let (all_vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let share_batches: Vec<Vec<Share>> = all_vshare_batches
+            .iter()
+            .map(|vshare_batch| initial_messages_batch(vshare_batch))
+            .collect();
+
+        let mut state = State::new(&indices, b);
+        let mut handle = |share_batch: &Vec<Share>| {
+            handle_share_batch(
+                &mut state,
+                share_batch.clone(),
+                &precompute,
+                &commitment_batch,
+                &h,
+            )
+        };
+        for share_batch in share_batches.iter() {
+            assert!(handle(share_batch).is_ok());
+        }
+
+        // A batch that is sent again is not counted again, even after the keys are reconstructed.
+        let mut different = share_batches[0].clone();
+        different[0].value = Scalar::new_random_using_thread_rng();
+        let mut malformed = different.clone();
+        malformed[1].index = indices[1];
+        assert_eq!(handle(&share_batches[0]), Err(RKPGError::DuplicateIndex));
+        assert_eq!(handle(&malformed), Err(RKPGError::InconsistentShareIndices));
+        assert_eq!(
+            handle(&different[..1].to_vec()),
+            Err(RKPGError::IncorrectBatchSize)
+        );
+        assert_eq!(
+            handle(&different),
+            Err(RKPGError::Equivocation(share_batches[0].clone(), different))
+        );
+    }
+
+    #[test]
+    fn empty_batches_and_large_thresholds_are_rejected() {
+        let n = 5;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let new = |k: usize| {
+            let (vshares_by_player, commitments, _, _) =
+                testutil::random_sharing_batch(n, k, b, &indices, &h);
+            Machine::new(params.clone(), commitments, &vshares_by_player[0]).err()
+        };
+
+        assert_eq!(new(3), None);
+        assert_eq!(new(4), Some(ParamsError::TooFewPlayers));
+        assert_eq!(
+            Machine::new(params.clone(), Vec::new(), &[]).err(),
+            Some(ParamsError::EmptyBatch)
+        );
+    }
+
+    #[test]
+    fn too_many_errors_is_reported_once_every_share_is_received() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let precompute = Precompute::new(indices.iter());
+        let (all_vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let mut share_batches: Vec<Vec<Share>> = all_vshare_batches
+            .iter()
+            .map(|vshare_batch| initial_messages_batch(vshare_batch))
+            .collect();
+
+        // Decoding can correct (n - k) / 2 = 3 errors, so with 5 corrupt shares it fails even once
+        // every share has been received.
+        for share_batch in share_batches.iter_mut().take(5) {
+            for share in share_batch.iter_mut() {
+                share.value = Scalar::new_random_using_thread_rng();
+            }
+        }
+
+        let mut state = State::new(&indices, b);
+        let last_share_batch = share_batches.pop().unwrap();
+        for share_batch in share_batches {
+            let res =
+                handle_share_batch(&mut state, share_batch, &precompute, &commitment_batch, &h);
+            assert_eq!(res, Ok(None));
+        }
+        let res = handle_share_batch(
+            &mut state,
+            last_share_batch,
+            &precompute,
+            &commitment_batch,
+            &h,
+        );
+        assert_eq!(res, Err(RKPGError::TooManyErrors));
+    }
+
+    #[test]
+    fn failed_decoding_is_not_blamed_on_the_last_sender() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (all_vshare_batches, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let mut machine = Machine::new(
+            Parameters::new(indices.clone(), indices[0], h).unwrap(),
+            commitment_batch,
+            &all_vshare_batches[0],
+        )
+        .unwrap();
+
+        // The first 5 players are corrupt, which is more than decoding can correct, and the honest
+        // player n is the last to arrive.
+        for (i, vshare_batch) in all_vshare_batches.iter().enumerate() {
+            let mut share_batch = initial_messages_batch(vshare_batch);
+            if i < 5 {
+                for share in share_batch.iter_mut() {
+                    share.value = Scalar::new_random_using_thread_rng();
+                }
+            }
+            let res = machine.handle_message(&indices[i], share_batch, &mut Vec::new());
+            assert_eq!(res, Ok(()));
+            assert_eq!(machine.is_done(), i + 1 == n);
+        }
+        assert_eq!(machine.output(), Some(Err(RKPGError::TooManyErrors)));
+    }
 }
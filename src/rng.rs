@@ -1,10 +1,13 @@
 use crate::open::{self, OpenError};
 use crate::params::Parameters;
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::secret::Secret;
 use secp256k1::group::Gej;
 use secp256k1::scalar::Scalar;
 use shamir::sss::Share;
 use shamir::vss::{SharingCommitment, VShare};
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct DirectedVShare {
     pub vshare: VShare,
     pub to: Scalar,
@@ -23,9 +26,10 @@ macro_rules! impl_rxg_initial_messages {
                 directed_vshares_batch.push(Vec::with_capacity(b));
             }
             for coeff_shares in coeff_shares_batch {
-                let messages = $j(coeff_shares.iter(), indices);
-                for (i, message) in messages.into_iter().enumerate() {
-                    directed_vshares_batch[i].push(message);
+                // The shares are copied into the output, so the intermediate vector is wiped.
+                let messages = Secret::new($j(coeff_shares.iter(), indices));
+                for (i, message) in messages.iter().enumerate() {
+                    directed_vshares_batch[i].push(*message);
                 }
             }
             directed_vshares_batch
@@ -74,6 +78,62 @@ macro_rules! impl_rxg_output_commitment {
 impl_rxg_output_commitment!(output_commitment_batch_rng, output_commitment_rng);
 impl_rxg_output_commitment!(output_commitment_batch_rzg, output_commitment_rzg);
 
+pub fn initial_messages_batch_double_rng(
+    coeff_shares_batch: &[Vec<VShare>],
+    indices: &[Scalar],
+    k: usize,
+) -> Vec<Vec<DirectedVShare>> {
+    // The copies of the low coefficients and the high shares are wiped once they have been copied
+    // into the output.
+    let low_coeff_shares_batch = Secret::new(low_coeffs_batch(coeff_shares_batch, k));
+    let high_directed_vshares_batch =
+        Secret::new(initial_messages_batch_rng(coeff_shares_batch, indices));
+    let mut directed_vshares_batch = initial_messages_batch_rng(&low_coeff_shares_batch, indices);
+    for (directed_vshares, high_directed_vshares) in directed_vshares_batch
+        .iter_mut()
+        .zip(high_directed_vshares_batch.iter())
+    {
+        directed_vshares.extend_from_slice(high_directed_vshares);
+    }
+    directed_vshares_batch
+}
+
+pub fn own_commitment_batch_double_rng(
+    coeff_commitments_batch: &[Vec<SharingCommitment>],
+    own_index: &Scalar,
+    k: usize,
+) -> Vec<SharingCommitment> {
+    let low_coeff_commitments_batch = low_coeffs_batch(coeff_commitments_batch, k);
+    let mut own_commitment_batch =
+        own_commitment_batch_rng(&low_coeff_commitments_batch, own_index);
+    own_commitment_batch.extend(own_commitment_batch_rng(coeff_commitments_batch, own_index));
+    own_commitment_batch
+}
+
+pub fn output_commitment_batch_double_rng(
+    coeff_commitments_batch: &[Vec<SharingCommitment>],
+    k: usize,
+) -> (Vec<SharingCommitment>, Vec<SharingCommitment>) {
+    let low_coeff_commitments_batch = low_coeffs_batch(coeff_commitments_batch, k);
+    (
+        output_commitment_batch_rng(&low_coeff_commitments_batch),
+        output_commitment_batch_rng(coeff_commitments_batch),
+    )
+}
+
+// The low degree sharing uses the first k coefficients of the high degree sharing, so that both
+// share the same constant term. The degree of the high sharing is one less than the number of
+// coefficients, e.g. 2k - 1 coefficients give the degree 2k - 2 of a product of two sharings.
+fn low_coeffs_batch<T: Clone>(coeffs_batch: &[Vec<T>], k: usize) -> Vec<Vec<T>> {
+    coeffs_batch
+        .iter()
+        .map(|coeffs| {
+            assert!(coeffs.len() > k);
+            coeffs[..k].to_vec()
+        })
+        .collect()
+}
+
 fn output_commitment_rng<'a, I>(coeff_commitments: I) -> SharingCommitment
 where
     I: Iterator<Item = &'a SharingCommitment> + ExactSizeIterator,
@@ -202,6 +262,143 @@ pub fn handle_directed_vshare_batch(
         }))
 }
 
+pub fn handle_directed_vshare_batch_double(
+    state: &mut open::State,
+    inst_params: &open::InstanceParams,
+    params: &Parameters,
+    directed_vshare_batch: Vec<DirectedVShare>,
+) -> Result<Option<(Vec<VShare>, Vec<VShare>)>, OpenError> {
+    Ok(
+        handle_directed_vshare_batch(state, inst_params, params, directed_vshare_batch)?.map(
+            |mut low_vshares| {
+                let high_vshares = low_vshares.split_off(low_vshares.len() / 2);
+                (low_vshares, high_vshares)
+            },
+        ),
+    )
+}
+
+// Runs RNG or RZG for one player. The coefficient shares and commitments are the outputs of brng.
+pub struct Machine {
+    params: Parameters,
+    inst_params: open::InstanceParams,
+    state: open::State,
+    messages: Vec<Vec<DirectedVShare>>,
+    output_commitment_batch: Vec<SharingCommitment>,
+    output: Option<Vec<VShare>>,
+}
+
+impl Machine {
+    pub fn new_rng(
+        params: Parameters,
+        coeff_shares_batch: &[Vec<VShare>],
+        coeff_commitments_batch: &[Vec<SharingCommitment>],
+    ) -> Self {
+        let messages = initial_messages_batch_rng(coeff_shares_batch, &params.indices);
+        let own_commitment_batch = own_commitment_batch_rng(coeff_commitments_batch, &params.index);
+        let output_commitment_batch = output_commitment_batch_rng(coeff_commitments_batch);
+        Machine::new(
+            params,
+            messages,
+            own_commitment_batch,
+            output_commitment_batch,
+        )
+    }
+
+    pub fn new_rzg(
+        params: Parameters,
+        coeff_shares_batch: &[Vec<VShare>],
+        coeff_commitments_batch: &[Vec<SharingCommitment>],
+    ) -> Self {
+        let messages = initial_messages_batch_rzg(coeff_shares_batch, &params.indices);
+        let own_commitment_batch = own_commitment_batch_rzg(coeff_commitments_batch, &params.index);
+        let output_commitment_batch = output_commitment_batch_rzg(coeff_commitments_batch);
+        Machine::new(
+            params,
+            messages,
+            own_commitment_batch,
+            output_commitment_batch,
+        )
+    }
+
+    fn new(
+        params: Parameters,
+        messages: Vec<Vec<DirectedVShare>>,
+        own_commitment_batch: Vec<SharingCommitment>,
+        output_commitment_batch: Vec<SharingCommitment>,
+    ) -> Self {
+        let inst_params = open::InstanceParams::new(own_commitment_batch);
+        let state = open::State::new(&inst_params);
+        Machine {
+            params,
+            inst_params,
+            state,
+            messages,
+            output_commitment_batch,
+            output: None,
+        }
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Vec<DirectedVShare>;
+    type Output = (Vec<VShare>, Vec<SharingCommitment>);
+    type Error = OpenError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Vec<DirectedVShare>>> {
+        self.params
+            .indices
+            .iter()
+            .zip(self.messages.drain(..))
+            .map(|(to, message)| Outgoing::directed(*to, message))
+            .collect()
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        directed_vshare_batch: Vec<DirectedVShare>,
+        _outgoing: &mut Vec<Outgoing<Vec<DirectedVShare>>>,
+    ) -> Result<(), Blame<OpenError>> {
+        if directed_vshare_batch
+            .iter()
+            .any(|dv| &dv.vshare.share.index != from)
+        {
+            return Err(Blame::new(*from, OpenError::InconsistentIndices));
+        }
+        if directed_vshare_batch
+            .iter()
+            .any(|dv| dv.to != self.params.index)
+        {
+            return Err(Blame::new(*from, OpenError::InvalidIndex));
+        }
+        let res = handle_directed_vshare_batch(
+            &mut self.state,
+            &self.inst_params,
+            &self.params,
+            directed_vshare_batch,
+        )
+        .map_err(|e| Blame::new(*from, e))?;
+        if res.is_some() {
+            self.output = res;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<(Vec<VShare>, Vec<SharingCommitment>)> {
+        let vshare_batch = self.output.clone()?;
+        Some((vshare_batch, self.output_commitment_batch.clone()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,11 +416,7 @@ mod tests {
         let n = indices.len();
         let mut player_params = Vec::with_capacity(n);
         for index in indices.iter() {
-            player_params.push(Parameters {
-                indices: indices.to_vec(),
-                index: *index,
-                h,
-            });
+            player_params.push(Parameters::new(indices.to_vec(), *index, h).unwrap());
         }
         let mut states = Vec::with_capacity(n);
         for inst_params in inst_params.iter() {
@@ -349,4 +542,74 @@ mod tests {
             assert!(secret.is_zero());
         }
     }
+
+    #[test]
+    fn double_rng_produces_sharings_of_the_same_secret() {
+        let n = 10;
+        let k = 3;
+        let b = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (mut inputs_by_player, commitments) = testutil::rxg_inputs(2 * k, b, &indices, &h);
+        let (low_commitments, high_commitments) =
+            output_commitment_batch_double_rng(&commitments, k);
+
+        let mut player_inst_params = Vec::with_capacity(n);
+        for index in indices.iter() {
+            player_inst_params.push(InstanceParams::new(own_commitment_batch_double_rng(
+                &commitments,
+                index,
+                k,
+            )));
+        }
+        let (player_params, mut states) = params_and_state(&indices, h, &player_inst_params);
+
+        let mut low_shares = Vec::<Vec<_>>::with_capacity(b);
+        low_shares.resize_with(b, || vec![]);
+        let mut high_shares = Vec::<Vec<_>>::with_capacity(b);
+        high_shares.resize_with(b, || vec![]);
+        for (count, index) in indices.iter().take(2 * k).enumerate() {
+            let messages = initial_messages_batch_double_rng(
+                &inputs_by_player.remove(index).unwrap(),
+                &indices,
+                k,
+            );
+            for (i, message) in messages.into_iter().enumerate() {
+                let res = handle_directed_vshare_batch_double(
+                    &mut states[i],
+                    &player_inst_params[i],
+                    &player_params[i],
+                    message,
+                );
+                assert!(res.is_ok());
+                let res = res.unwrap();
+                if count + 1 != 2 * k {
+                    assert!(res.is_none());
+                    continue;
+                }
+                let (low_vshares, high_vshares) = res.unwrap();
+                for (vshare, commitment) in low_vshares.iter().zip(low_commitments.iter()) {
+                    assert!(vss::vshare_is_valid(vshare, commitment, &h));
+                }
+                for (vshare, commitment) in high_vshares.iter().zip(high_commitments.iter()) {
+                    assert!(vss::vshare_is_valid(vshare, commitment, &h));
+                }
+                for (sharing, vshare) in low_shares.iter_mut().zip(low_vshares.into_iter()) {
+                    sharing.push(vshare);
+                }
+                for (sharing, vshare) in high_shares.iter_mut().zip(high_vshares.into_iter()) {
+                    sharing.push(vshare);
+                }
+            }
+        }
+
+        for (low_sharing, high_sharing) in low_shares.iter().zip(high_shares.iter()) {
+            let (low_secret, _) = vss::interpolate_shares_at_zero(low_sharing[..k].iter());
+            let (high_secret, _) = vss::interpolate_shares_at_zero(high_sharing[..2 * k].iter());
+            assert_eq!(low_secret, high_secret);
+            let (low_secret_all, _) = vss::interpolate_shares_at_zero(low_sharing.iter());
+            assert_eq!(low_secret, low_secret_all);
+        }
+    }
 }
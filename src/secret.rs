@@ -0,0 +1,124 @@
+use crate::rng::DirectedVShare;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::VShare;
+use std::ops::{Deref, DerefMut};
+
+// Values that hold secrets and can be overwritten with zeros, so that the secrets do not stay in
+// memory after they are no longer needed. Values are only wiped on drop when the zeroize feature is
+// enabled.
+pub trait Wipe {
+    fn wipe(&mut self);
+}
+
+#[cfg(feature = "zeroize")]
+const _: () = assert!(std::mem::size_of::<Scalar>() == 32);
+
+impl Wipe for Scalar {
+    #[cfg(feature = "zeroize")]
+    fn wipe(&mut self) {
+        // SAFETY: Scalar is defined in the secp256k1 crate as a plain array of 32 bit limbs, with
+        // no pointers or Drop impl, for which all zero bytes is a valid value and is the zero
+        // scalar. The layout is not ours, so its size is checked above, and wiped_values_are_zero
+        // checks that the result is zero when run with the zeroize feature.
+        unsafe { zeroize::zeroize_flat_type(self) };
+    }
+
+    #[cfg(not(feature = "zeroize"))]
+    fn wipe(&mut self) {
+        *self = Scalar::zero();
+    }
+}
+
+impl Wipe for Share {
+    fn wipe(&mut self) {
+        self.value.wipe();
+    }
+}
+
+impl Wipe for VShare {
+    fn wipe(&mut self) {
+        self.share.wipe();
+        self.decommitment.wipe();
+    }
+}
+
+impl Wipe for DirectedVShare {
+    fn wipe(&mut self) {
+        self.vshare.wipe();
+    }
+}
+
+// The elements are wiped in place before the vector is truncated, so the backing memory holds zeros
+// afterwards. Only the current elements are wiped, so a vector that has grown may have left copies
+// of earlier elements in memory that it has since freed.
+impl<T: Wipe> Wipe for Vec<T> {
+    fn wipe(&mut self) {
+        self.iter_mut().for_each(T::wipe);
+        self.clear();
+    }
+}
+
+// A value that is wiped when it is dropped.
+pub struct Secret<T: Wipe>(T);
+
+impl<T: Wipe> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Wipe> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Wipe> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T: Wipe> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.wipe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wiped_values_are_zero() {
+        let mut vshares = vec![
+            VShare {
+                share: Share {
+                    index: Scalar::new_random_using_thread_rng(),
+                    value: Scalar::new_random_using_thread_rng(),
+                },
+                decommitment: Scalar::new_random_using_thread_rng(),
+            };
+            3
+        ];
+        let mut vshare = vshares[0];
+        vshare.wipe();
+        assert_eq!(vshare.share.value, Scalar::zero());
+        assert_eq!(vshare.decommitment, Scalar::zero());
+        assert_eq!(vshare.share.index, vshares[0].share.index);
+
+        vshares.wipe();
+        assert!(vshares.is_empty());
+        // The elements are Copy, so clearing the vector leaves the wiped values in its spare
+        // capacity.
+        for vshare in vshares.spare_capacity_mut()[..3].iter() {
+            let vshare = unsafe { vshare.assume_init_ref() };
+            assert_eq!(vshare.share.value, Scalar::zero());
+            assert_eq!(vshare.decommitment, Scalar::zero());
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::vss::{self, SharingCommitment, VShare};
+
+// A player's share of a value together with the commitment to the sharing. All of the linear
+// operations update both the share and the commitment, so that the commitment always stays
+// consistent with the share.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedScalar {
+    vshare: VShare,
+    commitment: SharingCommitment,
+}
+
+impl SharedScalar {
+    pub fn new(vshare: VShare, commitment: SharingCommitment) -> Self {
+        SharedScalar { vshare, commitment }
+    }
+
+    pub fn vshare(&self) -> &VShare {
+        &self.vshare
+    }
+
+    pub fn commitment(&self) -> &SharingCommitment {
+        &self.commitment
+    }
+
+    pub fn into_parts(self) -> (VShare, SharingCommitment) {
+        (self.vshare, self.commitment)
+    }
+
+    pub fn is_valid(&self, h: &Gej) -> bool {
+        vss::vshare_is_valid(&self.vshare, &self.commitment, h)
+    }
+
+    pub fn add_assign_mut(&mut self, other: &SharedScalar) {
+        Linear::add_assign_mut(self, other);
+    }
+
+    pub fn sub_assign_mut(&mut self, other: &SharedScalar) {
+        let mut neg = other.clone();
+        neg.negate_assign_mut();
+        self.add_assign_mut(&neg);
+    }
+
+    pub fn negate_assign_mut(&mut self) {
+        Linear::negate_assign_mut(self);
+    }
+
+    pub fn scale_assign_mut(&mut self, scale: &Scalar) {
+        Linear::scale_assign_mut(self, scale);
+    }
+
+    pub fn add_constant_assign_mut(&mut self, constant: &Scalar) {
+        Linear::add_constant_assign_mut(self, constant);
+    }
+}
+
+// The local linear operations on shared values. A computation that is written once for any Linear
+// type can be run on the shares, on the commitments, or on both together as SharedScalars, and
+// so the results for the shares and the commitments can not differ.
+pub trait Linear: Clone {
+    fn add_assign_mut(&mut self, other: &Self);
+
+    fn negate_assign_mut(&mut self);
+
+    fn scale_assign_mut(&mut self, scale: &Scalar);
+
+    fn add_constant_assign_mut(&mut self, constant: &Scalar);
+}
+
+impl Linear for VShare {
+    fn add_assign_mut(&mut self, other: &Self) {
+        VShare::add_assign_mut(self, other);
+    }
+
+    fn negate_assign_mut(&mut self) {
+        util::negate_vshare(self);
+    }
+
+    fn scale_assign_mut(&mut self, scale: &Scalar) {
+        VShare::scale_assign_mut(self, scale);
+    }
+
+    fn add_constant_assign_mut(&mut self, constant: &Scalar) {
+        util::add_constant_to_vshare(self, constant);
+    }
+}
+
+impl Linear for SharingCommitment {
+    fn add_assign_mut(&mut self, other: &Self) {
+        SharingCommitment::add_assign_mut(self, other);
+    }
+
+    fn negate_assign_mut(&mut self) {
+        util::negate_commitment(self);
+    }
+
+    fn scale_assign_mut(&mut self, scale: &Scalar) {
+        SharingCommitment::scale_assign_mut(self, scale);
+    }
+
+    fn add_constant_assign_mut(&mut self, constant: &Scalar) {
+        util::add_constant_to_commitment(self, constant);
+    }
+}
+
+impl Linear for SharedScalar {
+    fn add_assign_mut(&mut self, other: &Self) {
+        Linear::add_assign_mut(&mut self.vshare, &other.vshare);
+        Linear::add_assign_mut(&mut self.commitment, &other.commitment);
+    }
+
+    fn negate_assign_mut(&mut self) {
+        Linear::negate_assign_mut(&mut self.vshare);
+        Linear::negate_assign_mut(&mut self.commitment);
+    }
+
+    fn scale_assign_mut(&mut self, scale: &Scalar) {
+        Linear::scale_assign_mut(&mut self.vshare, scale);
+        Linear::scale_assign_mut(&mut self.commitment, scale);
+    }
+
+    fn add_constant_assign_mut(&mut self, constant: &Scalar) {
+        Linear::add_constant_assign_mut(&mut self.vshare, constant);
+        Linear::add_constant_assign_mut(&mut self.commitment, constant);
+    }
+}
+
+// Returns -x.
+pub fn negated<T: Linear>(x: &T) -> T {
+    let mut neg = x.clone();
+    neg.negate_assign_mut();
+    neg
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedScalarBatch {
+    values: Vec<SharedScalar>,
+}
+
+impl SharedScalarBatch {
+    pub fn new(vshares: Vec<VShare>, commitments: Vec<SharingCommitment>) -> Self {
+        assert_eq!(vshares.len(), commitments.len());
+        SharedScalarBatch {
+            values: vshares
+                .into_iter()
+                .zip(commitments.into_iter())
+                .map(|(vshare, commitment)| SharedScalar::new(vshare, commitment))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn vshares(&self) -> Vec<VShare> {
+        self.values.iter().map(|value| value.vshare).collect()
+    }
+
+    pub fn commitments(&self) -> Vec<SharingCommitment> {
+        self.values
+            .iter()
+            .map(|value| value.commitment.clone())
+            .collect()
+    }
+
+    pub fn into_parts(self) -> (Vec<VShare>, Vec<SharingCommitment>) {
+        self.values
+            .into_iter()
+            .map(SharedScalar::into_parts)
+            .unzip()
+    }
+
+    pub fn get(&self, i: usize) -> Option<SharedScalar> {
+        self.values.get(i).cloned()
+    }
+
+    pub fn push(&mut self, shared: SharedScalar) {
+        self.values.push(shared);
+    }
+
+    pub fn is_valid(&self, h: &Gej) -> bool {
+        self.values.iter().all(|value| value.is_valid(h))
+    }
+
+    pub fn add_assign_mut(&mut self, other: &SharedScalarBatch) {
+        assert_eq!(self.len(), other.len());
+        for (value, other_value) in self.values.iter_mut().zip(other.values.iter()) {
+            value.add_assign_mut(other_value);
+        }
+    }
+
+    pub fn sub_assign_mut(&mut self, other: &SharedScalarBatch) {
+        let mut neg = other.clone();
+        neg.negate_assign_mut();
+        self.add_assign_mut(&neg);
+    }
+
+    pub fn negate_assign_mut(&mut self) {
+        self.values
+            .iter_mut()
+            .for_each(SharedScalar::negate_assign_mut);
+    }
+
+    pub fn scale_assign_mut(&mut self, scales: &[Scalar]) {
+        assert_eq!(self.len(), scales.len());
+        for (value, scale) in self.values.iter_mut().zip(scales.iter()) {
+            value.scale_assign_mut(scale);
+        }
+    }
+
+    pub fn add_constant_assign_mut(&mut self, constants: &[Scalar]) {
+        assert_eq!(self.len(), constants.len());
+        for (value, constant) in self.values.iter_mut().zip(constants.iter()) {
+            value.add_constant_assign_mut(constant);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+
+    #[test]
+    fn linear_operations_keep_shares_consistent_with_commitments() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (x_shares_by_player, x_commitments, x_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (y_shares_by_player, y_commitments, y_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let scales = scalar::random_scalars_using_thread_rng(b);
+        let constants = scalar::random_scalars_using_thread_rng(b);
+
+        let mut outputs_by_player = Vec::with_capacity(n);
+        for (x_batch, y_batch) in x_shares_by_player
+            .into_iter()
+            .zip(y_shares_by_player.into_iter())
+        {
+            let x = SharedScalarBatch::new(x_batch, x_commitments.clone());
+            let y = SharedScalarBatch::new(y_batch, y_commitments.clone());
+
+            // -(a x - y) + c
+            let mut output = x;
+            output.scale_assign_mut(&scales);
+            output.sub_assign_mut(&y);
+            output.negate_assign_mut();
+            output.add_constant_assign_mut(&constants);
+            assert!(output.is_valid(&h));
+            outputs_by_player.push(output.vshares());
+        }
+
+        let output_sharings = testutil::transpose(outputs_by_player);
+        for (i, sharing) in output_sharings.iter().enumerate() {
+            let (output, _) = vss::interpolate_shares_at_zero(sharing.iter());
+            let expected = (&y_secrets[i] - &scales[i] * &x_secrets[i]) + constants[i];
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn single_shared_scalar_matches_batch() {
+        let n = 10;
+        let k = 3;
+        let b = 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (x_shares_by_player, x_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let scale = Scalar::new_random_using_thread_rng();
+        let constant = Scalar::new_random_using_thread_rng();
+
+        let mut batch = SharedScalarBatch::new(x_shares_by_player[0].clone(), x_commitments);
+        let mut single = batch.get(0).unwrap();
+        let other = single.clone();
+        single.scale_assign_mut(&scale);
+        single.add_assign_mut(&other);
+        single.add_constant_assign_mut(&constant);
+        assert!(single.is_valid(&h));
+
+        let other = batch.clone();
+        batch.scale_assign_mut(&[scale]);
+        batch.add_assign_mut(&other);
+        batch.add_constant_assign_mut(&[constant]);
+        assert_eq!(batch.get(0), Some(single));
+    }
+}
@@ -0,0 +1,957 @@
+use crate::protocol::{Blame, Outgoing, Protocol, Recipient};
+use secp256k1::scalar::Scalar;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+// The order in which messages that are in flight are delivered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    // Messages are delivered in the order that they were sent.
+    InOrder,
+    // The message that was sent most recently is delivered first.
+    Reverse,
+    // Messages are delivered in a random order determined by the seed, so that a run can be
+    // reproduced.
+    Random(u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Envelope<M> {
+    pub from: Scalar,
+    pub to: Scalar,
+    pub message: M,
+}
+
+// Decides what corrupt players actually send. Messages from honest players are delivered
+// unchanged.
+pub trait Adversary<M> {
+    fn is_corrupt(&self, index: &Scalar) -> bool;
+
+    // Returns the envelopes that are delivered in place of one sent by a corrupt player. Returning
+    // nothing withholds the message.
+    fn tamper(&mut self, envelope: Envelope<M>) -> Vec<Envelope<M>>;
+}
+
+// An in-process network that runs one machine for each player and routes the messages between
+// them. Messages for an index that does not belong to any machine are dropped, so that a subset of
+// the players can be simulated, and messages for a player that is done are not delivered.
+pub struct Network<P: Protocol> {
+    machines: Vec<P>,
+    in_flight: VecDeque<Envelope<P::Message>>,
+    delivery: Delivery,
+    rng_state: u64,
+    adversary: Option<Box<dyn Adversary<P::Message>>>,
+}
+
+impl<P: Protocol> Network<P> {
+    pub fn new(machines: Vec<P>, delivery: Delivery) -> Self {
+        let rng_state = match delivery {
+            Delivery::Random(seed) => seed,
+            _ => 0,
+        };
+        Network {
+            machines,
+            in_flight: VecDeque::new(),
+            delivery,
+            rng_state,
+            adversary: None,
+        }
+    }
+
+    pub fn set_adversary(&mut self, adversary: Box<dyn Adversary<P::Message>>) {
+        self.adversary = Some(adversary);
+    }
+
+    pub fn machines(&self) -> &[P] {
+        &self.machines
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [P] {
+        &mut self.machines
+    }
+
+    pub fn into_machines(self) -> Vec<P> {
+        self.machines
+    }
+
+    pub fn in_flight(&self) -> &VecDeque<Envelope<P::Message>> {
+        &self.in_flight
+    }
+
+    // Collects the initial messages from every machine.
+    pub fn start(&mut self) {
+        for i in 0..self.machines.len() {
+            let from = self.machines[i].index();
+            let outgoing = self.machines[i].initial_messages();
+            self.send(from, outgoing);
+        }
+    }
+
+    // Puts messages in flight as if they were sent by the given player.
+    pub fn send(&mut self, from: Scalar, outgoing: Vec<Outgoing<P::Message>>) {
+        for Outgoing { to, message } in outgoing {
+            let recipients = match to {
+                Recipient::All => self.machines.iter().map(P::index).collect(),
+                Recipient::One(to) => vec![to],
+            };
+            for to in recipients {
+                let envelope = Envelope {
+                    from,
+                    to,
+                    message: message.clone(),
+                };
+                match self.adversary.as_mut() {
+                    Some(adversary) if adversary.is_corrupt(&from) => {
+                        self.in_flight.extend(adversary.tamper(envelope))
+                    }
+                    _ => self.in_flight.push_back(envelope),
+                }
+            }
+        }
+    }
+
+    // Delivers a single message. Returns None if there are no messages in flight, and otherwise
+    // the index of the receiver and the result of handling the message.
+    pub fn step(&mut self) -> Option<(Scalar, Result<(), Blame<P::Error>>)> {
+        let envelope = self.next_envelope()?;
+        let machine = match self
+            .machines
+            .iter_mut()
+            .find(|machine| machine.index() == envelope.to)
+        {
+            Some(machine) => machine,
+            None => return Some((envelope.to, Ok(()))),
+        };
+        if machine.is_done() {
+            return Some((envelope.to, Ok(())));
+        }
+        let mut outgoing = Vec::new();
+        let res = machine.handle_message(&envelope.from, envelope.message, &mut outgoing);
+        self.send(envelope.to, outgoing);
+        Some((envelope.to, res))
+    }
+
+    // Starts the machines and delivers messages until there are none left. Every invalid message
+    // is returned together with the index of the player that received it.
+    pub fn run(&mut self) -> Vec<(Scalar, Blame<P::Error>)> {
+        self.start();
+        let mut blames = Vec::new();
+        while let Some((to, res)) = self.step() {
+            if let Err(blame) = res {
+                blames.push((to, blame));
+            }
+        }
+        blames
+    }
+
+    pub fn outputs(&self) -> Vec<Option<P::Output>> {
+        self.machines.iter().map(P::output).collect()
+    }
+
+    fn next_envelope(&mut self) -> Option<Envelope<P::Message>> {
+        match self.delivery {
+            Delivery::InOrder => self.in_flight.pop_front(),
+            Delivery::Reverse => self.in_flight.pop_back(),
+            Delivery::Random(_) => {
+                if self.in_flight.is_empty() {
+                    return None;
+                }
+                let i = (self.next_random() % self.in_flight.len() as u64) as usize;
+                self.in_flight.swap_remove_back(i)
+            }
+        }
+    }
+
+    // SplitMix64, which is good enough for shuffling messages and works for any seed.
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+// Runs the machines to completion and returns the output of each player, in the same order as the
+// machines. Panics if any message is invalid or if any player does not finish.
+pub fn run<P>(machines: Vec<P>, delivery: Delivery) -> Vec<P::Output>
+where
+    P: Protocol,
+    P::Error: Debug,
+{
+    let mut network = Network::new(machines, delivery);
+    let blames = network.run();
+    assert!(blames.is_empty(), "invalid messages: {:?}", blames);
+    network
+        .outputs()
+        .into_iter()
+        .map(|output| output.expect("player should have finished"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beaver;
+    use crate::brng;
+    use crate::circuit::{self, Circuit, Value};
+    use crate::coin::{self, Coin};
+    use crate::elgamal;
+    use crate::mulopen;
+    use crate::open;
+    use crate::params::{Parameters, ParamsError};
+    use crate::prefix;
+    use crate::randbit;
+    use crate::recovery;
+    use crate::reshare;
+    use crate::rng;
+    use crate::shared::SharedScalarBatch;
+    use crate::testutil;
+    use crate::util;
+    use crate::vrf;
+    use secp256k1::group::Gej;
+    use secp256k1::scalar;
+    use shamir::vss::{self, SharingCommitment, VShare};
+
+    const ORDERS: [Delivery; 4] = [
+        Delivery::InOrder,
+        Delivery::Reverse,
+        Delivery::Random(0),
+        Delivery::Random(1234),
+    ];
+
+    fn params_by_player(indices: &[Scalar], h: &Gej) -> Vec<Parameters> {
+        indices
+            .iter()
+            .map(|index| Parameters::new(indices.to_vec(), *index, *h).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn open_machines_agree_on_the_secrets() {
+        let n = 10;
+        let k = 3;
+        let b = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, decommitments) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let expected: Vec<(Scalar, Scalar)> = secrets.into_iter().zip(decommitments).collect();
+
+        for delivery in ORDERS.iter() {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+                .map(|(params, vshare_batch)| {
+                    open::Machine::new(params, commitment_batch.clone(), vshare_batch.clone())
+                })
+                .collect();
+            for output in run(machines, *delivery) {
+                assert_eq!(output, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn rng_machines_produce_a_valid_sharing() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (inputs_by_player, commitments) = testutil::rxg_inputs(k, b, &indices, &h);
+
+        for delivery in ORDERS.iter() {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .map(|params| {
+                    let inputs = &inputs_by_player[&params.index];
+                    rng::Machine::new_rng(params, inputs, &commitments)
+                })
+                .collect();
+            let outputs = run(machines, *delivery);
+
+            let commitment_batch = outputs[0].1.clone();
+            for (vshare_batch, output_commitment_batch) in outputs.iter() {
+                assert_eq!(output_commitment_batch, &commitment_batch);
+                for (vshare, commitment) in vshare_batch.iter().zip(commitment_batch.iter()) {
+                    assert!(vss::vshare_is_valid(vshare, commitment, &h));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mulopen_machines_open_the_products() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (a_vshares_by_player, a_commitment_batch, a_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (b_vshares_by_player, b_commitment_batch, b_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (z_vshares_by_player, z_commitment_batch) =
+            testutil::zero_sharing_batch(n, k, b, &indices, &h);
+        let expected: Vec<Scalar> = a_secrets
+            .iter()
+            .zip(b_secrets.iter())
+            .map(|(a, b)| a * b)
+            .collect();
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(n);
+            for (i, params) in params_by_player(&indices, &h).into_iter().enumerate() {
+                machines.push(
+                    mulopen::Machine::new(
+                        params,
+                        a_vshares_by_player[i].clone(),
+                        b_vshares_by_player[i].clone(),
+                        z_vshares_by_player[i].clone(),
+                        a_commitment_batch.clone(),
+                        b_commitment_batch.clone(),
+                        z_commitment_batch.clone(),
+                    )
+                    .unwrap(),
+                );
+            }
+            for output in run(machines, *delivery) {
+                assert_eq!(output, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn messages_for_missing_players_are_dropped() {
+        let n = 10;
+        let k = 3;
+        let b = 1;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+
+        // Only the first k players are online, which is just enough to open.
+        let machines: Vec<_> = params_by_player(&indices, &h)
+            .into_iter()
+            .zip(vshares_by_player.iter())
+            .take(k)
+            .map(|(params, vshare_batch)| {
+                open::Machine::new(params, commitment_batch.clone(), vshare_batch.clone())
+            })
+            .collect();
+        let mut network = Network::new(machines, Delivery::Random(7));
+        assert!(network.run().is_empty());
+        assert!(network.in_flight().is_empty());
+        for output in network.outputs() {
+            assert_eq!(output.unwrap()[0].0, secrets[0]);
+        }
+    }
+
+    // Reconstructs the secret of each sharing in the batch from the output shares of every player.
+    fn secrets(vshares_by_player: Vec<Vec<VShare>>) -> Vec<Scalar> {
+        testutil::transpose(vshares_by_player)
+            .iter()
+            .map(|sharing| vss::interpolate_shares_at_zero(sharing.iter()).0)
+            .collect()
+    }
+
+    fn assert_valid(vshare_batch: &[VShare], commitment_batch: &[SharingCommitment], h: &Gej) {
+        assert_eq!(vshare_batch.len(), commitment_batch.len());
+        for (vshare, commitment) in vshare_batch.iter().zip(commitment_batch.iter()) {
+            assert!(vss::vshare_is_valid(vshare, commitment, h));
+        }
+    }
+
+    #[test]
+    fn brng_machines_produce_a_valid_sharing() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+
+        for delivery in ORDERS.iter() {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .map(|params| brng::Machine::new(params, k, b).unwrap())
+                .collect();
+            let outputs = run(machines, *delivery);
+
+            let commitment_batch = outputs[0].1.clone();
+            assert!(commitment_batch.iter().all(|com| com.len() == k));
+            for (vshare_batch, output_commitment_batch) in outputs.iter() {
+                assert_eq!(output_commitment_batch, &commitment_batch);
+                assert_valid(vshare_batch, &commitment_batch, &h);
+            }
+        }
+    }
+
+    #[test]
+    fn randbit_machines_produce_bits() {
+        let n = 10;
+        let k = 3;
+        let b = 4;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (r_vshares_by_player, r_commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (z_vshares_by_player, z_commitment_batch) =
+            testutil::rzg_by_player(2 * k - 1, b, &indices, &h);
+        let one = util::scalar_from_u64(1);
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(n);
+            for (i, params) in params_by_player(&indices, &h).into_iter().enumerate() {
+                machines.push(
+                    randbit::Machine::new(
+                        params,
+                        r_vshares_by_player[i].clone(),
+                        z_vshares_by_player[i].clone(),
+                        r_commitment_batch.clone(),
+                        z_commitment_batch.clone(),
+                    )
+                    .unwrap(),
+                );
+            }
+            let outputs: Vec<_> = run(machines, *delivery)
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+
+            let commitment_batch = outputs[0].1.clone();
+            let mut bits_by_player = Vec::with_capacity(n);
+            for (bit_batch, output_commitment_batch) in outputs {
+                assert_eq!(output_commitment_batch, commitment_batch);
+                assert_valid(&bit_batch, &commitment_batch, &h);
+                bits_by_player.push(bit_batch);
+            }
+            for bit in secrets(bits_by_player) {
+                assert!(bit.is_zero() || bit == one);
+            }
+        }
+    }
+
+    #[test]
+    fn triple_machines_produce_valid_triples() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (a_vshares_by_player, a_commitment_batch, a_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (b_vshares_by_player, b_commitment_batch, b_secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (
+            (r_low_vshares_by_player, r_low_commitment_batch),
+            (r_high_vshares_by_player, r_high_commitment_batch),
+        ) = testutil::double_rng_by_player(k, b, &indices, &h);
+        let expected: Vec<Scalar> = a_secrets
+            .iter()
+            .zip(b_secrets.iter())
+            .map(|(a, b)| a * b)
+            .collect();
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(n);
+            for (i, params) in params_by_player(&indices, &h).into_iter().enumerate() {
+                machines.push(
+                    beaver::TripleMachine::new(
+                        params,
+                        a_vshares_by_player[i].clone(),
+                        b_vshares_by_player[i].clone(),
+                        (
+                            r_low_vshares_by_player[i].clone(),
+                            r_high_vshares_by_player[i].clone(),
+                        ),
+                        a_commitment_batch.clone(),
+                        b_commitment_batch.clone(),
+                        (
+                            r_low_commitment_batch.clone(),
+                            r_high_commitment_batch.clone(),
+                        ),
+                    )
+                    .unwrap(),
+                );
+            }
+            let outputs = run(machines, *delivery);
+
+            let triple_commitment_batch = outputs[0].1.clone();
+            let mut c_vshares_by_player: Vec<Vec<VShare>> = Vec::with_capacity(n);
+            for (triple_batch, output_triple_commitment_batch) in outputs {
+                assert_eq!(output_triple_commitment_batch, triple_commitment_batch);
+                for (triple, commitment) in triple_batch.iter().zip(triple_commitment_batch.iter())
+                {
+                    assert!(vss::vshare_is_valid(&triple.a, &commitment.a, &h));
+                    assert!(vss::vshare_is_valid(&triple.b, &commitment.b, &h));
+                    assert!(vss::vshare_is_valid(&triple.c, &commitment.c, &h));
+                }
+                c_vshares_by_player.push(triple_batch.iter().map(|triple| triple.c).collect());
+            }
+            assert_eq!(secrets(c_vshares_by_player), expected);
+        }
+    }
+
+    #[test]
+    fn prefix_machines_compute_the_prefix_products() {
+        let n = 10;
+        let k = 3;
+        let m = 4;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let batch = |vshares_by_player: Vec<Vec<VShare>>,
+                     commitment_batch: Vec<SharingCommitment>| {
+            vshares_by_player
+                .into_iter()
+                .map(|vshare_batch| SharedScalarBatch::new(vshare_batch, commitment_batch.clone()))
+                .collect::<Vec<_>>()
+        };
+        let random = || {
+            let (vshares_by_player, commitment_batch, secrets, _) =
+                testutil::random_sharing_batch(n, k, m, &indices, &h);
+            (batch(vshares_by_player, commitment_batch), secrets)
+        };
+        let zero = |threshold| {
+            let (vshares_by_player, commitment_batch) =
+                testutil::zero_sharing_batch(n, threshold, m, &indices, &h);
+            batch(vshares_by_player, commitment_batch)
+        };
+        let (x_by_player, x_secrets) = random();
+        let (r_by_player, _) = random();
+        let (s_by_player, _) = random();
+        let z1_by_player = zero(2 * k - 1);
+        let z2_by_player = zero(2 * k - 1);
+        let (triples_by_player, triple_commitment_batch) =
+            testutil::beaver_triple_batch(n, k, m - 1, &indices, &h);
+
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let new = |z2: &SharedScalarBatch, num_triples: usize| {
+            prefix::Machine::new(
+                params.clone(),
+                x_by_player[0].clone(),
+                r_by_player[0].clone(),
+                s_by_player[0].clone(),
+                z1_by_player[0].clone(),
+                z2.clone(),
+                (
+                    triples_by_player[0][..num_triples].to_vec(),
+                    triple_commitment_batch[..num_triples].to_vec(),
+                ),
+            )
+            .err()
+        };
+        assert_eq!(new(&z2_by_player[0], m - 1), None);
+        assert_eq!(
+            new(&z2_by_player[0], m - 2),
+            Some(prefix::PrefixError::InvalidBatchSize)
+        );
+        assert_eq!(
+            new(&zero(k)[0], m - 1),
+            Some(prefix::PrefixError::Params(
+                ParamsError::InvalidCommitmentThreshold
+            ))
+        );
+        let mut expected = Vec::with_capacity(m);
+        let mut product = util::scalar_from_u64(1);
+        for x in x_secrets.iter() {
+            product = &product * x;
+            expected.push(product);
+        }
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(n);
+            for (i, params) in params_by_player(&indices, &h).into_iter().enumerate() {
+                machines.push(
+                    prefix::Machine::new(
+                        params,
+                        x_by_player[i].clone(),
+                        r_by_player[i].clone(),
+                        s_by_player[i].clone(),
+                        z1_by_player[i].clone(),
+                        z2_by_player[i].clone(),
+                        (
+                            triples_by_player[i].clone(),
+                            triple_commitment_batch.clone(),
+                        ),
+                    )
+                    .unwrap(),
+                );
+            }
+            let outputs = run(machines, *delivery);
+
+            let commitment_batch = outputs[0].1.clone();
+            let mut vshares_by_player = Vec::with_capacity(n);
+            for (vshare_batch, output_commitment_batch) in outputs {
+                assert_eq!(output_commitment_batch, commitment_batch);
+                assert_valid(&vshare_batch, &commitment_batch, &h);
+                vshares_by_player.push(vshare_batch);
+            }
+            assert_eq!(secrets(vshares_by_player), expected);
+        }
+    }
+
+    #[test]
+    fn reshare_machines_preserve_the_secrets() {
+        let n = 10;
+        let old_k = 4;
+        let new_k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, expected, _) =
+            testutil::random_sharing_batch(n, old_k, b, &indices, &h);
+
+        // The committee reshares to itself, so every player is both a dealer and a receiver.
+        for delivery in ORDERS.iter() {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+                .map(|(params, vshare_batch)| {
+                    let inst_params = reshare::InstanceParams::new(
+                        indices.clone(),
+                        commitment_batch.clone(),
+                        new_k,
+                    );
+                    reshare::Machine::new(params, inst_params, Some(vshare_batch.clone())).unwrap()
+                })
+                .collect();
+            let outputs = run(machines, *delivery);
+
+            let new_commitment_batch = outputs[0].1.clone();
+            assert!(new_commitment_batch.iter().all(|com| com.len() == new_k));
+            let mut new_vshares_by_player = Vec::with_capacity(n);
+            for (vshare_batch, output_commitment_batch) in outputs {
+                assert_eq!(output_commitment_batch, new_commitment_batch);
+                assert_valid(&vshare_batch, &new_commitment_batch, &h);
+                new_vshares_by_player.push(vshare_batch);
+            }
+            assert_eq!(secrets(new_vshares_by_player), expected);
+        }
+    }
+
+    // The dealers that are not in the new committee have no output, so the network is run directly
+    // rather than with run.
+    #[test]
+    fn reshare_machines_hand_over_to_a_new_committee() {
+        let old_n = 6;
+        let new_n = 5;
+        let old_k = 3;
+        let new_k = 2;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let old_indices = scalar::random_scalars_using_thread_rng(old_n);
+        let new_indices = scalar::random_scalars_using_thread_rng(new_n);
+        let (vshares_by_player, commitment_batch, expected, _) =
+            testutil::random_sharing_batch(old_n, old_k, b, &old_indices, &h);
+        let inst_params =
+            || reshare::InstanceParams::new(old_indices.clone(), commitment_batch.clone(), new_k);
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(old_n + new_n);
+            for (params, vshare_batch) in params_by_player(&old_indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+            {
+                machines.push(
+                    reshare::Machine::new_dealer(
+                        params,
+                        new_indices.clone(),
+                        inst_params(),
+                        vshare_batch.clone(),
+                    )
+                    .unwrap(),
+                );
+            }
+            for params in params_by_player(&new_indices, &h) {
+                machines.push(reshare::Machine::new(params, inst_params(), None).unwrap());
+            }
+            let mut network = Network::new(machines, *delivery);
+            assert!(network.run().is_empty());
+            assert!(network.machines().iter().all(|machine| machine.is_done()));
+
+            let outputs = network.outputs();
+            assert!(outputs[..old_n].iter().all(Option::is_none));
+            let outputs: Vec<_> = outputs[old_n..]
+                .iter()
+                .cloned()
+                .map(Option::unwrap)
+                .collect();
+            let new_commitment_batch = outputs[0].1.clone();
+            let mut new_vshares_by_player = Vec::with_capacity(new_n);
+            for (vshare_batch, output_commitment_batch) in outputs {
+                assert_eq!(output_commitment_batch, new_commitment_batch);
+                assert_valid(&vshare_batch, &new_commitment_batch, &h);
+                new_vshares_by_player.push(vshare_batch);
+            }
+            assert_eq!(secrets(new_vshares_by_player), expected);
+        }
+    }
+
+    // The helpers in recovery have no output, so the network is run directly rather than with run.
+    #[test]
+    fn recovery_machines_recover_the_lost_shares() {
+        let n = 10;
+        let k = 4;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let lost_index = indices[0];
+        let helpers = indices[1..].to_vec();
+        let (vshares_by_player, commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let (inputs_by_player, coeff_commitments) = testutil::rxg_inputs(k - 1, b, &helpers, &h);
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(n);
+            machines.push(
+                recovery::Machine::new_lost(
+                    Parameters::new(indices.clone(), lost_index, h).unwrap(),
+                    commitment_batch.clone(),
+                    &coeff_commitments,
+                )
+                .unwrap(),
+            );
+            for (params, vshare_batch) in params_by_player(&helpers, &h)
+                .into_iter()
+                .zip(vshares_by_player[1..].iter())
+            {
+                let inputs = &inputs_by_player[&params.index];
+                machines.push(
+                    recovery::Machine::new_helper(
+                        params,
+                        lost_index,
+                        vshare_batch.clone(),
+                        inputs,
+                        &coeff_commitments,
+                    )
+                    .unwrap(),
+                );
+            }
+            let mut network = Network::new(machines, *delivery);
+            assert!(network.run().is_empty());
+            assert!(network.machines().iter().all(|machine| machine.is_done()));
+
+            let outputs = network.outputs();
+            assert_eq!(outputs[0], Some(vshares_by_player[0].clone()));
+            assert!(outputs[1..].iter().all(Option::is_none));
+        }
+    }
+
+    #[test]
+    fn beaver_machines_evaluate_a_circuit() {
+        let n = 10;
+        let k = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, inputs, _) =
+            testutil::random_sharing_batch(n, k, 3, &indices, &h);
+
+        // o = open(xy), w = oz.
+        let mut circuit = Circuit::new();
+        let x = circuit.input();
+        let y = circuit.input();
+        let z = circuit.input();
+        let xy = circuit.mul(x, y);
+        let o = circuit.open(xy);
+        let w = circuit.mul(o, z);
+        circuit.output(o);
+        circuit.output(w);
+        let xy_secret = &inputs[0] * &inputs[1];
+        let w_secret = &xy_secret * &inputs[2];
+
+        // Only xy needs a triple, since o is public.
+        let state = circuit::State::new(
+            circuit.clone(),
+            SharedScalarBatch::new(vshares_by_player[0].clone(), commitment_batch.clone()),
+        )
+        .unwrap();
+        assert_eq!(state.num_triples(), 1);
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        assert_eq!(
+            beaver::Machine::new(params, state, Vec::new(), Vec::new()).err(),
+            Some(ParamsError::NotEnoughTriples)
+        );
+
+        let machines = || {
+            let (triples_by_player, triple_commitment_batch) =
+                testutil::beaver_triple_batch(n, k, 1, &indices, &h);
+            let mut machines = Vec::with_capacity(n);
+            for ((params, vshare_batch), triple_batch) in params_by_player(&indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+                .zip(triples_by_player.into_iter())
+            {
+                let state = circuit::State::new(
+                    circuit.clone(),
+                    SharedScalarBatch::new(vshare_batch.clone(), commitment_batch.clone()),
+                )
+                .unwrap();
+                machines.push(
+                    beaver::Machine::new(
+                        params,
+                        state,
+                        triple_batch,
+                        triple_commitment_batch.clone(),
+                    )
+                    .unwrap(),
+                );
+            }
+            machines
+        };
+
+        for delivery in ORDERS.iter() {
+            let machines = machines();
+            let outputs = run(machines, *delivery);
+
+            let mut w_vshares_by_player = Vec::with_capacity(n);
+            for output in outputs {
+                assert_eq!(output[0], Value::Public(xy_secret));
+                match &output[1] {
+                    Value::Shared(shared) => {
+                        assert!(shared.is_valid(&h));
+                        w_vshares_by_player.push(vec![*shared.vshare()]);
+                    }
+                    Value::Public(_) => panic!("output should be shared"),
+                }
+            }
+            assert_eq!(secrets(w_vshares_by_player), vec![w_secret]);
+        }
+
+        // An invalid message that arrives before the machine has started is reported once it
+        // has, and does not stop the circuit from being evaluated.
+        let mut network = Network::new(machines(), Delivery::InOrder);
+        let res = network.machines_mut()[0].handle_message(
+            &indices[1],
+            (0, vshares_by_player[2].clone()),
+            &mut Vec::new(),
+        );
+        assert_eq!(res, Ok(()));
+        let blames = network.run();
+        assert_eq!(
+            blames,
+            vec![(
+                indices[0],
+                Blame::new(indices[1], open::OpenError::InconsistentIndices)
+            )]
+        );
+        assert!(network.outputs().iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn coin_machines_agree_on_the_coin() {
+        let n = 10;
+        let k = 3;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, 1, &indices, &h);
+
+        for delivery in ORDERS.iter() {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .zip(vshares_by_player.iter())
+                .map(|(params, vshare_batch)| {
+                    coin::Machine::new(
+                        params,
+                        Coin::new(vshare_batch[0], commitment_batch[0].clone()),
+                    )
+                })
+                .collect();
+            for output in run(machines, *delivery) {
+                assert_eq!(output, secrets[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn elgamal_machines_decrypt_the_messages() {
+        let n = 10;
+        let k = 3;
+        let b = 2;
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (key_vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let mut messages = Vec::with_capacity(b);
+        let mut ciphertext_batch = Vec::with_capacity(b);
+        for secret in secrets.iter() {
+            let mut pub_key = Gej::default();
+            pub_key.scalar_base_mul(secret);
+            let message = Gej::new_random_using_thread_rng();
+            ciphertext_batch.push(elgamal::encrypt(&pub_key, &message));
+            messages.push(message);
+        }
+
+        for delivery in ORDERS.iter() {
+            let machines = params_by_player(&indices, &h)
+                .into_iter()
+                .zip(key_vshares_by_player.iter())
+                .map(|(params, key_vshare_batch)| {
+                    elgamal::Machine::new(
+                        params,
+                        ciphertext_batch.clone(),
+                        commitment_batch.clone(),
+                        key_vshare_batch,
+                    )
+                })
+                .collect();
+            for output in run(machines, *delivery) {
+                assert_eq!(output, messages);
+            }
+        }
+    }
+
+    #[test]
+    fn vrf_machines_produce_a_verifiable_output() {
+        let n = 10;
+        let k = 3;
+        let input = b"round 7";
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (key_vshares_by_player, key_commitment_batch, key_secrets, _) =
+            testutil::random_sharing_batch(n, k, 1, &indices, &h);
+        let (nonce_vshares_by_player, nonce_commitment_batch, _, _) =
+            testutil::random_sharing_batch(n, k, 1, &indices, &h);
+        let mut pub_key = Gej::default();
+        pub_key.scalar_base_mul(&key_secrets[0]);
+
+        for delivery in ORDERS.iter() {
+            let mut machines = Vec::with_capacity(n);
+            for (i, params) in params_by_player(&indices, &h).into_iter().enumerate() {
+                machines.push(vrf::Machine::new(
+                    params,
+                    input,
+                    pub_key,
+                    key_commitment_batch[0].clone(),
+                    nonce_commitment_batch[0].clone(),
+                    key_vshares_by_player[i][0],
+                    nonce_vshares_by_player[i][0],
+                ));
+            }
+            let outputs = run(machines, *delivery);
+
+            let expected = outputs[0].0;
+            for (output, proof) in outputs {
+                assert_eq!(output, expected);
+                assert_eq!(vrf::verify(&pub_key, input, &proof), Some(expected));
+            }
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use crate::beaver::{Triple, TripleCommitment};
+use crate::mulopen;
+use crate::open;
+use crate::params::Parameters;
+use crate::rng::{self, DirectedVShare};
+use crate::util;
 use secp256k1::group::Gej;
 use secp256k1::scalar::Scalar;
 use shamir::vss::{self, SharingCommitment, VShare};
@@ -133,3 +139,261 @@ pub fn rxg_inputs(
 
     (inputs_by_player, commitments)
 }
+
+// Runs a protocol from the rng module between all of the players, with coeffs random coefficients
+// for each output. Returns the outputs of each player and the coefficient commitments.
+fn rxg_by_player<F, G>(
+    coeffs: usize,
+    b: usize,
+    indices: &[Scalar],
+    h: &Gej,
+    messages: F,
+    own_commitment_batch: G,
+) -> (Vec<Vec<VShare>>, Vec<Vec<SharingCommitment>>)
+where
+    F: Fn(&[Vec<VShare>], &[Scalar]) -> Vec<Vec<DirectedVShare>>,
+    G: Fn(&[Vec<SharingCommitment>], &Scalar) -> Vec<SharingCommitment>,
+{
+    let (inputs_by_player, coeff_commitments) = rxg_inputs(coeffs, b, indices, h);
+    let messages_by_dealer: Vec<_> = indices
+        .iter()
+        .map(|index| messages(&inputs_by_player[index], indices))
+        .collect();
+
+    let mut outputs_by_player = Vec::with_capacity(indices.len());
+    for (i, index) in indices.iter().enumerate() {
+        let inst_params =
+            open::InstanceParams::new(own_commitment_batch(&coeff_commitments, index));
+        let params = Parameters::new(indices.to_vec(), *index, *h).unwrap();
+        let mut state = open::State::new(&inst_params);
+        let output = messages_by_dealer
+            .iter()
+            .find_map(|messages| {
+                rng::handle_directed_vshare_batch(
+                    &mut state,
+                    &inst_params,
+                    &params,
+                    messages[i].clone(),
+                )
+                .expect("shares should be valid")
+            })
+            .expect("not enough shares to open");
+        outputs_by_player.push(output);
+    }
+
+    (outputs_by_player, coeff_commitments)
+}
+
+// Random sharings with threshold k, generated by running rng.
+pub fn rng_by_player(
+    k: usize,
+    b: usize,
+    indices: &[Scalar],
+    h: &Gej,
+) -> (Vec<Vec<VShare>>, Vec<SharingCommitment>) {
+    let (vshares_by_player, coeff_commitments) = rxg_by_player(
+        k,
+        b,
+        indices,
+        h,
+        rng::initial_messages_batch_rng,
+        rng::own_commitment_batch_rng,
+    );
+    (
+        vshares_by_player,
+        rng::output_commitment_batch_rng(&coeff_commitments),
+    )
+}
+
+// Sharings of zero with threshold k, generated by running rzg.
+pub fn rzg_by_player(
+    k: usize,
+    b: usize,
+    indices: &[Scalar],
+    h: &Gej,
+) -> (Vec<Vec<VShare>>, Vec<SharingCommitment>) {
+    let (vshares_by_player, coeff_commitments) = rxg_by_player(
+        k - 1,
+        b,
+        indices,
+        h,
+        rng::initial_messages_batch_rzg,
+        rng::own_commitment_batch_rzg,
+    );
+    (
+        vshares_by_player,
+        rng::output_commitment_batch_rzg(&coeff_commitments),
+    )
+}
+
+// Random double sharings with thresholds k and 2k - 1, generated by running the double rng. The
+// low threshold sharings are returned first.
+#[allow(clippy::type_complexity)]
+pub fn double_rng_by_player(
+    k: usize,
+    b: usize,
+    indices: &[Scalar],
+    h: &Gej,
+) -> (
+    (Vec<Vec<VShare>>, Vec<SharingCommitment>),
+    (Vec<Vec<VShare>>, Vec<SharingCommitment>),
+) {
+    let (vshares_by_player, coeff_commitments) = rxg_by_player(
+        2 * k - 1,
+        b,
+        indices,
+        h,
+        |coeff_shares_batch, indices| {
+            rng::initial_messages_batch_double_rng(coeff_shares_batch, indices, k)
+        },
+        |coeff_commitments_batch, index| {
+            rng::own_commitment_batch_double_rng(coeff_commitments_batch, index, k)
+        },
+    );
+    let (low_commitments, high_commitments) =
+        rng::output_commitment_batch_double_rng(&coeff_commitments, k);
+    let mut low_by_player = Vec::with_capacity(indices.len());
+    let mut high_by_player = Vec::with_capacity(indices.len());
+    for mut vshares in vshares_by_player {
+        high_by_player.push(vshares.split_off(b));
+        low_by_player.push(vshares);
+    }
+    (
+        (low_by_player, low_commitments),
+        (high_by_player, high_commitments),
+    )
+}
+
+pub fn beaver_triple_batch(
+    n: usize,
+    k: usize,
+    b: usize,
+    indices: &[Scalar],
+    h: &Gej,
+) -> (Vec<Vec<Triple>>, Vec<TripleCommitment>) {
+    let (a_shares_by_player, a_commitments, a_secrets, _) =
+        random_sharing_batch(n, k, b, indices, h);
+    let (b_shares_by_player, b_commitments, b_secrets, _) =
+        random_sharing_batch(n, k, b, indices, h);
+
+    let mut c_sharing_batch = Vec::with_capacity(b);
+    let mut triple_commitment_batch = Vec::with_capacity(b);
+    for (((a_secret, b_secret), a_commitment), b_commitment) in a_secrets
+        .iter()
+        .zip(b_secrets.iter())
+        .zip(a_commitments.into_iter())
+        .zip(b_commitments.into_iter())
+    {
+        let (c_vshares, c_commitment) = vss::vshare_secret(h, indices, &(a_secret * b_secret), k);
+        c_sharing_batch.push(c_vshares);
+        triple_commitment_batch.push(TripleCommitment {
+            a: a_commitment,
+            b: b_commitment,
+            c: c_commitment,
+        });
+    }
+    let c_shares_by_player = transpose(c_sharing_batch);
+
+    let mut triples_by_player = Vec::with_capacity(n);
+    for ((a_vshares, b_vshares), c_vshares) in a_shares_by_player
+        .into_iter()
+        .zip(b_shares_by_player.into_iter())
+        .zip(c_shares_by_player.into_iter())
+    {
+        let mut triple_batch = Vec::with_capacity(b);
+        for ((a, b), c) in a_vshares
+            .into_iter()
+            .zip(b_vshares.into_iter())
+            .zip(c_vshares.into_iter())
+        {
+            triple_batch.push(Triple { a, b, c });
+        }
+        triples_by_player.push(triple_batch);
+    }
+
+    (triples_by_player, triple_commitment_batch)
+}
+
+pub fn open_by_player(
+    vshares_by_player: &[Vec<VShare>],
+    commitment_batch: Vec<SharingCommitment>,
+    indices: &[Scalar],
+    h: &Gej,
+) -> Vec<(Scalar, Scalar)> {
+    let inst_params = open::InstanceParams::new(commitment_batch);
+    let params = Parameters::new(indices.to_vec(), indices[0], *h).unwrap();
+    let mut state = open::State::new(&inst_params);
+    for vshare_batch in vshares_by_player.iter() {
+        let res = state
+            .handle_vshare_batch(&inst_params, &params, vshare_batch.clone())
+            .expect("shares should be valid");
+        if let Some(values) = res {
+            return values;
+        }
+    }
+    panic!("not enough shares to open")
+}
+
+pub fn mulopen_by_player(
+    a_vshares_by_player: &[Vec<VShare>],
+    b_vshares_by_player: &[Vec<VShare>],
+    z_vshares_by_player: &[Vec<VShare>],
+    a_commitment_batch: &[SharingCommitment],
+    b_commitment_batch: &[SharingCommitment],
+    z_commitment_batch: &[SharingCommitment],
+    h: &Gej,
+) -> Vec<Scalar> {
+    let b = a_commitment_batch.len();
+    let mut state = Vec::with_capacity(b);
+    state.resize_with(b, Vec::new);
+    for ((a_vshares, b_vshares), z_vshares) in a_vshares_by_player
+        .iter()
+        .zip(b_vshares_by_player.iter())
+        .zip(z_vshares_by_player.iter())
+    {
+        let message_batch = mulopen::initial_message_batch(
+            a_vshares.clone(),
+            b_vshares.clone(),
+            z_vshares.clone(),
+            h,
+        );
+        let res = mulopen::handle_message_batch(
+            &mut state,
+            message_batch,
+            a_commitment_batch,
+            b_commitment_batch,
+            z_commitment_batch,
+            h,
+        )
+        .expect("messages should be valid");
+        if let Some(values) = res {
+            return values;
+        }
+    }
+    panic!("not enough messages to open")
+}
+
+pub fn random_bit_sharing_batch(
+    k: usize,
+    b: usize,
+    indices: &[Scalar],
+    h: &Gej,
+) -> (Vec<Vec<VShare>>, Vec<SharingCommitment>, Vec<bool>) {
+    let mut sharing_batch = Vec::with_capacity(b);
+    let mut commitment_batch = Vec::with_capacity(b);
+    let mut bits = Vec::with_capacity(b);
+    for _ in 0..b {
+        let bit = util::scalar_to_bits_le(&Scalar::new_random_using_thread_rng())[0];
+        let secret = if bit {
+            util::scalar_from_u64(1)
+        } else {
+            Scalar::zero()
+        };
+        let (vshares, commitment) = vss::vshare_secret(h, indices, &secret, k);
+        sharing_batch.push(vshares);
+        commitment_batch.push(commitment);
+        bits.push(bit);
+    }
+
+    (transpose(sharing_batch), commitment_batch, bits)
+}
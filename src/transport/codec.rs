@@ -0,0 +1,218 @@
+use crate::rng::DirectedVShare;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::sss::Share;
+use shamir::vss::{SharingCommitment, VShare};
+use std::convert::TryInto;
+
+// Encoding of messages for transports that send bytes. Decoding reads from the front of the
+// slice and advances it, so that the encodings of composite types are just the encodings of their
+// parts in order.
+pub trait Codec: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    fn decode(bs: &mut &[u8]) -> Option<Self>;
+}
+
+fn take<'a>(bs: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bs.len() < len {
+        return None;
+    }
+    let (head, tail) = bs.split_at(len);
+    *bs = tail;
+    Some(head)
+}
+
+impl Codec for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(u64::from_be_bytes(take(bs, 8)?.try_into().ok()?))
+    }
+}
+
+impl Codec for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        u64::decode(bs)?.try_into().ok()
+    }
+}
+
+impl Codec for Scalar {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut bs = [0_u8; 32];
+        self.put_b32(&mut bs);
+        buf.extend_from_slice(&bs);
+    }
+
+    // Encodings that are not less than the group order are rejected, so that every scalar has a
+    // single encoding. These are detected by the value having been reduced.
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        let scalar_bs = take(bs, 32)?;
+        let mut scalar = Scalar::default();
+        scalar.set_b32(scalar_bs);
+        let mut reduced = [0_u8; 32];
+        scalar.put_b32(&mut reduced);
+        if reduced[..] != scalar_bs[..] {
+            return None;
+        }
+        Some(scalar)
+    }
+}
+
+impl Codec for Gej {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut bs = [0_u8; 33];
+        self.put_bytes(&mut bs);
+        buf.extend_from_slice(&bs);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        let mut point_bs = [0_u8; 33];
+        point_bs.copy_from_slice(take(bs, 33)?);
+        let mut point = Gej::default();
+        point.set_bytes(&point_bs).ok()?;
+        Some(point)
+    }
+}
+
+impl Codec for Share {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.index.encode(buf);
+        self.value.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(Share {
+            index: Scalar::decode(bs)?,
+            value: Scalar::decode(bs)?,
+        })
+    }
+}
+
+impl Codec for VShare {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.share.encode(buf);
+        self.decommitment.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(VShare {
+            share: Share::decode(bs)?,
+            decommitment: Scalar::decode(bs)?,
+        })
+    }
+}
+
+impl Codec for DirectedVShare {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.vshare.encode(buf);
+        self.to.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(DirectedVShare {
+            vshare: VShare::decode(bs)?,
+            to: Scalar::decode(bs)?,
+        })
+    }
+}
+
+impl Codec for SharingCommitment {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        for i in 0..self.len() {
+            self[i].encode(buf);
+        }
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some(SharingCommitment::new_from_vec(Vec::<Gej>::decode(bs)?))
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        for t in self.iter() {
+            t.encode(buf);
+        }
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(bs)?;
+        // The length is not trusted, since every element takes at least one byte.
+        let mut v = Vec::with_capacity(len.min(bs.len()));
+        for _ in 0..len {
+            v.push(T::decode(bs)?);
+        }
+        Some(v)
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    fn decode(bs: &mut &[u8]) -> Option<Self> {
+        Some((A::decode(bs)?, B::decode(bs)?))
+    }
+}
+
+// Decodes a complete message, so that trailing bytes are an error.
+pub fn decode_exact<T: Codec>(mut bs: &[u8]) -> Option<T> {
+    let t = T::decode(&mut bs)?;
+    if bs.is_empty() {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_survive_encoding() {
+        let vshare = VShare {
+            share: Share {
+                index: Scalar::new_random_using_thread_rng(),
+                value: Scalar::new_random_using_thread_rng(),
+            },
+            decommitment: Scalar::new_random_using_thread_rng(),
+        };
+        let message = (7_usize, vec![vshare; 3]);
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+        assert_eq!(decode_exact(&buf), Some(message));
+
+        buf.push(0);
+        assert_eq!(decode_exact::<(usize, Vec<VShare>)>(&buf), None);
+        assert_eq!(decode_exact::<(usize, Vec<VShare>)>(&buf[..40]), None);
+
+        let point = Gej::new_random_using_thread_rng();
+        let mut buf = Vec::new();
+        point.encode(&mut buf);
+        assert_eq!(decode_exact(&buf), Some(point));
+    }
+
+    #[test]
+    fn scalars_have_a_single_encoding() {
+        let scalar = Scalar::new_random_using_thread_rng();
+        let mut buf = Vec::new();
+        scalar.encode(&mut buf);
+        assert_eq!(decode_exact(&buf), Some(scalar));
+
+        // 2^256 - 1 is not less than the group order, and would otherwise decode to the same
+        // scalar as 2^256 - 1 - n.
+        assert_eq!(decode_exact::<Scalar>(&[0xff; 32]), None);
+    }
+}
@@ -0,0 +1,60 @@
+use super::{Transport, TransportError};
+use secp256k1::scalar::Scalar;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+// A transport between players in the same process, using a channel for each player.
+pub struct Loopback<M> {
+    index: Scalar,
+    senders: Vec<(Scalar, Sender<(Scalar, M)>)>,
+    receiver: Receiver<(Scalar, M)>,
+}
+
+// Creates a transport for each of the players, in the same order as the indices.
+pub fn network<M>(indices: &[Scalar]) -> Vec<Loopback<M>> {
+    let (senders, receivers): (Vec<_>, Vec<_>) = indices.iter().map(|_| mpsc::channel()).unzip();
+    let senders: Vec<_> = indices.iter().cloned().zip(senders.into_iter()).collect();
+    indices
+        .iter()
+        .zip(receivers.into_iter())
+        .map(|(index, receiver)| Loopback {
+            index: *index,
+            senders: senders.clone(),
+            receiver,
+        })
+        .collect()
+}
+
+impl<M: Clone> Transport<M> for Loopback<M> {
+    fn index(&self) -> Scalar {
+        self.index
+    }
+
+    fn send(&mut self, to: &Scalar, message: &M) -> Result<(), TransportError> {
+        let (_, sender) = self
+            .senders
+            .iter()
+            .find(|(index, _)| index == to)
+            .ok_or(TransportError::UnknownIndex)?;
+        sender
+            .send((self.index, message.clone()))
+            .map_err(|_| TransportError::Disconnected)
+    }
+
+    fn broadcast(&mut self, message: &M) -> Result<(), TransportError> {
+        for (_, sender) in self.senders.iter() {
+            sender
+                .send((self.index, message.clone()))
+                .map_err(|_| TransportError::Disconnected)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Option<(Scalar, M)>, TransportError> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(received) => Ok(Some(received)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(TransportError::Disconnected),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::protocol::{Blame, Outgoing, Protocol, Recipient};
+use secp256k1::scalar::Scalar;
+use std::io;
+use std::time::{Duration, Instant};
+
+pub mod codec;
+pub mod loopback;
+pub mod tcp;
+
+pub use loopback::Loopback;
+pub use tcp::Tcp;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransportError {
+    UnknownIndex,
+    Disconnected,
+    InvalidMessage,
+    Io(io::ErrorKind),
+}
+
+impl From<io::Error> for TransportError {
+    fn from(e: io::Error) -> Self {
+        TransportError::Io(e.kind())
+    }
+}
+
+// Delivers messages between players, who are identified by their indices. A broadcast message is
+// also delivered to the sender.
+pub trait Transport<M> {
+    fn index(&self) -> Scalar;
+
+    fn send(&mut self, to: &Scalar, message: &M) -> Result<(), TransportError>;
+
+    fn broadcast(&mut self, message: &M) -> Result<(), TransportError>;
+
+    // Waits for a message for at most the given duration, and returns None if there was none.
+    fn receive(&mut self, timeout: Duration) -> Result<Option<(Scalar, M)>, TransportError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunError {
+    Timeout,
+    Transport(TransportError),
+}
+
+impl From<TransportError> for RunError {
+    fn from(e: TransportError) -> Self {
+        RunError::Transport(e)
+    }
+}
+
+// The output of the machine, which is None for players that finish without one (e.g. dealers that
+// only help other players), together with the invalid messages that were received.
+pub type RunResult<O, E> = Result<(Option<O>, Vec<Blame<E>>), RunError>;
+
+// Drives the machine to completion over the transport. Invalid messages are ignored, apart from
+// being reported at the end. The run fails if no new round is started within round_timeout, where
+// a new round starts whenever handling a message produces messages to send.
+pub fn run<P, T>(
+    machine: &mut P,
+    transport: &mut T,
+    round_timeout: Duration,
+) -> RunResult<P::Output, P::Error>
+where
+    P: Protocol,
+    T: Transport<P::Message>,
+{
+    let mut blames = Vec::new();
+    send_all(transport, machine.initial_messages())?;
+    let mut deadline = Instant::now() + round_timeout;
+    while !machine.is_done() {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(RunError::Timeout);
+        }
+        let (from, message) = match transport.receive(deadline - now)? {
+            Some(received) => received,
+            None => return Err(RunError::Timeout),
+        };
+        let mut outgoing = Vec::new();
+        let res = machine.handle_message(&from, message, &mut outgoing);
+        if !outgoing.is_empty() {
+            deadline = Instant::now() + round_timeout;
+        }
+        send_all(transport, outgoing)?;
+        if let Err(blame) = res {
+            blames.push(blame);
+        }
+    }
+    Ok((machine.output(), blames))
+}
+
+fn send_all<M, T: Transport<M>>(
+    transport: &mut T,
+    outgoing: Vec<Outgoing<M>>,
+) -> Result<(), TransportError> {
+    for Outgoing { to, message } in outgoing {
+        match to {
+            Recipient::All => transport.broadcast(&message)?,
+            Recipient::One(to) => transport.send(&to, &message)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open;
+    use crate::params::Parameters;
+    use crate::testutil;
+    use secp256k1::group::Gej;
+    use secp256k1::scalar;
+    use shamir::vss::VShare;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn open_machines(n: usize, k: usize) -> (Vec<Scalar>, Vec<open::Machine>, Vec<Scalar>) {
+        let b = 2;
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (vshares_by_player, commitment_batch, secrets, _) =
+            testutil::random_sharing_batch(n, k, b, &indices, &h);
+        let machines = indices
+            .iter()
+            .zip(vshares_by_player.into_iter())
+            .map(|(index, vshare_batch)| {
+                let params = Parameters::new(indices.clone(), *index, h).unwrap();
+                open::Machine::new(params, commitment_batch.clone(), vshare_batch)
+            })
+            .collect();
+        (indices, machines, secrets)
+    }
+
+    // Runs each player in their own thread. The transports are returned so that no player
+    // disconnects before every player has finished.
+    fn run_in_threads<T>(machines: Vec<open::Machine>, transports: Vec<T>, secrets: &[Scalar])
+    where
+        T: Transport<Vec<VShare>> + Send + 'static,
+    {
+        let handles: Vec<_> = machines
+            .into_iter()
+            .zip(transports.into_iter())
+            .map(|(mut machine, mut transport)| {
+                thread::spawn(move || {
+                    let res = run(&mut machine, &mut transport, TIMEOUT);
+                    (res, transport)
+                })
+            })
+            .collect();
+        for handle in handles {
+            let (res, _transport) = handle.join().unwrap();
+            let (output, blames) = res.unwrap();
+            assert!(blames.is_empty());
+            let values: Vec<Scalar> = output.unwrap().iter().map(|(s, _)| *s).collect();
+            assert_eq!(values, secrets);
+        }
+    }
+
+    #[test]
+    fn open_runs_over_loopback() {
+        let (indices, machines, secrets) = open_machines(10, 3);
+        let transports = loopback::network(&indices);
+        run_in_threads(machines, transports, &secrets);
+    }
+
+    #[test]
+    fn open_runs_over_tcp() {
+        let n = 5;
+        let (indices, machines, secrets) = open_machines(n, 3);
+        let listeners: Vec<TcpListener> = (0..n)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let peers: Vec<_> = indices
+            .iter()
+            .zip(listeners.iter())
+            .map(|(index, listener)| (*index, listener.local_addr().unwrap()))
+            .collect();
+        let transports = indices
+            .iter()
+            .zip(listeners.into_iter())
+            .map(|(index, listener)| Tcp::connect(*index, listener, &peers).unwrap())
+            .collect();
+        run_in_threads(machines, transports, &secrets);
+    }
+
+    #[test]
+    fn run_times_out_without_enough_players() {
+        let (indices, mut machines, _) = open_machines(10, 3);
+        let mut transports = loopback::network(&indices);
+        let res = run(
+            &mut machines[0],
+            &mut transports[0],
+            Duration::from_millis(50),
+        );
+        assert_eq!(res.map(|_| ()), Err(RunError::Timeout));
+    }
+}
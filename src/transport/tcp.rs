@@ -0,0 +1,164 @@
+use super::codec::{self, Codec};
+use super::{Transport, TransportError};
+use secp256k1::scalar::Scalar;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+// Frames longer than this are treated as invalid, so that a peer cannot make us allocate an
+// arbitrary amount of memory.
+const MAX_FRAME_LEN: usize = 1 << 24;
+
+// A transport over TCP, where every player connects to every other player and sends their index
+// when they connect. Messages are sent as length prefixed frames. Messages from each peer are read
+// on a separate thread, and a peer that sends an invalid frame is no longer read from.
+//
+// The indices that peers claim are not authenticated, so this should only be used between
+// players that trust the network, e.g. on localhost.
+pub struct Tcp<M> {
+    index: Scalar,
+    streams: Vec<(Scalar, TcpStream)>,
+    own_sender: Sender<(Scalar, M)>,
+    receiver: Receiver<(Scalar, M)>,
+}
+
+impl<M: Codec + Send + 'static> Tcp<M> {
+    // Connects to all of the peers, which must include the player themselves. The listeners of all
+    // of the players need to be bound before any of them connect.
+    pub fn connect(
+        index: Scalar,
+        listener: TcpListener,
+        peers: &[(Scalar, SocketAddr)],
+    ) -> Result<Self, TransportError> {
+        let (own_sender, receiver) = mpsc::channel();
+
+        let others: Vec<Scalar> = peers
+            .iter()
+            .map(|(peer, _)| *peer)
+            .filter(|peer| peer != &index)
+            .collect();
+        let sender = own_sender.clone();
+        thread::spawn(move || accept(listener, others, sender));
+
+        let mut streams = Vec::with_capacity(peers.len());
+        for (peer, addr) in peers.iter().filter(|(peer, _)| peer != &index) {
+            let mut stream = TcpStream::connect(addr)?;
+            stream.set_nodelay(true)?;
+            let mut buf = Vec::with_capacity(32);
+            index.encode(&mut buf);
+            stream.write_all(&buf)?;
+            streams.push((*peer, stream));
+        }
+
+        Ok(Tcp {
+            index,
+            streams,
+            own_sender,
+            receiver,
+        })
+    }
+}
+
+fn accept<M: Codec + Send + 'static>(
+    listener: TcpListener,
+    mut expected: Vec<Scalar>,
+    sender: Sender<(Scalar, M)>,
+) {
+    while !expected.is_empty() {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(_) => return,
+        };
+        let mut bs = [0_u8; 32];
+        if stream.read_exact(&mut bs).is_err() {
+            continue;
+        }
+        let peer = match codec::decode_exact::<Scalar>(&bs) {
+            Some(peer) => peer,
+            None => continue,
+        };
+        let i = match expected.iter().position(|index| index == &peer) {
+            Some(i) => i,
+            None => continue,
+        };
+        expected.swap_remove(i);
+        let sender = sender.clone();
+        thread::spawn(move || read_frames(stream, peer, sender));
+    }
+}
+
+fn read_frames<M: Codec>(mut stream: TcpStream, peer: Scalar, sender: Sender<(Scalar, M)>) {
+    loop {
+        let mut len_bs = [0_u8; 4];
+        if stream.read_exact(&mut len_bs).is_err() {
+            return;
+        }
+        let len = u32::from_be_bytes(len_bs) as usize;
+        if len > MAX_FRAME_LEN {
+            return;
+        }
+        let mut frame = vec![0_u8; len];
+        if stream.read_exact(&mut frame).is_err() {
+            return;
+        }
+        let message = match codec::decode_exact(&frame) {
+            Some(message) => message,
+            None => return,
+        };
+        if sender.send((peer, message)).is_err() {
+            return;
+        }
+    }
+}
+
+fn write_frame<M: Codec>(stream: &mut TcpStream, message: &M) -> Result<(), TransportError> {
+    let mut buf = vec![0_u8; 4];
+    message.encode(&mut buf);
+    let len = buf.len() - 4;
+    if len > MAX_FRAME_LEN {
+        return Err(TransportError::InvalidMessage);
+    }
+    buf[..4].copy_from_slice(&(len as u32).to_be_bytes());
+    stream.write_all(&buf)?;
+    Ok(())
+}
+
+impl<M: Codec + Clone> Transport<M> for Tcp<M> {
+    fn index(&self) -> Scalar {
+        self.index
+    }
+
+    fn send(&mut self, to: &Scalar, message: &M) -> Result<(), TransportError> {
+        if to == &self.index {
+            return self
+                .own_sender
+                .send((self.index, message.clone()))
+                .map_err(|_| TransportError::Disconnected);
+        }
+        let (_, stream) = self
+            .streams
+            .iter_mut()
+            .find(|(index, _)| index == to)
+            .ok_or(TransportError::UnknownIndex)?;
+        write_frame(stream, message)
+    }
+
+    fn broadcast(&mut self, message: &M) -> Result<(), TransportError> {
+        for (_, stream) in self.streams.iter_mut() {
+            write_frame(stream, message)?;
+        }
+        self.own_sender
+            .send((self.index, message.clone()))
+            .map_err(|_| TransportError::Disconnected)
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Option<(Scalar, M)>, TransportError> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(received) => Ok(Some(received)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(TransportError::Disconnected),
+        }
+    }
+}
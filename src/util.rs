@@ -1,7 +1,157 @@
-use shamir::vss::VShare;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use shamir::vss::{SharingCommitment, VShare};
 
 pub fn all_indices_equal_in_vshare_batch(vshares: &[VShare]) -> bool {
     vshares
         .windows(2)
         .all(|w| w[0].share.index == w[1].share.index)
 }
+
+pub fn scalar_from_u64(x: u64) -> Scalar {
+    let mut bs = [0_u8; 32];
+    bs[24..].copy_from_slice(&x.to_be_bytes());
+    let mut scalar = Scalar::default();
+    scalar.set_b32(&bs);
+    scalar
+}
+
+pub fn scalar_pow_two(i: usize) -> Scalar {
+    assert!(i < 256);
+    let mut bs = [0_u8; 32];
+    bs[31 - i / 8] = 1 << (i % 8);
+    let mut scalar = Scalar::default();
+    scalar.set_b32(&bs);
+    scalar
+}
+
+pub fn scalar_to_bits_le(x: &Scalar) -> Vec<bool> {
+    let mut bs = [0_u8; 32];
+    x.put_b32(&mut bs);
+    let mut bits = Vec::with_capacity(256);
+    for byte in bs.iter().rev() {
+        for i in 0..8 {
+            bits.push((byte >> i) & 1 == 1);
+        }
+    }
+    bits
+}
+
+pub fn lagrange_coefficient(index: &Scalar, indices: &[Scalar], at: &Scalar) -> Scalar {
+    let mut numerator = scalar_from_u64(1);
+    let mut denominator = scalar_from_u64(1);
+    for other in indices.iter().filter(|other| *other != index) {
+        numerator = numerator * (at - other);
+        denominator = denominator * (index - other);
+    }
+    denominator.inverse_assign();
+    numerator * denominator
+}
+
+pub fn lagrange_coefficient_at_zero(index: &Scalar, indices: &[Scalar]) -> Scalar {
+    lagrange_coefficient(index, indices, &Scalar::zero())
+}
+
+pub fn negate_vshare(vshare: &mut VShare) {
+    vshare.share.value.negate_assign_mut();
+    vshare.decommitment.negate_assign_mut();
+}
+
+pub fn negate_commitment(commitment: &mut SharingCommitment) {
+    let mut neg_one = scalar_from_u64(1);
+    neg_one.negate_assign_mut();
+    commitment.scale_assign_mut(&neg_one);
+}
+
+pub fn add_constant_to_vshare(vshare: &mut VShare, constant: &Scalar) {
+    vshare.share.value = vshare.share.value + constant;
+}
+
+pub fn add_constant_to_commitment(commitment: &mut SharingCommitment, constant: &Scalar) {
+    let mut constant_commitment = SharingCommitment::with_capacity(commitment.len());
+    let mut g_constant = Gej::default();
+    g_constant.scalar_base_mul(constant);
+    constant_commitment.push(g_constant);
+    for _ in 1..commitment.len() {
+        constant_commitment.push(Gej::infinity());
+    }
+    commitment.add_assign_mut(&constant_commitment);
+}
+
+// The order of the scalar field is n = 2^6 * Q + 1 for odd Q.
+const SQRT_TWO_ADICITY: usize = 6;
+const SQRT_Q: [u8; 32] = [
+    0x03, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xfa, 0xea, 0xbb, 0x73, 0x9a, 0xbd, 0x22, 0x80, 0xee, 0xff, 0x49, 0x7a, 0x33, 0x40, 0xd9, 0x05,
+];
+const SQRT_Q_PLUS_ONE_HALF: [u8; 32] = [
+    0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xfd, 0x75, 0x5d, 0xb9, 0xcd, 0x5e, 0x91, 0x40, 0x77, 0x7f, 0xa4, 0xbd, 0x19, 0xa0, 0x6c, 0x83,
+];
+const SQRT_NON_RESIDUE: u64 = 5;
+
+pub fn scalar_pow(base: &Scalar, exp_be: &[u8]) -> Scalar {
+    let mut res = scalar_from_u64(1);
+    for byte in exp_be {
+        for i in (0..8).rev() {
+            res = &res * &res;
+            if (byte >> i) & 1 == 1 {
+                res = &res * base;
+            }
+        }
+    }
+    res
+}
+
+// Tonelli-Shanks. The root that is returned is deterministic, so all players that compute the
+// square root of the same value will obtain the same result.
+pub fn scalar_sqrt(x: &Scalar) -> Option<Scalar> {
+    if x.is_zero() {
+        return Some(Scalar::zero());
+    }
+    let one = scalar_from_u64(1);
+    let mut m = SQRT_TWO_ADICITY;
+    let mut c = scalar_pow(&scalar_from_u64(SQRT_NON_RESIDUE), &SQRT_Q);
+    let mut t = scalar_pow(x, &SQRT_Q);
+    let mut r = scalar_pow(x, &SQRT_Q_PLUS_ONE_HALF);
+    while t != one {
+        let mut i = 0;
+        let mut t_pow = t;
+        while t_pow != one {
+            t_pow = &t_pow * &t_pow;
+            i += 1;
+            if i == m {
+                return None;
+            }
+        }
+        let mut b = c;
+        for _ in 0..(m - i - 1) {
+            b = &b * &b;
+        }
+        m = i;
+        c = &b * &b;
+        t = &t * &c;
+        r = &r * &b;
+    }
+    Some(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqrt_of_square_is_a_root() {
+        for _ in 0..10 {
+            let x = Scalar::new_random_using_thread_rng();
+            let square = &x * &x;
+            let root = scalar_sqrt(&square).unwrap();
+            assert_eq!(&root * &root, square);
+        }
+    }
+
+    #[test]
+    fn sqrt_of_non_residue_is_none() {
+        assert_eq!(scalar_sqrt(&scalar_from_u64(SQRT_NON_RESIDUE)), None);
+    }
+}
@@ -0,0 +1,526 @@
+use crate::dleq;
+use crate::exponent;
+use crate::hash_to_curve::hash_to_curve;
+use crate::open::{self, OpenError};
+use crate::params::Parameters;
+use crate::protocol::{Blame, Outgoing, Protocol};
+use crate::util;
+use secp256k1::group::Gej;
+use secp256k1::scalar::Scalar;
+use sha2::{Digest, Sha256};
+use shamir::vss::{self, SharingCommitment, VShare};
+
+pub const DOMAIN: &[u8] = b"mpc-rs/vrf";
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum VRFError {
+    InvalidIndex,
+    DuplicateIndex,
+    InvalidProof,
+    InvalidResponse,
+}
+
+// The VRF output for an input x under the secret key s is derived from gamma = H(x)^s, where H
+// hashes onto the curve. The proof is a Chaum-Pedersen proof that log_g(y) = log_H(x)(gamma) for
+// the public key y = g^s, so it can be checked by anyone who knows the public key.
+//
+// The proof is computed jointly using a random sharing of a nonce r in two rounds:
+//
+// 1. Each player sends H(x)^s_i, g^r_i and H(x)^r_i, each with a proof that the exponent matches
+//    their share. Once k of these have been received, gamma = H(x)^s, g^r and H(x)^r are
+//    interpolated in the exponent and the challenge e is computed.
+// 2. The response z = r + es is opened with open::State.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proof {
+    pub gamma: Gej,
+    pub challenge: Scalar,
+    pub response: Scalar,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialEvaluation {
+    pub index: Scalar,
+    pub gamma: Gej,
+    pub gamma_proof: dleq::Proof,
+    pub nonce_g: Gej,
+    pub nonce_g_proof: dleq::Proof,
+    pub nonce_h: Gej,
+    pub nonce_h_proof: dleq::Proof,
+}
+
+fn exponent_share(base: &Gej, vshare: &VShare, h: &Gej) -> (Gej, dleq::Proof) {
+    let mut value = Gej::default();
+    value.scalar_mul(base, &vshare.share.value);
+    let proof = dleq::prove(base, &vshare.share.value, &vshare.decommitment, h);
+    (value, proof)
+}
+
+fn generator() -> Gej {
+    let mut g = Gej::default();
+    g.scalar_base_mul(&util::scalar_from_u64(1));
+    g
+}
+
+pub fn input_point(input: &[u8]) -> Gej {
+    hash_to_curve(DOMAIN, input)
+}
+
+pub fn partial_evaluation(
+    input: &[u8],
+    key_vshare: &VShare,
+    nonce_vshare: &VShare,
+    h: &Gej,
+) -> PartialEvaluation {
+    assert_eq!(key_vshare.share.index, nonce_vshare.share.index);
+    let base = input_point(input);
+    let (gamma, gamma_proof) = exponent_share(&base, key_vshare, h);
+    let (nonce_g, nonce_g_proof) = exponent_share(&generator(), nonce_vshare, h);
+    let (nonce_h, nonce_h_proof) = exponent_share(&base, nonce_vshare, h);
+    PartialEvaluation {
+        index: key_vshare.share.index,
+        gamma,
+        gamma_proof,
+        nonce_g,
+        nonce_g_proof,
+        nonce_h,
+        nonce_h_proof,
+    }
+}
+
+pub struct InstanceParams {
+    base: Gej,
+    pub_key: Gej,
+    key_commitment: SharingCommitment,
+    nonce_commitment: SharingCommitment,
+}
+
+impl InstanceParams {
+    pub fn new(
+        input: &[u8],
+        pub_key: Gej,
+        key_commitment: SharingCommitment,
+        nonce_commitment: SharingCommitment,
+    ) -> Self {
+        assert_eq!(key_commitment.len(), nonce_commitment.len());
+        InstanceParams {
+            base: input_point(input),
+            pub_key,
+            key_commitment,
+            nonce_commitment,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.key_commitment.len()
+    }
+
+    pub fn partial_evaluation_is_valid(&self, partial: &PartialEvaluation, h: &Gej) -> bool {
+        let key_eval = vss::poly_eval_gej_slice_in_exponent(&self.key_commitment, &partial.index);
+        let nonce_eval =
+            vss::poly_eval_gej_slice_in_exponent(&self.nonce_commitment, &partial.index);
+        dleq::verify(
+            &partial.gamma_proof,
+            &self.base,
+            &key_eval,
+            &partial.gamma,
+            h,
+        ) && dleq::verify(
+            &partial.nonce_g_proof,
+            &generator(),
+            &nonce_eval,
+            &partial.nonce_g,
+            h,
+        ) && dleq::verify(
+            &partial.nonce_h_proof,
+            &self.base,
+            &nonce_eval,
+            &partial.nonce_h,
+            h,
+        )
+    }
+
+    // The commitment for the response z = r + es, which is to be opened in the second round.
+    pub fn response_commitment(&self, challenge: &Scalar) -> SharingCommitment {
+        let mut commitment = self.key_commitment.clone();
+        commitment.scale_assign_mut(challenge);
+        commitment.add_assign_mut(&self.nonce_commitment);
+        commitment
+    }
+}
+
+pub fn response_vshare(key_vshare: &VShare, nonce_vshare: &VShare, challenge: &Scalar) -> VShare {
+    let mut vshare = *key_vshare;
+    vshare.scale_assign_mut(challenge);
+    vshare.add_assign_mut(nonce_vshare);
+    vshare
+}
+
+pub struct State {
+    indices: Vec<Scalar>,
+    gammas: Vec<Gej>,
+    nonce_gs: Vec<Gej>,
+    nonce_hs: Vec<Gej>,
+    challenge: Option<(Gej, Scalar)>,
+}
+
+impl State {
+    pub fn new(inst_params: &InstanceParams) -> Self {
+        let k = inst_params.threshold();
+        State {
+            indices: Vec::with_capacity(k),
+            gammas: Vec::with_capacity(k),
+            nonce_gs: Vec::with_capacity(k),
+            nonce_hs: Vec::with_capacity(k),
+            challenge: None,
+        }
+    }
+
+    pub fn partials_received(&self) -> usize {
+        self.indices.len()
+    }
+
+    // Returns the challenge once enough valid partial evaluations have been received.
+    pub fn handle_partial_evaluation(
+        &mut self,
+        inst_params: &InstanceParams,
+        params: &Parameters,
+        partial: PartialEvaluation,
+    ) -> Result<Option<Scalar>, VRFError> {
+        use VRFError::*;
+
+        if !params.indices.contains(&partial.index) {
+            return Err(InvalidIndex);
+        }
+        if self.indices.contains(&partial.index) {
+            return Err(DuplicateIndex);
+        }
+        if !inst_params.partial_evaluation_is_valid(&partial, &params.h) {
+            return Err(InvalidProof);
+        }
+        if self.challenge.is_some() {
+            return Ok(None);
+        }
+
+        self.indices.push(partial.index);
+        self.gammas.push(partial.gamma);
+        self.nonce_gs.push(partial.nonce_g);
+        self.nonce_hs.push(partial.nonce_h);
+        if self.partials_received() < inst_params.threshold() {
+            return Ok(None);
+        }
+
+        let precompute = exponent::Precompute::new_at_zero(&self.indices);
+        let gamma = precompute.interpolate(&self.gammas);
+        let nonce_g = precompute.interpolate(&self.nonce_gs);
+        let nonce_h = precompute.interpolate(&self.nonce_hs);
+        let challenge = compute_challenge(
+            &inst_params.base,
+            &inst_params.pub_key,
+            &gamma,
+            &nonce_g,
+            &nonce_h,
+        );
+        self.challenge = Some((gamma, challenge));
+        Ok(Some(challenge))
+    }
+
+    pub fn challenge(&self) -> Option<Scalar> {
+        self.challenge.map(|(_, challenge)| challenge)
+    }
+
+    pub fn proof(&self, response: Scalar) -> Option<Proof> {
+        let (gamma, challenge) = self.challenge?;
+        Some(Proof {
+            gamma,
+            challenge,
+            response,
+        })
+    }
+}
+
+fn compute_challenge(
+    base: &Gej,
+    pub_key: &Gej,
+    gamma: &Gej,
+    nonce_g: &Gej,
+    nonce_h: &Gej,
+) -> Scalar {
+    let mut challenge = Scalar::default();
+    let mut hasher = Sha256::new();
+    let mut bs = [0_u8; 165];
+    base.put_bytes(&mut bs);
+    pub_key.put_bytes(&mut bs[33..]);
+    gamma.put_bytes(&mut bs[66..]);
+    nonce_g.put_bytes(&mut bs[99..]);
+    nonce_h.put_bytes(&mut bs[132..]);
+    hasher.update(&bs);
+    let hash = hasher.finalize();
+    challenge.set_b32(hash.as_slice());
+    challenge
+}
+
+pub fn output(proof: &Proof) -> [u8; 32] {
+    let mut bs = [0_u8; 33];
+    proof.gamma.put_bytes(&mut bs);
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    hasher.update(&bs);
+    let mut out = [0_u8; 32];
+    out.copy_from_slice(hasher.finalize().as_slice());
+    out
+}
+
+// Checks the proof against the public key, returning the VRF output if it is valid. The nonce
+// commitments g^r = g^z y^-e and H(x)^r = H(x)^z gamma^-e are recomputed from the response and
+// must hash to the challenge.
+pub fn verify(pub_key: &Gej, input: &[u8], proof: &Proof) -> Option<[u8; 32]> {
+    let base = input_point(input);
+    let mut neg_challenge = proof.challenge;
+    neg_challenge.negate_assign_mut();
+
+    let mut nonce_g = Gej::default();
+    nonce_g.scalar_base_mul(&proof.response);
+    let mut tmp = Gej::default();
+    tmp.scalar_mul(pub_key, &neg_challenge);
+    nonce_g.add_assign(&tmp);
+
+    let mut nonce_h = Gej::default();
+    nonce_h.scalar_mul(&base, &proof.response);
+    tmp.scalar_mul(&proof.gamma, &neg_challenge);
+    nonce_h.add_assign(&tmp);
+
+    let challenge = compute_challenge(&base, pub_key, &proof.gamma, &nonce_g, &nonce_h);
+    if challenge == proof.challenge {
+        Some(output(proof))
+    } else {
+        None
+    }
+}
+
+impl From<OpenError> for VRFError {
+    fn from(e: OpenError) -> Self {
+        match e {
+            OpenError::InvalidIndex => VRFError::InvalidIndex,
+            OpenError::DuplicateIndex => VRFError::DuplicateIndex,
+            _ => VRFError::InvalidResponse,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Partial(PartialEvaluation),
+    Response(VShare),
+}
+
+// Runs both rounds of the VRF evaluation for one player. Responses that arrive before the player
+// has computed the challenge are kept until it has.
+pub struct Machine {
+    params: Parameters,
+    inst_params: InstanceParams,
+    state: State,
+    key_vshare: VShare,
+    nonce_vshare: VShare,
+    partial: PartialEvaluation,
+    response: Option<(open::InstanceParams, open::State)>,
+    pending: Vec<(Scalar, VShare)>,
+    output: Option<([u8; 32], Proof)>,
+}
+
+impl Machine {
+    pub fn new(
+        params: Parameters,
+        input: &[u8],
+        pub_key: Gej,
+        key_commitment: SharingCommitment,
+        nonce_commitment: SharingCommitment,
+        key_vshare: VShare,
+        nonce_vshare: VShare,
+    ) -> Self {
+        let partial = partial_evaluation(input, &key_vshare, &nonce_vshare, &params.h);
+        let inst_params = InstanceParams::new(input, pub_key, key_commitment, nonce_commitment);
+        let state = State::new(&inst_params);
+        Machine {
+            params,
+            inst_params,
+            state,
+            key_vshare,
+            nonce_vshare,
+            partial,
+            response: None,
+            pending: Vec::new(),
+            output: None,
+        }
+    }
+
+    fn handle_response(&mut self, from: &Scalar, vshare: VShare) -> Result<(), Blame<VRFError>> {
+        if &vshare.share.index != from {
+            return Err(Blame::new(*from, VRFError::InvalidIndex));
+        }
+        let (inst_params, state) = self.response.as_mut().expect("challenge should be known");
+        let res = state
+            .handle_vshare_batch(inst_params, &self.params, vec![vshare])
+            .map_err(|e| Blame::new(*from, e.into()))?;
+        if let Some(values) = res {
+            let proof = self.state.proof(values[0].0).unwrap();
+            self.output = Some((output(&proof), proof));
+        }
+        Ok(())
+    }
+}
+
+impl Protocol for Machine {
+    type Message = Message;
+    type Output = ([u8; 32], Proof);
+    type Error = VRFError;
+
+    fn index(&self) -> Scalar {
+        self.params.index
+    }
+
+    fn initial_messages(&mut self) -> Vec<Outgoing<Message>> {
+        vec![Outgoing::broadcast(Message::Partial(self.partial.clone()))]
+    }
+
+    fn handle_message(
+        &mut self,
+        from: &Scalar,
+        message: Message,
+        outgoing: &mut Vec<Outgoing<Message>>,
+    ) -> Result<(), Blame<VRFError>> {
+        match message {
+            Message::Partial(partial) => {
+                if &partial.index != from {
+                    return Err(Blame::new(*from, VRFError::InvalidIndex));
+                }
+                let challenge = match self
+                    .state
+                    .handle_partial_evaluation(&self.inst_params, &self.params, partial)
+                    .map_err(|e| Blame::new(*from, e))?
+                {
+                    Some(challenge) => challenge,
+                    None => return Ok(()),
+                };
+                let vshare = response_vshare(&self.key_vshare, &self.nonce_vshare, &challenge);
+                outgoing.push(Outgoing::broadcast(Message::Response(vshare)));
+                let inst_params = open::InstanceParams::new(vec![self
+                    .inst_params
+                    .response_commitment(&challenge)]);
+                let state = open::State::new(&inst_params);
+                self.response = Some((inst_params, state));
+
+                // Every pending response is handled, but only the first invalid one is reported.
+                let mut blame = None;
+                for (from, vshare) in std::mem::take(&mut self.pending) {
+                    if let Err(e) = self.handle_response(&from, vshare) {
+                        blame.get_or_insert(e);
+                    }
+                }
+                match blame {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                }
+            }
+            Message::Response(vshare) => {
+                if self.response.is_none() {
+                    self.pending.push((*from, vshare));
+                    Ok(())
+                } else {
+                    self.handle_response(from, vshare)
+                }
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn output(&self) -> Option<([u8; 32], Proof)> {
+        self.output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use secp256k1::scalar;
+
+    #[test]
+    fn threshold_proofs_verify_against_the_public_key() {
+        let n = 10;
+        let k = 3;
+        let input = b"round 42";
+
+        let h = Gej::new_random_using_thread_rng();
+        let indices = scalar::random_scalars_using_thread_rng(n);
+        let (key_shares_by_player, key_commitments, key_secrets, _) =
+            testutil::random_sharing_batch(n, k, 1, &indices, &h);
+        let (nonce_shares_by_player, nonce_commitments, _, _) =
+            testutil::random_sharing_batch(n, k, 1, &indices, &h);
+        let mut pub_key = Gej::default();
+        pub_key.scalar_base_mul(&key_secrets[0]);
+
+        let inst_params = InstanceParams::new(
+            input,
+            pub_key,
+            key_commitments[0].clone(),
+            nonce_commitments[0].clone(),
+        );
+        let params = Parameters::new(indices.clone(), indices[0], h).unwrap();
+        let mut state = State::new(&inst_params);
+
+        let mut bad_partial = partial_evaluation(
+            input,
+            &key_shares_by_player[0][0],
+            &nonce_shares_by_player[0][0],
+            &h,
+        );
+        bad_partial.gamma = Gej::new_random_using_thread_rng();
+        assert_eq!(
+            state.handle_partial_evaluation(&inst_params, &params, bad_partial),
+            Err(VRFError::InvalidProof)
+        );
+
+        let mut challenge = None;
+        for (key_batch, nonce_batch) in key_shares_by_player
+            .iter()
+            .zip(nonce_shares_by_player.iter())
+            .take(k)
+        {
+            let partial = partial_evaluation(input, &key_batch[0], &nonce_batch[0], &h);
+            challenge = state
+                .handle_partial_evaluation(&inst_params, &params, partial)
+                .unwrap();
+        }
+        let challenge = challenge.unwrap();
+
+        let mut response_shares_by_player = Vec::with_capacity(n);
+        for (key_batch, nonce_batch) in key_shares_by_player
+            .iter()
+            .zip(nonce_shares_by_player.iter())
+        {
+            response_shares_by_player.push(vec![response_vshare(
+                &key_batch[0],
+                &nonce_batch[0],
+                &challenge,
+            )]);
+        }
+        let opened = testutil::open_by_player(
+            &response_shares_by_player,
+            vec![inst_params.response_commitment(&challenge)],
+            &indices,
+            &h,
+        );
+        let proof = state.proof(opened[0].0).unwrap();
+
+        let mut expected_gamma = Gej::default();
+        expected_gamma.scalar_mul(&input_point(input), &key_secrets[0]);
+        assert_eq!(proof.gamma, expected_gamma);
+        assert_eq!(verify(&pub_key, input, &proof), Some(output(&proof)));
+        assert_eq!(verify(&pub_key, b"round 43", &proof), None);
+        let other_key = Gej::new_random_using_thread_rng();
+        assert_eq!(verify(&other_key, input, &proof), None);
+    }
+}